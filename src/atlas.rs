@@ -0,0 +1,522 @@
+//! Sprite atlases: bin-packing many images onto one or more texture pages.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use image::{RgbaImage, imageops};
+
+use crate::{Error, Layout, Result};
+
+/// Bin-packing algorithm used to place sprites on a page.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Packer {
+    /// Maximal rectangles with best short side fit: tracks every free
+    /// rectangle and picks the one the sprite fills most tightly.
+    #[default]
+    MaxRects,
+    /// Bottom-left skyline: tracks the top edge of placed sprites and puts
+    /// each sprite where it ends lowest. Faster, a little less dense.
+    Skyline,
+}
+
+impl fmt::Display for Packer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Packer::MaxRects => "maxrects",
+            Packer::Skyline => "skyline",
+        })
+    }
+}
+
+impl FromStr for Packer {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "maxrects" | "max-rects" => Ok(Packer::MaxRects),
+            "skyline" => Ok(Packer::Skyline),
+            _ => Err(Error::UnknownPacker(s.to_string())),
+        }
+    }
+}
+
+/// Which way [`AtlasOptions::rotate`] turns sprites. Engines disagree:
+/// TexturePacker JSON and Phaser expect clockwise, libGDX counter-clockwise,
+/// see [`SheetFormat::rotation`](crate::SheetFormat::rotation).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Rotation {
+    #[default]
+    Clockwise,
+    CounterClockwise,
+}
+
+/// Settings for building an atlas.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AtlasOptions {
+    pub packer: Packer,
+    /// Largest page width in pixels.
+    pub max_width: u32,
+    /// Largest page height in pixels.
+    pub max_height: u32,
+    /// Transparent pixels between neighbouring sprites.
+    pub padding: u32,
+    /// Pixels each sprite's edge is repeated outwards, so filtering at the
+    /// border does not bleed in neighbours.
+    pub extrude: u32,
+    /// Allow sprites to be turned 90 degrees to fit better.
+    pub rotate: bool,
+    /// Which way rotated sprites are turned.
+    pub rotation: Rotation,
+    /// Round page sizes up to powers of two.
+    pub power_of_two: bool,
+    /// Crop each sprite to the pixels that stay visible when packed in
+    /// this layout, see [`trim_bounds`]. Pass the layout the pages are
+    /// packed in so trimming drops exactly the pixels that pack as
+    /// transparent.
+    pub trim: Option<Layout>,
+}
+
+impl Default for AtlasOptions {
+    fn default() -> Self {
+        Self {
+            packer: Packer::default(),
+            max_width: 2048,
+            max_height: 2048,
+            padding: 2,
+            extrude: 0,
+            rotate: false,
+            rotation: Rotation::default(),
+            power_of_two: false,
+            trim: None,
+        }
+    }
+}
+
+/// An image to place on an atlas.
+#[derive(Debug, Clone)]
+pub struct Sprite {
+    /// Name the sprite is known by in metadata, usually its file name.
+    pub name: String,
+    pub image: RgbaImage,
+}
+
+/// Where a sprite ended up.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Placement {
+    pub name: String,
+    /// Index of the page holding the sprite.
+    pub page: usize,
+    /// Left edge of the sprite on its page, inside any extrusion.
+    pub x: u32,
+    /// Top edge of the sprite on its page, inside any extrusion.
+    pub y: u32,
+    /// Width of the sprite before rotation.
+    pub width: u32,
+    /// Height of the sprite before rotation.
+    pub height: u32,
+    /// Whether the sprite was turned 90 degrees in the atlas's
+    /// [`Rotation`], so it covers `height` by `width` pixels of the page.
+    pub rotated: bool,
+    /// Position of the sprite within its source image, non-zero when
+    /// transparent borders were trimmed off.
+    pub offset_x: u32,
+    pub offset_y: u32,
+    /// Size of the source image before trimming.
+    pub source_width: u32,
+    pub source_height: u32,
+}
+
+impl Placement {
+    /// Whether the sprite is smaller than its source image.
+    pub fn trimmed(&self) -> bool {
+        (self.width, self.height) != (self.source_width, self.source_height)
+    }
+}
+
+/// Pages of an atlas with the placement of every sprite.
+#[derive(Debug, Clone)]
+pub struct Atlas {
+    pub pages: Vec<RgbaImage>,
+    /// One placement per sprite, in the order the sprites were given.
+    pub placements: Vec<Placement>,
+}
+
+/// Packs `sprites` onto as few pages as fit them, largest sprites first.
+pub fn build_atlas(sprites: &[Sprite], options: &AtlasOptions) -> Result<Atlas> {
+    let (max_width, max_height) = match options.power_of_two {
+        true => (
+            floor_power_of_two(options.max_width),
+            floor_power_of_two(options.max_height),
+        ),
+        false => (options.max_width, options.max_height),
+    };
+    // Each sprite as placed, with its offset in the source image.
+    let trimmed: Vec<(RgbaImage, u32, u32)> = sprites
+        .iter()
+        .map(|sprite| match &options.trim {
+            Some(layout) => {
+                let (x, y, w, h) = trim_bounds(&sprite.image, layout);
+                (
+                    imageops::crop_imm(&sprite.image, x, y, w, h).to_image(),
+                    x,
+                    y,
+                )
+            }
+            None => (sprite.image.clone(), 0, 0),
+        })
+        .collect();
+    // Cells include the extrusion on both sides and the padding on the
+    // right and bottom; the bin is grown by the padding so the last row and
+    // column need none.
+    let margin = 2 * options.extrude + options.padding;
+    let (bin_width, bin_height) = (max_width + options.padding, max_height + options.padding);
+    let cells: Vec<(u32, u32)> = trimmed
+        .iter()
+        .map(|(image, _, _)| (image.width() + margin, image.height() + margin))
+        .collect();
+    for ((sprite, (image, _, _)), &(w, h)) in sprites.iter().zip(&trimmed).zip(&cells) {
+        let fits = |w, h| w <= bin_width && h <= bin_height;
+        if !(fits(w, h) || options.rotate && fits(h, w)) {
+            return Err(Error::SpriteTooLarge {
+                name: sprite.name.clone(),
+                width: image.width(),
+                height: image.height(),
+            });
+        }
+    }
+
+    let mut remaining: Vec<usize> = (0..sprites.len()).collect();
+    remaining.sort_by_key(|&i| {
+        let (w, h) = cells[i];
+        (std::cmp::Reverse(w.max(h)), std::cmp::Reverse(w.min(h)))
+    });
+    let mut placed = vec![None; sprites.len()];
+    let mut pages = Vec::new();
+    while !remaining.is_empty() {
+        let mut bin = Bin::new(options.packer, bin_width, bin_height);
+        let mut left = Vec::new();
+        let mut used = (0, 0);
+        for i in remaining {
+            let (w, h) = cells[i];
+            match bin.insert(w, h, options.rotate) {
+                Some((rect, rotated)) => {
+                    used.0 = used.0.max(rect.x + rect.w - options.padding);
+                    used.1 = used.1.max(rect.y + rect.h - options.padding);
+                    placed[i] = Some((pages.len(), rect, rotated));
+                }
+                None => left.push(i),
+            }
+        }
+        let size = match options.power_of_two {
+            true => (used.0.next_power_of_two(), used.1.next_power_of_two()),
+            false => used,
+        };
+        pages.push(RgbaImage::new(size.0, size.1));
+        remaining = left;
+    }
+
+    let mut placements = Vec::with_capacity(sprites.len());
+    for ((sprite, (image, offset_x, offset_y)), slot) in sprites.iter().zip(trimmed).zip(placed) {
+        let (page, rect, rotated) = slot.expect("every sprite fits an empty page");
+        let turned = match (rotated, options.rotation) {
+            (true, Rotation::Clockwise) => imageops::rotate90(&image),
+            (true, Rotation::CounterClockwise) => imageops::rotate270(&image),
+            (false, _) => image.clone(),
+        };
+        let cell = extrude(&turned, options.extrude);
+        imageops::replace(&mut pages[page], &cell, rect.x as i64, rect.y as i64);
+        placements.push(Placement {
+            name: sprite.name.clone(),
+            page,
+            x: rect.x + options.extrude,
+            y: rect.y + options.extrude,
+            width: image.width(),
+            height: image.height(),
+            rotated,
+            offset_x,
+            offset_y,
+            source_width: sprite.image.width(),
+            source_height: sprite.image.height(),
+        });
+    }
+    Ok(Atlas { pages, placements })
+}
+
+/// Tight bounds `(x, y, width, height)` of the pixels in `image` that keep
+/// some alpha when packed in `layout`: a 1-bit alpha follows its alpha
+/// rule, wider alpha is quantized and layouts without alpha pack every
+/// pixel opaque. A sprite with none keeps its top-left pixel, so it still
+/// has a place on the atlas.
+pub fn trim_bounds(image: &RgbaImage, layout: &Layout) -> (u32, u32, u32, u32) {
+    let mut bounds: Option<(u32, u32, u32, u32)> = None;
+    for (x, y, px) in image.enumerate_pixels() {
+        if layout.unpack(layout.pack(px.0))[3] != 0 {
+            bounds = Some(match bounds {
+                Some((x0, y0, x1, y1)) => (x0.min(x), y0.min(y), x1.max(x), y1.max(y)),
+                None => (x, y, x, y),
+            });
+        }
+    }
+    match bounds {
+        Some((x0, y0, x1, y1)) => (x0, y0, x1 - x0 + 1, y1 - y0 + 1),
+        None => (0, 0, image.width().min(1), image.height().min(1)),
+    }
+}
+
+/// File holding page `page` of an atlas of `pages` pages written to
+/// `path`: `path` itself for a single page, otherwise `name-N.ext` next
+/// to it.
+pub fn page_path(path: &Path, page: usize, pages: usize) -> PathBuf {
+    if pages == 1 {
+        return path.to_path_buf();
+    }
+    let mut name = path.file_stem().unwrap_or_default().to_os_string();
+    name.push(format!("-{page}"));
+    if let Some(extension) = path.extension() {
+        name.push(".");
+        name.push(extension);
+    }
+    path.with_file_name(name)
+}
+
+/// Surrounds `image` with `by` copies of its edge pixels.
+fn extrude(image: &RgbaImage, by: u32) -> RgbaImage {
+    if by == 0 || image.width() == 0 || image.height() == 0 {
+        return image.clone();
+    }
+    let (width, height) = image.dimensions();
+    RgbaImage::from_fn(width + 2 * by, height + 2 * by, |x, y| {
+        let sx = x.saturating_sub(by).min(width - 1);
+        let sy = y.saturating_sub(by).min(height - 1);
+        *image.get_pixel(sx, sy)
+    })
+}
+
+fn floor_power_of_two(v: u32) -> u32 {
+    match v {
+        0 => 0,
+        v => 1 << (31 - v.leading_zeros()),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Rect {
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+}
+
+impl Rect {
+    fn contains(&self, other: &Rect) -> bool {
+        other.x >= self.x
+            && other.y >= self.y
+            && other.x + other.w <= self.x + self.w
+            && other.y + other.h <= self.y + self.h
+    }
+
+    fn intersects(&self, other: &Rect) -> bool {
+        other.x < self.x + self.w
+            && self.x < other.x + other.w
+            && other.y < self.y + self.h
+            && self.y < other.y + other.h
+    }
+}
+
+/// Free space of one page.
+enum Bin {
+    MaxRects {
+        free: Vec<Rect>,
+    },
+    Skyline {
+        width: u32,
+        height: u32,
+        /// Segments of the skyline as `(x, y, width)`, left to right.
+        nodes: Vec<(u32, u32, u32)>,
+    },
+}
+
+impl Bin {
+    fn new(packer: Packer, width: u32, height: u32) -> Bin {
+        match packer {
+            Packer::MaxRects => Bin::MaxRects {
+                free: vec![Rect {
+                    x: 0,
+                    y: 0,
+                    w: width,
+                    h: height,
+                }],
+            },
+            Packer::Skyline => Bin::Skyline {
+                width,
+                height,
+                nodes: vec![(0, 0, width)],
+            },
+        }
+    }
+
+    /// Places a `w` by `h` cell, turned if allowed and better, returning
+    /// the occupied rectangle and whether it was turned.
+    fn insert(&mut self, w: u32, h: u32, rotate: bool) -> Option<(Rect, bool)> {
+        let orientations: &[(u32, u32, bool)] = match rotate {
+            true => &[(w, h, false), (h, w, true)],
+            false => &[(w, h, false)],
+        };
+        match self {
+            Bin::MaxRects { free } => {
+                // Best short side fit, ties broken by the long side.
+                let (rect, rotated) = orientations
+                    .iter()
+                    .flat_map(|&(w, h, rotated)| {
+                        free.iter()
+                            .filter(move |f| f.w >= w && f.h >= h)
+                            .map(move |f| {
+                                let (dw, dh) = (f.w - w, f.h - h);
+                                let rect = Rect {
+                                    x: f.x,
+                                    y: f.y,
+                                    w,
+                                    h,
+                                };
+                                ((dw.min(dh), dw.max(dh)), rect, rotated)
+                            })
+                    })
+                    .min_by_key(|&(score, rect, _)| (score, rect.y, rect.x))
+                    .map(|(_, rect, rotated)| (rect, rotated))?;
+                split_free(free, &rect);
+                Some((rect, rotated))
+            }
+            Bin::Skyline {
+                width,
+                height,
+                nodes,
+            } => {
+                // Lowest top edge, ties broken by the narrowest segment.
+                let (width, height) = (*width, *height);
+                let (i, rect, rotated) = orientations
+                    .iter()
+                    .flat_map(|&(w, h, rotated)| {
+                        let nodes = &*nodes;
+                        (0..nodes.len()).filter_map(move |i| {
+                            let y = skyline_fit(nodes, i, w, h, width, height)?;
+                            let rect = Rect {
+                                x: nodes[i].0,
+                                y,
+                                w,
+                                h,
+                            };
+                            Some((i, rect, rotated))
+                        })
+                    })
+                    .min_by_key(|&(i, rect, _)| (rect.y + rect.h, nodes[i].2, rect.x))?;
+                skyline_add(nodes, i, &rect);
+                Some((rect, rotated))
+            }
+        }
+    }
+}
+
+/// Replaces every free rectangle overlapping `used` by the parts of it
+/// left over, then drops rectangles contained in others.
+fn split_free(free: &mut Vec<Rect>, used: &Rect) {
+    let mut next = Vec::with_capacity(free.len() + 4);
+    for f in free.drain(..) {
+        if !f.intersects(used) {
+            next.push(f);
+            continue;
+        }
+        if used.x > f.x {
+            next.push(Rect {
+                w: used.x - f.x,
+                ..f
+            });
+        }
+        if used.x + used.w < f.x + f.w {
+            next.push(Rect {
+                x: used.x + used.w,
+                w: f.x + f.w - (used.x + used.w),
+                ..f
+            });
+        }
+        if used.y > f.y {
+            next.push(Rect {
+                h: used.y - f.y,
+                ..f
+            });
+        }
+        if used.y + used.h < f.y + f.h {
+            next.push(Rect {
+                y: used.y + used.h,
+                h: f.y + f.h - (used.y + used.h),
+                ..f
+            });
+        }
+    }
+    let mut i = 0;
+    while i < next.len() {
+        let contained = (0..next.len())
+            .any(|j| j != i && next[j].contains(&next[i]) && (next[j] != next[i] || j < i));
+        if contained {
+            next.swap_remove(i);
+        } else {
+            i += 1;
+        }
+    }
+    *free = next;
+}
+
+/// Top edge a `w` by `h` cell would have with its left edge at skyline
+/// segment `i`, if it fits.
+fn skyline_fit(
+    nodes: &[(u32, u32, u32)],
+    i: usize,
+    w: u32,
+    h: u32,
+    width: u32,
+    height: u32,
+) -> Option<u32> {
+    let x = nodes[i].0;
+    if x + w > width {
+        return None;
+    }
+    let mut y = 0;
+    let mut covered = 0;
+    for &(_, node_y, node_w) in &nodes[i..] {
+        if covered >= w {
+            break;
+        }
+        y = y.max(node_y);
+        covered += node_w;
+    }
+    (y + h <= height).then_some(y)
+}
+
+/// Raises the skyline over a cell placed at segment `i`.
+fn skyline_add(nodes: &mut Vec<(u32, u32, u32)>, i: usize, rect: &Rect) {
+    nodes.insert(i, (rect.x, rect.y + rect.h, rect.w));
+    let right = rect.x + rect.w;
+    let j = i + 1;
+    while j < nodes.len() {
+        let (x, y, w) = nodes[j];
+        if x >= right {
+            break;
+        }
+        if x + w <= right {
+            nodes.remove(j);
+        } else {
+            nodes[j] = (right, y, x + w - right);
+            break;
+        }
+    }
+    // Merge neighbours at the same height.
+    let mut k = 0;
+    while k + 1 < nodes.len() {
+        if nodes[k].1 == nodes[k + 1].1 {
+            nodes[k].2 += nodes[k + 1].2;
+            nodes.remove(k + 1);
+        } else {
+            k += 1;
+        }
+    }
+}
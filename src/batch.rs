@@ -0,0 +1,191 @@
+//! Converting many files at once: expanding directories and glob patterns,
+//! naming outputs from a template and running conversions on a thread pool.
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use rayon::prelude::*;
+
+use crate::{Error, Result};
+
+/// Settings for converting many files at once.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BatchOptions {
+    /// Output path relative to the output directory. `{dir}` is the input's
+    /// directory below the searched directory or pattern prefix, `{stem}` its
+    /// file name without extension, `{ext}` its extension and `{name}` its
+    /// full file name.
+    pub template: String,
+    /// Number of worker threads; 0 uses one per CPU.
+    pub jobs: usize,
+}
+
+impl Default for BatchOptions {
+    fn default() -> Self {
+        Self {
+            template: "{dir}/{stem}.png".to_string(),
+            jobs: 0,
+        }
+    }
+}
+
+/// An input file found by [`expand_inputs`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BatchInput {
+    pub path: PathBuf,
+    /// Directory of `path` below the directory or pattern prefix it was found
+    /// under; empty for files named directly.
+    pub dir: PathBuf,
+}
+
+/// Outcome of a batch run.
+#[derive(Debug, Default)]
+pub struct BatchReport {
+    /// Number of files converted successfully.
+    pub converted: usize,
+    /// Inputs that failed, with their errors, in input order.
+    pub failures: Vec<(PathBuf, Error)>,
+}
+
+/// Whether `input` is a glob pattern rather than a literal path.
+pub fn is_pattern(input: &Path) -> bool {
+    !input.exists() && input.to_string_lossy().contains(['*', '?', '['])
+}
+
+/// Expands files, directories and glob patterns into a list of input files.
+///
+/// Directories are searched recursively and only files for which `accept`
+/// returns true are kept; files named directly or matched by a pattern are
+/// always kept. A directory or pattern that yields nothing is an error.
+pub fn expand_inputs(
+    inputs: &[PathBuf],
+    accept: impl Fn(&Path) -> bool,
+) -> Result<Vec<BatchInput>> {
+    let mut found = Vec::new();
+    for input in inputs {
+        let before = found.len();
+        if input.is_dir() {
+            walk(input, input, &accept, &mut found)?;
+        } else if is_pattern(input) {
+            let pattern = input.to_string_lossy();
+            let prefix = pattern_prefix(input);
+            for path in glob::glob(&pattern)? {
+                let path = path.map_err(std::io::Error::from)?;
+                if path.is_file() {
+                    found.push(BatchInput {
+                        dir: relative_dir(&path, &prefix),
+                        path,
+                    });
+                }
+            }
+        } else {
+            found.push(BatchInput {
+                path: input.clone(),
+                dir: PathBuf::new(),
+            });
+        }
+        if found.len() == before {
+            return Err(Error::NoInputs(input.clone()));
+        }
+    }
+    Ok(found)
+}
+
+/// Output path of `input` under `out_dir`, following `template`.
+pub fn output_path(out_dir: &Path, template: &str, input: &BatchInput) -> PathBuf {
+    let part = |s: Option<&std::ffi::OsStr>| s.unwrap_or_default().to_string_lossy().into_owned();
+    let dir = match input.dir.as_os_str() {
+        dir if dir.is_empty() => ".".to_string(),
+        dir => dir.to_string_lossy().into_owned(),
+    };
+    let name = template
+        .replace("{dir}", &dir)
+        .replace("{stem}", &part(input.path.file_stem()))
+        .replace("{ext}", &part(input.path.extension()))
+        .replace("{name}", &part(input.path.file_name()));
+    out_dir.join(name)
+}
+
+/// Runs `convert(input, output)` for every input on a thread pool, creating
+/// output directories as needed. Failures are collected rather than
+/// stopping the run; two inputs that would share an output are refused
+/// before anything is written.
+pub fn run_batch(
+    inputs: &[BatchInput],
+    out_dir: &Path,
+    options: &BatchOptions,
+    convert: impl Fn(&Path, &Path) -> Result<()> + Sync,
+) -> Result<BatchReport> {
+    let outputs: Vec<PathBuf> = inputs
+        .iter()
+        .map(|input| output_path(out_dir, &options.template, input))
+        .collect();
+    let mut seen = HashSet::new();
+    if let Some(output) = outputs.iter().find(|&output| !seen.insert(output)) {
+        return Err(Error::DuplicateOutput(output.clone()));
+    }
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(options.jobs)
+        .build()?;
+    let results: Vec<Result<()>> = pool.install(|| {
+        inputs
+            .par_iter()
+            .zip(&outputs)
+            .map(|(input, output)| {
+                if let Some(parent) = output.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                convert(&input.path, output)
+            })
+            .collect()
+    });
+
+    let mut report = BatchReport::default();
+    for (input, result) in inputs.iter().zip(results) {
+        match result {
+            Ok(()) => report.converted += 1,
+            Err(err) => report.failures.push((input.path.clone(), err)),
+        }
+    }
+    Ok(report)
+}
+
+fn walk(
+    root: &Path,
+    dir: &Path,
+    accept: &impl Fn(&Path) -> bool,
+    found: &mut Vec<BatchInput>,
+) -> Result<()> {
+    let mut entries = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    entries.sort();
+    for path in entries {
+        if path.is_dir() {
+            walk(root, &path, accept, found)?;
+        } else if accept(&path) {
+            found.push(BatchInput {
+                dir: relative_dir(&path, root),
+                path,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// The leading components of a pattern that contain no wildcards.
+fn pattern_prefix(pattern: &Path) -> PathBuf {
+    pattern
+        .components()
+        .take_while(|c| !c.as_os_str().to_string_lossy().contains(['*', '?', '[']))
+        .collect()
+}
+
+fn relative_dir(path: &Path, root: &Path) -> PathBuf {
+    path.parent()
+        .and_then(|parent| parent.strip_prefix(root).ok())
+        .map(Path::to_path_buf)
+        .unwrap_or_default()
+}
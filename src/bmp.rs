@@ -0,0 +1,118 @@
+//! Windows bitmaps with 16 or 32-bit pixels described by `BI_BITFIELDS` masks.
+
+use crate::{Endian, Error, Layout, RawFormat, Result};
+
+const FILE_HEADER_SIZE: u32 = 14;
+const V4_HEADER_SIZE: u32 = 108;
+
+const BI_RGB: u32 = 0;
+const BI_BITFIELDS: u32 = 3;
+const BI_ALPHABITFIELDS: u32 = 6;
+
+const LCS_SRGB: u32 = 0x7352_4742;
+
+/// Writes a bottom-up bitmap with a `BITMAPV4HEADER` whose `BI_BITFIELDS`
+/// masks, including the alpha mask, describe `layout`.
+pub fn encode(layout: &Layout, width: u32, height: u32, packed: &[u32]) -> Result<Vec<u8>> {
+    if !matches!(layout.bits, 16 | 32) || layout.l.is_some() {
+        return Err(Error::InvalidFile {
+            format: "BMP",
+            reason: format!("bitfield bitmaps cannot store {layout}"),
+        });
+    }
+    let raw = RawFormat {
+        align: 4,
+        ..RawFormat::default()
+    };
+    let data_offset = FILE_HEADER_SIZE + V4_HEADER_SIZE;
+    let image_size = raw.row_pitch(layout, width) * height as usize;
+
+    let mut out = b"BM".to_vec();
+    let mut put = |v: u32| out.extend_from_slice(&v.to_le_bytes());
+    put(data_offset + image_size as u32);
+    put(0); // reserved
+    put(data_offset);
+    put(V4_HEADER_SIZE);
+    put(width);
+    put(height);
+    put(1 | (layout.bits << 16)); // planes and bit count
+    put(BI_BITFIELDS);
+    put(image_size as u32);
+    put(2835); // 72 DPI horizontally
+    put(2835); // and vertically
+    put(0); // palette colours
+    put(0); // important colours
+    layout.masks().into_iter().for_each(&mut put);
+    put(LCS_SRGB);
+    (0..12).for_each(|_| put(0)); // endpoints and gamma
+
+    let rows: Vec<u32> = packed
+        .chunks(width.max(1) as usize)
+        .rev()
+        .flatten()
+        .copied()
+        .collect();
+    out.extend(raw.encode(layout, width, &rows)?);
+    Ok(out)
+}
+
+/// Reads a 16 or 32-bit bitmap stored as `BI_RGB`, `BI_BITFIELDS` or
+/// `BI_ALPHABITFIELDS`, returning its layout, width, height and top-down
+/// row-major values.
+pub fn decode(bytes: &[u8]) -> Result<(Layout, u32, u32, Vec<u32>)> {
+    let invalid = |reason: &str| Error::InvalidFile {
+        format: "BMP",
+        reason: reason.to_string(),
+    };
+    if bytes.len() < 54 || &bytes[..2] != b"BM" {
+        return Err(invalid("missing BMP header"));
+    }
+    let word = |i: usize| {
+        bytes
+            .get(i..i + 4)
+            .map_or(0, |b| u32::from_le_bytes(b.try_into().unwrap()))
+    };
+    let (data_offset, header_size) = (word(10) as usize, word(14));
+    let (width, height) = (word(18) as i32, word(22) as i32);
+    let (bit_count, compression) = (word(26) >> 16, word(30));
+    if width < 0 || !matches!(bit_count, 16 | 32) {
+        return Err(invalid("only 16 and 32-bit bitmaps hold packed pixels"));
+    }
+
+    // Masks follow a 40-byte header or sit at the same offset inside larger
+    // ones; only BI_ALPHABITFIELDS and headers of 56 bytes or more carry alpha.
+    let mask_count = if compression == BI_ALPHABITFIELDS || header_size >= 56 {
+        4
+    } else {
+        3
+    };
+    let masks = match compression {
+        BI_RGB if bit_count == 16 => [0x7C00, 0x03E0, 0x001F, 0],
+        BI_RGB => [0xFF_0000, 0xFF00, 0xFF, 0],
+        BI_BITFIELDS | BI_ALPHABITFIELDS => {
+            let mut masks = [0; 4];
+            (0..mask_count).for_each(|i| masks[i] = word(54 + 4 * i));
+            masks
+        }
+        _ => return Err(invalid("compressed bitmaps are not supported")),
+    };
+    let layout = Layout::from_masks(bit_count, masks, false)?;
+
+    let (width, rows) = (width as u32, height.unsigned_abs());
+    let raw = RawFormat {
+        endian: Endian::Little,
+        offset: data_offset,
+        stride: None,
+        align: 4,
+    };
+    let mut packed = raw.decode(&layout, width, rows, bytes)?;
+    if height > 0 {
+        packed = packed
+            .chunks(width.max(1) as usize)
+            .rev()
+            .flatten()
+            .copied()
+            .collect();
+    }
+    Ok((layout, width, rows, packed))
+}
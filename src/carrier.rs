@@ -0,0 +1,114 @@
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::{Error, Language};
+
+/// How packed pixels are stored in a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Carrier {
+    /// An image file in any other format the `image` crate can write, chosen
+    /// by extension: greyscale for 8 and 16-bit layouts, RGBA8 for 32-bit ones.
+    #[default]
+    Image,
+    /// Headerless bytes, see [`RawFormat`](crate::RawFormat).
+    Raw,
+    /// A source array, see [`SourceOptions`](crate::SourceOptions). Write only.
+    Source(Language),
+    /// A DirectDraw Surface with the layout described by channel masks.
+    Dds,
+    /// A KTX 1 file for OpenGL; only layouts with a packed GL type.
+    Ktx,
+    /// A KTX 2 file for Vulkan; only layouts with a packed `VkFormat`.
+    Ktx2,
+    /// A Truevision TGA file with the alpha channel as attribute bits.
+    Tga,
+    /// A Windows bitmap with `BI_BITFIELDS` channel masks.
+    Bmp,
+}
+
+impl Carrier {
+    /// Picks the carrier implied by a file extension, falling back to [`Carrier::Image`].
+    pub fn from_path(path: &Path) -> Carrier {
+        let ext = path.extension().and_then(|ext| ext.to_str());
+        Carrier::from_extension(ext.unwrap_or_default())
+    }
+
+    /// Picks the carrier implied by an extension without the leading dot,
+    /// falling back to [`Carrier::Image`].
+    pub fn from_extension(ext: &str) -> Carrier {
+        match ext.to_ascii_lowercase().as_str() {
+            "raw" | "bin" => Carrier::Raw,
+            "dds" => Carrier::Dds,
+            "ktx" => Carrier::Ktx,
+            "ktx2" => Carrier::Ktx2,
+            "tga" => Carrier::Tga,
+            "bmp" => Carrier::Bmp,
+            "h" | "c" => Carrier::Source(Language::C),
+            "hpp" | "hh" | "hxx" | "cpp" | "cc" | "cxx" => Carrier::Source(Language::Cpp),
+            "rs" => Carrier::Source(Language::Rust),
+            "s" | "asm" => Carrier::Source(Language::Asm),
+            _ => Carrier::Image,
+        }
+    }
+
+    /// Recognises a carrier from the start or end of its data, falling back
+    /// to [`Carrier::Image`]. Raw data has no signature and is never detected.
+    pub fn detect(bytes: &[u8]) -> Carrier {
+        match bytes {
+            [b'D', b'D', b'S', b' ', ..] => Carrier::Dds,
+            [0xAB, b'K', b'T', b'X', b' ', b'1', b'1', 0xBB, ..] => Carrier::Ktx,
+            [0xAB, b'K', b'T', b'X', b' ', b'2', b'0', 0xBB, ..] => Carrier::Ktx2,
+            [b'B', b'M', ..] => Carrier::Bmp,
+            _ if bytes.ends_with(b"TRUEVISION-XFILE.\0") => Carrier::Tga,
+            _ => Carrier::Image,
+        }
+    }
+
+    /// Whether files of this carrier record their own layout.
+    pub fn describes_layout(self) -> bool {
+        !matches!(self, Carrier::Image | Carrier::Raw | Carrier::Source(_))
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Carrier::Image => "image",
+            Carrier::Raw => "raw",
+            Carrier::Source(language) => language.name(),
+            Carrier::Dds => "dds",
+            Carrier::Ktx => "ktx",
+            Carrier::Ktx2 => "ktx2",
+            Carrier::Tga => "tga",
+            Carrier::Bmp => "bmp",
+        }
+    }
+}
+
+impl fmt::Display for Carrier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Carrier {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        [
+            Carrier::Image,
+            Carrier::Raw,
+            Carrier::Source(Language::C),
+            Carrier::Source(Language::Cpp),
+            Carrier::Source(Language::Rust),
+            Carrier::Source(Language::Asm),
+            Carrier::Dds,
+            Carrier::Ktx,
+            Carrier::Ktx2,
+            Carrier::Tga,
+            Carrier::Bmp,
+        ]
+        .into_iter()
+        .find(|c| c.name().eq_ignore_ascii_case(s))
+        .ok_or_else(|| Error::UnknownCarrier(s.to_string()))
+    }
+}
@@ -0,0 +1,122 @@
+//! DirectDraw Surface files with uncompressed, mask-described pixels.
+
+use crate::{Endian, Error, Layout, PixelFormat, RawFormat, Result};
+
+const MAGIC: &[u8; 4] = b"DDS ";
+const HEADER_SIZE: u32 = 124;
+const PIXEL_FORMAT_SIZE: u32 = 32;
+
+const DDSD_CAPS: u32 = 0x1;
+const DDSD_HEIGHT: u32 = 0x2;
+const DDSD_WIDTH: u32 = 0x4;
+const DDSD_PITCH: u32 = 0x8;
+const DDSD_PIXELFORMAT: u32 = 0x1000;
+
+const DDPF_ALPHAPIXELS: u32 = 0x1;
+const DDPF_ALPHA: u32 = 0x2;
+const DDPF_FOURCC: u32 = 0x4;
+const DDPF_RGB: u32 = 0x40;
+const DDPF_LUMINANCE: u32 = 0x20000;
+
+const DDSCAPS_TEXTURE: u32 = 0x1000;
+
+const DXGI_FORMAT_B5G6R5_UNORM: u32 = 85;
+const DXGI_FORMAT_B5G5R5A1_UNORM: u32 = 86;
+const DXGI_FORMAT_B4G4R4A4_UNORM: u32 = 115;
+
+/// Writes a DDS file whose legacy pixel format describes `layout` by its
+/// channel masks, so A1R5G5B5, R5G6B5 and A4R4G4B4 open as the matching
+/// DXGI formats in standard viewers.
+pub fn encode(layout: &Layout, width: u32, height: u32, packed: &[u32]) -> Result<Vec<u8>> {
+    let raw = RawFormat::default();
+    let pitch = raw.row_pitch(layout, width);
+    let [r, g, b, a] = layout.masks();
+    let mut flags = if layout.l.is_some() {
+        DDPF_LUMINANCE
+    } else if r | g | b != 0 {
+        DDPF_RGB
+    } else {
+        DDPF_ALPHA
+    };
+    if a != 0 && flags != DDPF_ALPHA {
+        flags |= DDPF_ALPHAPIXELS;
+    }
+
+    let mut out = MAGIC.to_vec();
+    let mut put = |v: u32| out.extend_from_slice(&v.to_le_bytes());
+    put(HEADER_SIZE);
+    put(DDSD_CAPS | DDSD_HEIGHT | DDSD_WIDTH | DDSD_PITCH | DDSD_PIXELFORMAT);
+    put(height);
+    put(width);
+    put(pitch as u32);
+    put(0); // depth
+    put(0); // mipmap count
+    (0..11).for_each(|_| put(0));
+    put(PIXEL_FORMAT_SIZE);
+    put(flags);
+    put(0); // FourCC
+    put(layout.bits);
+    [r, g, b, a].into_iter().for_each(&mut put);
+    put(DDSCAPS_TEXTURE);
+    (0..4).for_each(|_| put(0));
+    out.extend(raw.encode(layout, width, packed)?);
+    Ok(out)
+}
+
+/// Reads the top-level surface of a DDS file with a legacy mask-described
+/// pixel format or one of the 16-bit DXGI formats, returning its layout,
+/// width, height and row-major values.
+pub fn decode(bytes: &[u8]) -> Result<(Layout, u32, u32, Vec<u32>)> {
+    let invalid = |reason: &str| Error::InvalidFile {
+        format: "DDS",
+        reason: reason.to_string(),
+    };
+    if bytes.len() < 128 || &bytes[..4] != MAGIC {
+        return Err(invalid("missing DDS header"));
+    }
+    let word = |i: usize| u32::from_le_bytes(bytes[i..i + 4].try_into().unwrap());
+    let (flags, height, width, pitch) = (word(8), word(12), word(16), word(20) as usize);
+    let (pf_flags, four_cc, bit_count) = (word(80), &bytes[84..88], word(88));
+    let masks = [word(92), word(96), word(100), word(104)];
+
+    let mut offset = 128;
+    let layout = if pf_flags & DDPF_FOURCC != 0 {
+        if four_cc != b"DX10" {
+            return Err(invalid("compressed surfaces are not supported"));
+        }
+        if bytes.len() < 148 {
+            return Err(invalid("truncated DX10 header"));
+        }
+        offset = 148;
+        match word(128) {
+            DXGI_FORMAT_B5G6R5_UNORM => PixelFormat::Rgb565.layout(),
+            DXGI_FORMAT_B5G5R5A1_UNORM => PixelFormat::Argb1555.layout(),
+            DXGI_FORMAT_B4G4R4A4_UNORM => PixelFormat::Argb4444.layout(),
+            _ => return Err(invalid("unsupported DXGI format")),
+        }
+    } else {
+        if ![8, 16, 32].contains(&bit_count) {
+            return Err(invalid("unsupported bit count"));
+        }
+        let masks = if pf_flags & (DDPF_RGB | DDPF_LUMINANCE) == 0 {
+            [0, 0, 0, masks[3]]
+        } else if pf_flags & DDPF_ALPHAPIXELS == 0 {
+            [masks[0], masks[1], masks[2], 0]
+        } else {
+            masks
+        };
+        Layout::from_masks(bit_count, masks, pf_flags & DDPF_LUMINANCE != 0)?
+    };
+
+    // The pitch comes straight from the header; `RawFormat::decode` checks
+    // the sizes derived from it against the input.
+    let stride = (flags & DDSD_PITCH != 0 && pitch != 0).then_some(pitch);
+    let raw = RawFormat {
+        endian: Endian::Little,
+        offset,
+        stride,
+        ..RawFormat::default()
+    };
+    let packed = raw.decode(&layout, width, height, bytes)?;
+    Ok((layout, width, height, packed))
+}
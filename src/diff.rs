@@ -0,0 +1,95 @@
+//! Images that show where an image and its round trip differ.
+
+use std::cmp::Ordering;
+
+use image::{Rgb, RgbImage, Rgba, RgbaImage, imageops};
+
+use crate::{Error, Result};
+
+/// Colours of the heatmap from no error to the largest error.
+const HEAT: [[u8; 3]; 5] = [
+    [0, 0, 0],
+    [40, 20, 140],
+    [190, 40, 110],
+    [250, 140, 20],
+    [255, 255, 160],
+];
+
+/// Colours per-pixel error, the largest difference of the red, green and blue
+/// channels, from black through purple and orange to pale yellow. Errors of
+/// `max` or more get the hottest colour; `None` scales to the largest error
+/// in the image so faint banding still shows.
+pub fn heatmap(expected: &RgbaImage, actual: &RgbaImage, max: Option<u8>) -> Result<RgbImage> {
+    check_dimensions(expected, actual)?;
+    let errors: Vec<u8> = expected
+        .pixels()
+        .zip(actual.pixels())
+        .map(|(x, y)| colour_error(x, y))
+        .collect();
+    let max = max
+        .unwrap_or_else(|| errors.iter().copied().max().unwrap_or(0))
+        .max(1);
+
+    let pixels = errors
+        .iter()
+        .flat_map(|&e| heat(e.min(max) as f32 / max as f32))
+        .collect();
+    let heat = RgbImage::from_vec(expected.width(), expected.height(), pixels);
+    Ok(heat.expect("buffer sized from image dimensions"))
+}
+
+/// Marks pixels whose alpha changed: red where the round trip became more
+/// transparent, green where it became more opaque, black where alpha held.
+pub fn alpha_mismatch(expected: &RgbaImage, actual: &RgbaImage) -> Result<RgbImage> {
+    check_dimensions(expected, actual)?;
+    let mut mask = RgbImage::new(expected.width(), expected.height());
+    for ((x, y), px) in expected
+        .pixels()
+        .zip(actual.pixels())
+        .zip(mask.pixels_mut())
+    {
+        *px = match x[3].cmp(&y[3]) {
+            Ordering::Greater => Rgb([255, 0, 0]),
+            Ordering::Less => Rgb([0, 255, 0]),
+            Ordering::Equal => Rgb([0, 0, 0]),
+        };
+    }
+    Ok(mask)
+}
+
+/// Places images left to right, separated by `gap` transparent pixels and
+/// aligned to the top.
+pub fn side_by_side(images: &[&RgbaImage], gap: u32) -> RgbaImage {
+    let width = images.iter().map(|img| img.width()).sum::<u32>()
+        + gap * images.len().saturating_sub(1) as u32;
+    let height = images.iter().map(|img| img.height()).max().unwrap_or(0);
+    let mut strip = RgbaImage::new(width, height);
+    let mut x = 0;
+    for img in images {
+        imageops::replace(&mut strip, *img, x as i64, 0);
+        x += img.width() + gap;
+    }
+    strip
+}
+
+fn colour_error(x: &Rgba<u8>, y: &Rgba<u8>) -> u8 {
+    (0..3).map(|c| x[c].abs_diff(y[c])).max().unwrap_or(0)
+}
+
+fn heat(t: f32) -> [u8; 3] {
+    let pos = t * (HEAT.len() - 1) as f32;
+    let i = (pos as usize).min(HEAT.len() - 2);
+    let f = pos - i as f32;
+    let (a, b) = (HEAT[i], HEAT[i + 1]);
+    [0, 1, 2].map(|c| (a[c] as f32 + (b[c] as f32 - a[c] as f32) * f).round() as u8)
+}
+
+pub(crate) fn check_dimensions(expected: &RgbaImage, actual: &RgbaImage) -> Result<()> {
+    if expected.dimensions() != actual.dimensions() {
+        return Err(Error::DimensionMismatch {
+            expected: expected.dimensions(),
+            actual: actual.dimensions(),
+        });
+    }
+    Ok(())
+}
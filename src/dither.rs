@@ -0,0 +1,408 @@
+use std::fmt;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+use image::RgbaImage;
+
+use crate::{Channel, Error, Layout, PackOptions};
+
+/// Side length of the built-in blue-noise threshold map.
+const BLUE_NOISE_SIZE: usize = 32;
+
+/// How a channel is dithered before quantization.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Dither {
+    /// Round every pixel to the nearest level.
+    #[default]
+    None,
+    /// Offset each pixel by a tiled threshold map.
+    Ordered(ThresholdMap),
+    /// Push each pixel's quantization error onto its unprocessed neighbours.
+    Diffusion(Kernel),
+}
+
+/// A tiled threshold map for ordered dithering.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThresholdMap {
+    /// Bayer matrix of the given side length: 2, 4, 8 or 16.
+    Bayer(u32),
+    /// The built-in 32x32 blue-noise map.
+    BlueNoise,
+}
+
+impl ThresholdMap {
+    /// Threshold in `[0, 1)` for pixel `(x, y)`.
+    pub fn threshold(self, x: u32, y: u32) -> f32 {
+        match self {
+            ThresholdMap::Bayer(n) => {
+                let rank = bayer_rank(x % n, y % n, n);
+                (rank as f32 + 0.5) / (n * n) as f32
+            }
+            ThresholdMap::BlueNoise => {
+                let map = blue_noise();
+                let i =
+                    (y as usize % BLUE_NOISE_SIZE) * BLUE_NOISE_SIZE + x as usize % BLUE_NOISE_SIZE;
+                (map[i] as f32 + 0.5) / map.len() as f32
+            }
+        }
+    }
+}
+
+/// An error-diffusion kernel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kernel {
+    FloydSteinberg,
+    /// Spreads only 3/4 of the error, keeping contrast at the cost of detail
+    /// in highlights and shadows.
+    Atkinson,
+    /// Three-row Sierra.
+    Sierra,
+    /// Two-row Sierra.
+    Sierra2,
+    SierraLite,
+}
+
+impl Kernel {
+    /// Neighbour offsets `(dx, dy)` with their weights, and the divisor.
+    pub(crate) fn taps(self) -> (&'static [(i32, i32, f32)], f32) {
+        match self {
+            Kernel::FloydSteinberg => {
+                (&[(1, 0, 7.0), (-1, 1, 3.0), (0, 1, 5.0), (1, 1, 1.0)], 16.0)
+            }
+            Kernel::Atkinson => (
+                &[
+                    (1, 0, 1.0),
+                    (2, 0, 1.0),
+                    (-1, 1, 1.0),
+                    (0, 1, 1.0),
+                    (1, 1, 1.0),
+                    (0, 2, 1.0),
+                ],
+                8.0,
+            ),
+            Kernel::Sierra => (
+                &[
+                    (1, 0, 5.0),
+                    (2, 0, 3.0),
+                    (-2, 1, 2.0),
+                    (-1, 1, 4.0),
+                    (0, 1, 5.0),
+                    (1, 1, 4.0),
+                    (2, 1, 2.0),
+                    (-1, 2, 2.0),
+                    (0, 2, 3.0),
+                    (1, 2, 2.0),
+                ],
+                32.0,
+            ),
+            Kernel::Sierra2 => (
+                &[
+                    (1, 0, 4.0),
+                    (2, 0, 3.0),
+                    (-2, 1, 1.0),
+                    (-1, 1, 2.0),
+                    (0, 1, 3.0),
+                    (1, 1, 2.0),
+                    (2, 1, 1.0),
+                ],
+                16.0,
+            ),
+            Kernel::SierraLite => (&[(1, 0, 2.0), (-1, 1, 1.0), (0, 1, 1.0)], 4.0),
+        }
+    }
+}
+
+impl fmt::Display for Dither {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Dither::None => f.write_str("none"),
+            Dither::Ordered(ThresholdMap::Bayer(n)) => write!(f, "bayer{n}"),
+            Dither::Ordered(ThresholdMap::BlueNoise) => f.write_str("blue-noise"),
+            Dither::Diffusion(Kernel::FloydSteinberg) => f.write_str("floyd-steinberg"),
+            Dither::Diffusion(Kernel::Atkinson) => f.write_str("atkinson"),
+            Dither::Diffusion(Kernel::Sierra) => f.write_str("sierra"),
+            Dither::Diffusion(Kernel::Sierra2) => f.write_str("sierra2"),
+            Dither::Diffusion(Kernel::SierraLite) => f.write_str("sierra-lite"),
+        }
+    }
+}
+
+impl FromStr for Dither {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lower = s.to_ascii_lowercase();
+        let dither = match lower.as_str() {
+            "none" => Dither::None,
+            "blue-noise" => Dither::Ordered(ThresholdMap::BlueNoise),
+            "bayer2" => Dither::Ordered(ThresholdMap::Bayer(2)),
+            "bayer4" => Dither::Ordered(ThresholdMap::Bayer(4)),
+            "bayer8" => Dither::Ordered(ThresholdMap::Bayer(8)),
+            "bayer16" => Dither::Ordered(ThresholdMap::Bayer(16)),
+            "floyd-steinberg" => Dither::Diffusion(Kernel::FloydSteinberg),
+            "atkinson" => Dither::Diffusion(Kernel::Atkinson),
+            "sierra" => Dither::Diffusion(Kernel::Sierra),
+            "sierra2" => Dither::Diffusion(Kernel::Sierra2),
+            "sierra-lite" => Dither::Diffusion(Kernel::SierraLite),
+            _ => return Err(Error::UnknownDither(s.to_string())),
+        };
+        Ok(dither)
+    }
+}
+
+/// Dithers `img` in place so that the rounding done by [`Layout::pack`]
+/// spreads each pixel between the two nearest levels. Alpha is dithered first
+/// so that colour diffusion sees the final coverage.
+pub(crate) fn dither_image(layout: &Layout, img: &mut RgbaImage, options: &PackOptions) {
+    if let Some(alpha) = alpha_target(layout)
+        && options.alpha_dither != Dither::None
+    {
+        if alpha.binary {
+            // Alpha at or below the threshold stays transparent whatever the dither says.
+            for px in img.pixels_mut() {
+                if !layout.alpha_mode.covers(px.0[3]) {
+                    px.0[3] = 0;
+                }
+            }
+        }
+        let targets = [None, None, None, Some(alpha)];
+        let transparent = vec![false; img.len() / 4];
+        apply(
+            img,
+            options.alpha_dither,
+            targets,
+            &transparent,
+            options.serpentine,
+        );
+    }
+
+    let transparent: Vec<bool> = match options.dither {
+        Dither::Diffusion(_) if options.protect_transparent => img
+            .pixels()
+            .map(|px| layout.unpack(layout.pack(px.0))[3] == 0)
+            .collect(),
+        _ => vec![false; img.len() / 4],
+    };
+    let targets = colour_targets(layout);
+    apply(
+        img,
+        options.dither,
+        targets,
+        &transparent,
+        options.serpentine,
+    );
+}
+
+/// A channel being dithered.
+#[derive(Debug, Clone, Copy)]
+struct Target {
+    ch: Channel,
+    /// A 1-bit alpha channel, which is decided by the alpha threshold rather
+    /// than by rounding, so it is written as fully opaque or transparent.
+    binary: bool,
+}
+
+impl Target {
+    /// Distance between two quantization levels in 8-bit units.
+    fn step(self) -> f32 {
+        255.0 / ((1u32 << self.ch.bits) - 1) as f32
+    }
+
+    /// The 8-bit value to store for the ideal value `v`, and what it decodes to.
+    fn settle(self, v: f32) -> (u8, f32) {
+        if self.binary {
+            let out = if v >= 127.5 { 255 } else { 0 };
+            (out, out as f32)
+        } else {
+            let out = v.round().clamp(0.0, 255.0) as u8;
+            (out, self.ch.expand(self.ch.quantize(out)) as f32)
+        }
+    }
+}
+
+fn apply(
+    img: &mut RgbaImage,
+    dither: Dither,
+    targets: [Option<Target>; 4],
+    transparent: &[bool],
+    serpentine: bool,
+) {
+    match dither {
+        Dither::None => {}
+        Dither::Ordered(map) => ordered(img, map, targets),
+        Dither::Diffusion(kernel) => diffuse(img, kernel, targets, transparent, serpentine),
+    }
+}
+
+fn ordered(img: &mut RgbaImage, map: ThresholdMap, targets: [Option<Target>; 4]) {
+    for (x, y, px) in img.enumerate_pixels_mut() {
+        let t = map.threshold(x, y) - 0.5;
+        for (v, target) in px.0.iter_mut().zip(targets) {
+            if let Some(target) = target {
+                *v = target.settle(*v as f32 + t * target.step()).0;
+            }
+        }
+    }
+}
+
+fn diffuse(
+    img: &mut RgbaImage,
+    kernel: Kernel,
+    targets: [Option<Target>; 4],
+    transparent: &[bool],
+    serpentine: bool,
+) {
+    let (taps, divisor) = kernel.taps();
+    let (width, height) = (img.width() as i32, img.height() as i32);
+    let mut work: Vec<[f32; 4]> = img.pixels().map(|px| px.0.map(|v| v as f32)).collect();
+
+    for y in 0..height {
+        let reverse = serpentine && y % 2 == 1;
+        for i in 0..width {
+            let x = if reverse { width - 1 - i } else { i };
+            let idx = (y * width + x) as usize;
+            if transparent[idx] {
+                continue;
+            }
+            let px = img.get_pixel_mut(x as u32, y as u32);
+            let mut error = [0.0f32; 4];
+            for c in 0..4 {
+                let Some(target) = targets[c] else { continue };
+                let (out, decoded) = target.settle(work[idx][c]);
+                px.0[c] = out;
+                error[c] = work[idx][c] - decoded;
+            }
+            for &(dx, dy, weight) in taps {
+                let (nx, ny) = (if reverse { x - dx } else { x + dx }, y + dy);
+                if nx < 0 || nx >= width || ny >= height {
+                    continue;
+                }
+                let n = (ny * width + nx) as usize;
+                if transparent[n] {
+                    continue;
+                }
+                for c in 0..4 {
+                    work[n][c] += error[c] * weight / divisor;
+                }
+            }
+        }
+    }
+}
+
+/// The channels quantizing R, G and B, where they are narrow enough to be
+/// worth dithering. Luminance layouts dither all three by the `L` channel.
+fn colour_targets(layout: &Layout) -> [Option<Target>; 4] {
+    let narrow = |ch: Option<Channel>| {
+        ch.filter(|ch| ch.bits < 8)
+            .map(|ch| Target { ch, binary: false })
+    };
+    match layout.l {
+        Some(_) => [narrow(layout.l), narrow(layout.l), narrow(layout.l), None],
+        None => [narrow(layout.r), narrow(layout.g), narrow(layout.b), None],
+    }
+}
+
+fn alpha_target(layout: &Layout) -> Option<Target> {
+    layout.a.filter(|ch| ch.bits < 8).map(|ch| Target {
+        ch,
+        binary: ch.bits == 1,
+    })
+}
+
+/// Position of `(x, y)` in the recursively built `n`x`n` Bayer matrix. The
+/// lowest coordinate bits select the most significant part of the rank.
+fn bayer_rank(mut x: u32, mut y: u32, n: u32) -> u32 {
+    let mut rank = 0;
+    let mut size = n;
+    while size > 1 {
+        rank = rank * 4 + [0, 2, 3, 1][(y % 2 * 2 + x % 2) as usize];
+        x /= 2;
+        y /= 2;
+        size /= 2;
+    }
+    rank
+}
+
+/// Ranks of the built-in blue-noise map, generated once with the
+/// void-and-cluster method on a torus.
+fn blue_noise() -> &'static [u16] {
+    static MAP: OnceLock<Vec<u16>> = OnceLock::new();
+    MAP.get_or_init(|| void_and_cluster(BLUE_NOISE_SIZE))
+}
+
+fn void_and_cluster(size: usize) -> Vec<u16> {
+    let n = size * size;
+    let sigma = 1.5f32;
+    let kernel: Vec<f32> = (0..n)
+        .map(|i| {
+            let (dx, dy) = ((i % size) as f32, (i / size) as f32);
+            let dx = dx.min(size as f32 - dx);
+            let dy = dy.min(size as f32 - dy);
+            (-(dx * dx + dy * dy) / (2.0 * sigma * sigma)).exp()
+        })
+        .collect();
+
+    let mut pattern = vec![false; n];
+    let mut energy = vec![0.0f32; n];
+    let toggle = |pattern: &mut [bool], energy: &mut [f32], i: usize| {
+        pattern[i] = !pattern[i];
+        let sign = if pattern[i] { 1.0 } else { -1.0 };
+        let (ix, iy) = (i % size, i / size);
+        for (j, e) in energy.iter_mut().enumerate() {
+            let dx = (j % size + size - ix) % size;
+            let dy = (j / size + size - iy) % size;
+            *e += sign * kernel[dy * size + dx];
+        }
+    };
+    let tightest_cluster = |pattern: &[bool], energy: &[f32]| {
+        (0..n)
+            .filter(|&i| pattern[i])
+            .max_by(|&a, &b| energy[a].total_cmp(&energy[b]))
+            .unwrap()
+    };
+    let largest_void = |pattern: &[bool], energy: &[f32]| {
+        (0..n)
+            .filter(|&i| !pattern[i])
+            .min_by(|&a, &b| energy[a].total_cmp(&energy[b]))
+            .unwrap()
+    };
+
+    // Deterministic initial pattern of roughly 10% minority pixels.
+    let mut seed = 0x2545_f491_u32;
+    let ones = n / 10;
+    while pattern.iter().filter(|&&p| p).count() < ones {
+        seed ^= seed << 13;
+        seed ^= seed >> 17;
+        seed ^= seed << 5;
+        let i = seed as usize % n;
+        if !pattern[i] {
+            toggle(&mut pattern, &mut energy, i);
+        }
+    }
+    // Relax it until removing the tightest cluster creates the largest void.
+    for _ in 0..n {
+        let cluster = tightest_cluster(&pattern, &energy);
+        toggle(&mut pattern, &mut energy, cluster);
+        let void = largest_void(&pattern, &energy);
+        if void == cluster {
+            toggle(&mut pattern, &mut energy, cluster);
+            break;
+        }
+        toggle(&mut pattern, &mut energy, void);
+    }
+
+    let mut ranks = vec![0u16; n];
+    let (initial, initial_energy) = (pattern.clone(), energy.clone());
+    for rank in (0..ones).rev() {
+        let cluster = tightest_cluster(&pattern, &energy);
+        toggle(&mut pattern, &mut energy, cluster);
+        ranks[cluster] = rank as u16;
+    }
+    let (mut pattern, mut energy) = (initial, initial_energy);
+    for rank in ones..n {
+        let void = largest_void(&pattern, &energy);
+        toggle(&mut pattern, &mut energy, void);
+        ranks[void] = rank as u16;
+    }
+    ranks
+}
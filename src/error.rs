@@ -0,0 +1,176 @@
+use std::io;
+use std::path::PathBuf;
+
+/// Errors produced while packing or unpacking images.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// Reading or writing a file failed.
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    /// Decoding or encoding an image failed.
+    #[error(transparent)]
+    Image(#[from] image::ImageError),
+    /// The input image does not hold packed data of the expected size.
+    #[error("image of type {0:?} does not match the layout's container")]
+    UnsupportedColorType(image::ColorType),
+    /// The pixel format name is not recognised.
+    #[error("unknown pixel format `{0}`")]
+    UnknownFormat(String),
+    /// The alpha polarity name is not recognised.
+    #[error("unknown alpha polarity `{0}`, expected opaque, transparent or semi-transparent")]
+    UnknownAlphaPolarity(String),
+    /// The dithering mode name is not recognised.
+    #[error(
+        "unknown dither mode `{0}`, expected none, bayer2, bayer4, bayer8, bayer16, blue-noise, floyd-steinberg, atkinson, sierra, sierra2 or sierra-lite"
+    )]
+    UnknownDither(String),
+    /// The palette quantizer name is not recognised.
+    #[error("unknown quantizer `{0}`, expected median-cut or k-means")]
+    UnknownQuantizer(String),
+    /// Palette indices of an unsupported width were requested.
+    #[error("palette indices must be 4 or 8 bits, not {0}")]
+    InvalidIndexBits(u32),
+    /// The carrier has no way to store palette indices.
+    #[error("{0} files cannot hold palette indices")]
+    IndexedCarrier(crate::Carrier),
+    /// A palette kept in its own file was used with standard input or output.
+    #[error("paletted data on standard input or output needs an explicit palette file")]
+    MissingPalette,
+    /// The tile format name is not recognised.
+    #[error("unknown tile format `{0}`, expected gba, nds or snes")]
+    UnknownTileFormat(String),
+    /// Tiled images must be made of whole tiles.
+    #[error("{width}x{height} is not a whole number of 8x8 tiles")]
+    TileDimensions { width: u32, height: u32 },
+    /// An image has more unique tiles than a tilemap entry can number.
+    #[error("image has more than {0} unique tiles")]
+    TooManyTiles(usize),
+    /// The carrier has no way to store tiles.
+    #[error("{0} files cannot hold tiles")]
+    TiledCarrier(crate::Carrier),
+    /// A tilemap kept in its own file was used with standard input or output.
+    #[error("tiled data on standard input or output needs an explicit tilemap file")]
+    MissingTilemap,
+    /// The swizzle name is not recognised.
+    #[error("unknown swizzle `{0}`, expected morton, psp, ps2, 3ds or switch")]
+    UnknownSwizzle(String),
+    /// The swizzle has no layout for pixels of this size.
+    #[error("{swizzle} swizzle does not support {bits}-bit pixels")]
+    UnsupportedSwizzle { swizzle: crate::Swizzle, bits: u32 },
+    /// Swizzling was combined with paletted or tiled output.
+    #[error("swizzling applies to packed pixels, not palette indices or tiles")]
+    SwizzleConflict,
+    /// The atlas packer name is not recognised.
+    #[error("unknown packer `{0}`, expected maxrects or skyline")]
+    UnknownPacker(String),
+    /// A sprite does not fit on an empty atlas page.
+    #[error("sprite `{name}` ({width}x{height}) does not fit the largest atlas page")]
+    SpriteTooLarge {
+        name: String,
+        width: u32,
+        height: u32,
+    },
+    /// The sprite sheet metadata format name is not recognised.
+    #[error("unknown sheet format `{0}`, expected json-hash, json-array, phaser, libgdx or csv")]
+    UnknownSheetFormat(String),
+    /// Sprite sheet metadata was requested for standard output without a file.
+    #[error("a sprite sheet on standard output needs an explicit metadata file")]
+    MissingSheetFile,
+    /// A multi-page atlas was written to standard output.
+    #[error("the atlas needs {0} pages, which cannot all go to standard output")]
+    AtlasPages(usize),
+    /// A slicing grid fits no whole cell into the image.
+    #[error("no {width}x{height} grid cell fits the image")]
+    EmptyGrid { width: u32, height: u32 },
+    /// Sprite sheet metadata lists several pages, none of them the input.
+    #[error("the sprite sheet metadata does not list page `{0}`")]
+    SheetPage(String),
+    /// Two frames would be written to the same file.
+    #[error("more than one frame is named `{0}`")]
+    DuplicateFrame(String),
+    /// Frames sliced into their own files were written to standard output.
+    #[error("sliced frames need an output directory, not standard output")]
+    SliceStdout,
+    /// The mipmap filter name is not recognised.
+    #[error("unknown mip filter `{0}`, expected box, triangle, mitchell or lanczos")]
+    UnknownMipFilter(String),
+    /// Mipmap levels written to their own files were sent to standard output.
+    #[error("the image has {0} mip levels, which cannot all go to standard output")]
+    MipLevels(usize),
+    /// A mip-chain image was combined with paletted or tiled output.
+    #[error("a mip-chain image holds packed pixels, not palette indices or tiles")]
+    MipChainConflict,
+    /// The layout spec could not be parsed.
+    #[error("invalid layout `{spec}`: {reason}")]
+    InvalidLayout { spec: String, reason: String },
+    /// The layout does not fit into the requested container type.
+    #[error("{layout}-bit layout does not fit a {container}-bit container")]
+    ContainerTooSmall { layout: u32, container: u32 },
+    /// The carrier name is not recognised.
+    #[error(
+        "unknown carrier `{0}`, expected image, raw, c, cpp, rust, asm, dds, ktx, ktx2, tga or bmp"
+    )]
+    UnknownCarrier(String),
+    /// The carrier can be written but not read back.
+    #[error("{0} files cannot be unpacked")]
+    UnreadableCarrier(crate::Carrier),
+    /// The byte order name is not recognised.
+    #[error("unknown byte order `{0}`, expected little or big")]
+    UnknownEndian(String),
+    /// Headerless input was given without its width and height.
+    #[error("headerless input needs an explicit width and height")]
+    MissingDimensions,
+    /// The row stride is shorter than a row of pixels.
+    #[error("stride of {stride} bytes is shorter than a {row}-byte row")]
+    StrideTooSmall { stride: usize, row: usize },
+    /// Rows this far apart would make the output implausibly large.
+    #[error("{rows} rows {stride} bytes apart exceed the 4 GiB raw output limit")]
+    RawTooLarge { stride: usize, rows: usize },
+    /// The input ends before all pixels were read.
+    #[error("input holds {actual} bytes, expected at least {expected}")]
+    Truncated { expected: usize, actual: usize },
+    /// A channel mask in a file header is not a contiguous run of bits.
+    #[error("channel mask {0:#010x} is not contiguous")]
+    InvalidMask(u32),
+    /// A channel mask in a file header has bits beyond the pixel size.
+    #[error("channel mask {mask:#010x} does not fit {bits}-bit pixels")]
+    MaskOutOfRange { mask: u32, bits: u32 },
+    /// Two channel masks in a file header share bits.
+    #[error("channel masks {0:#010x} and {1:#010x} overlap")]
+    OverlappingMasks(u32, u32),
+    /// A container file is malformed or uses an unsupported feature.
+    #[error("invalid {format} file: {reason}")]
+    InvalidFile {
+        format: &'static str,
+        reason: String,
+    },
+    /// No image format matches the requested output extension.
+    #[error("no image format for extension `{0}`")]
+    UnknownImageFormat(String),
+    /// Output to standard output was requested without naming its format.
+    #[error("standard output needs an explicit output format")]
+    MissingOutputFormat,
+    /// A glob pattern is malformed.
+    #[error(transparent)]
+    Pattern(#[from] glob::PatternError),
+    /// The worker threads for a batch could not be started.
+    #[error(transparent)]
+    ThreadPool(#[from] rayon::ThreadPoolBuildError),
+    /// A directory or glob pattern did not yield any input files.
+    #[error("`{}` matched no input files", .0.display())]
+    NoInputs(PathBuf),
+    /// Two batch inputs would be written to the same output file.
+    #[error("several inputs would be written to `{}`", .0.display())]
+    DuplicateOutput(PathBuf),
+    /// Two images that are compared differ in size.
+    #[error("image is {}x{}, expected {}x{}", .actual.0, .actual.1, .expected.0, .expected.1)]
+    DimensionMismatch {
+        expected: (u32, u32),
+        actual: (u32, u32),
+    },
+    /// A buffer does not match the length implied by its dimensions.
+    #[error("buffer holds {actual} elements, expected {expected}")]
+    BufferSize { expected: usize, actual: usize },
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
@@ -0,0 +1,519 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::Error;
+
+/// A single colour channel inside a packed value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Channel {
+    /// Bit position of the least significant bit.
+    pub shift: u32,
+    /// Number of bits.
+    pub bits: u32,
+}
+
+impl Channel {
+    const fn new(shift: u32, bits: u32) -> Self {
+        Self { shift, bits }
+    }
+
+    /// The largest value this channel holds.
+    pub fn max(self) -> u32 {
+        (1 << self.bits) - 1
+    }
+
+    /// Quantizes an 8-bit value to this channel's width, rounding to nearest.
+    pub fn quantize(self, v: u8) -> u32 {
+        (v as u32 * self.max() + 127) / 255
+    }
+
+    /// Expands a value of this channel's width back to 8 bits.
+    pub fn expand(self, q: u32) -> u8 {
+        let max = self.max();
+        ((q as u64 * 255 + max as u64 / 2) / max as u64) as u8
+    }
+
+    /// The bits this channel occupies.
+    pub fn mask(self) -> u32 {
+        self.max() << self.shift
+    }
+
+    /// Reads a channel from a contiguous bit mask, `None` for an empty mask.
+    pub fn from_mask(mask: u32) -> Result<Option<Self>, Error> {
+        if mask == 0 {
+            return Ok(None);
+        }
+        let shift = mask.trailing_zeros();
+        let bits = mask.count_ones();
+        if bits > 16 || (mask >> shift) != (1 << bits) - 1 {
+            return Err(Error::InvalidMask(mask));
+        }
+        Ok(Some(Channel::new(shift, bits)))
+    }
+
+    fn insert(self, q: u32) -> u32 {
+        q << self.shift
+    }
+
+    /// Reads this channel's raw value out of a packed pixel.
+    pub fn extract(self, packed: u32) -> u32 {
+        (packed >> self.shift) & self.max()
+    }
+}
+
+/// Bit positions of each channel in a packed pixel. Missing channels are
+/// dropped on pack; colours decode as 0 and alpha as opaque on unpack. Bits
+/// not covered by any channel are padding and always written as zero.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Layout {
+    /// Container size in bits: 8, 16 or 32.
+    pub bits: u32,
+    pub r: Option<Channel>,
+    pub g: Option<Channel>,
+    pub b: Option<Channel>,
+    pub a: Option<Channel>,
+    /// Luminance, mutually exclusive with `r`, `g` and `b`.
+    pub l: Option<Channel>,
+    /// How a 1-bit alpha channel is set and read.
+    pub alpha_mode: AlphaMode,
+}
+
+impl Layout {
+    /// Packs 8-bit RGBA into a value of this layout. A 1-bit alpha channel
+    /// follows [`Layout::alpha_mode`], wider ones are quantized like colours.
+    pub fn pack(&self, [r, g, b, a]: [u8; 4]) -> u32 {
+        let mut packed = 0;
+        for (ch, v) in [
+            (self.r, r),
+            (self.g, g),
+            (self.b, b),
+            (self.l, luma(r, g, b)),
+        ] {
+            if let Some(ch) = ch {
+                packed |= ch.insert(ch.quantize(v));
+            }
+        }
+        if let Some(ch) = self.a {
+            let q = if ch.bits == 1 {
+                let covered = self.alpha_mode.covers(a);
+                match self.alpha_mode.polarity {
+                    AlphaPolarity::Opaque => covered as u32,
+                    AlphaPolarity::Transparent => !covered as u32,
+                    AlphaPolarity::SemiTransparent if !covered => return 0,
+                    // Covered black sets the bit so it does not read back as
+                    // the all-zero transparent value.
+                    AlphaPolarity::SemiTransparent => (a < 255 || packed == 0) as u32,
+                }
+            } else {
+                ch.quantize(a)
+            };
+            packed |= ch.insert(q);
+        }
+        packed
+    }
+
+    /// Expands a packed value to 8-bit RGBA.
+    pub fn unpack(&self, packed: u32) -> [u8; 4] {
+        let get =
+            |ch: Option<Channel>, default| ch.map_or(default, |ch| ch.expand(ch.extract(packed)));
+        let a = match self.a {
+            Some(ch) if ch.bits == 1 => {
+                let set = ch.extract(packed) == 1;
+                match (self.alpha_mode.polarity, set) {
+                    (AlphaPolarity::Opaque, true) | (AlphaPolarity::Transparent, false) => 255,
+                    (AlphaPolarity::Opaque, false) | (AlphaPolarity::Transparent, true) => 0,
+                    (AlphaPolarity::SemiTransparent, true) if packed == ch.insert(1) => 255,
+                    (AlphaPolarity::SemiTransparent, true) => 128,
+                    (AlphaPolarity::SemiTransparent, false) if packed == 0 => 0,
+                    (AlphaPolarity::SemiTransparent, false) => 255,
+                }
+            }
+            _ => get(self.a, 255),
+        };
+        match self.l {
+            Some(_) => {
+                let l = get(self.l, 0);
+                [l, l, l, a]
+            }
+            None => [get(self.r, 0), get(self.g, 0), get(self.b, 0), a],
+        }
+    }
+
+    /// The channels present, named by their spec letter, in R, G, B, A, L order.
+    pub fn channels(&self) -> impl Iterator<Item = (char, Channel)> {
+        [
+            ('R', self.r),
+            ('G', self.g),
+            ('B', self.b),
+            ('A', self.a),
+            ('L', self.l),
+        ]
+        .into_iter()
+        .filter_map(|(name, ch)| ch.map(|ch| (name, ch)))
+    }
+
+    /// Parses a layout spec such as `A1R5G5B5`, `R10G10B10A2` or `X1R5G5B5`.
+    ///
+    /// Channels are listed from the most significant bit down, each as one of
+    /// `R`, `G`, `B`, `A`, `L` (luminance) or `X` (padding) followed by its
+    /// width. The widths must add up to 8, 16 or 32 bits.
+    pub fn parse_spec(spec: &str) -> Result<Self, Error> {
+        let invalid = |reason: String| Error::InvalidLayout {
+            spec: spec.to_string(),
+            reason,
+        };
+
+        let mut fields = Vec::new();
+        let mut chars = spec.trim().chars().peekable();
+        while let Some(c) = chars.next() {
+            let name = c.to_ascii_uppercase();
+            if !"RGBALX".contains(name) {
+                return Err(invalid(format!("unexpected character `{c}`")));
+            }
+            let mut digits = String::new();
+            while let Some(d) = chars.next_if(char::is_ascii_digit) {
+                digits.push(d);
+            }
+            if digits.is_empty() {
+                return Err(invalid(format!("channel `{name}` has no width")));
+            }
+            let bits: u32 = digits
+                .parse()
+                .map_err(|e| invalid(format!("channel `{name}` width `{digits}`: {e}")))?;
+            let max = if name == 'X' { 32 } else { 16 };
+            if bits == 0 || bits > max {
+                return Err(invalid(format!(
+                    "channel `{name}` width must be between 1 and {max}"
+                )));
+            }
+            fields.push((name, bits));
+        }
+
+        let total = fields
+            .iter()
+            .try_fold(0u32, |total, &(_, bits)| total.checked_add(bits))
+            .filter(|total| [8, 16, 32].contains(total));
+        let Some(total) = total else {
+            let total: u64 = fields.iter().map(|&(_, bits)| u64::from(bits)).sum();
+            return Err(invalid(format!(
+                "widths add up to {total} bits, expected 8, 16 or 32"
+            )));
+        };
+
+        let mut layout = Layout {
+            bits: total,
+            r: None,
+            g: None,
+            b: None,
+            a: None,
+            l: None,
+            alpha_mode: AlphaMode::default(),
+        };
+        let mut shift = total;
+        for (name, bits) in fields {
+            shift -= bits;
+            let slot = match name {
+                'R' => &mut layout.r,
+                'G' => &mut layout.g,
+                'B' => &mut layout.b,
+                'A' => &mut layout.a,
+                'L' => &mut layout.l,
+                _ => continue,
+            };
+            if slot.is_some() {
+                return Err(invalid(format!("channel `{name}` appears more than once")));
+            }
+            *slot = Some(Channel::new(shift, bits));
+        }
+
+        if layout.l.is_some() && (layout.r.is_some() || layout.g.is_some() || layout.b.is_some()) {
+            return Err(invalid(
+                "`L` cannot be combined with `R`, `G` or `B`".to_string(),
+            ));
+        }
+        if layout.channels().next().is_none() {
+            return Err(invalid("layout has no channels".to_string()));
+        }
+        Ok(layout)
+    }
+
+    /// Builds a layout from the per-channel bit masks found in DDS and BMP
+    /// headers. Empty masks mark missing channels; with `luminance` the red
+    /// mask describes `L`. Masks must fit in `bits` and must not share bits.
+    pub fn from_masks(bits: u32, [r, g, b, a]: [u32; 4], luminance: bool) -> Result<Self, Error> {
+        let masks = [r, g, b, a];
+        if let Some(&mask) = masks.iter().find(|&&m| u64::from(m) >> bits != 0) {
+            return Err(Error::MaskOutOfRange { mask, bits });
+        }
+        for (i, &first) in masks.iter().enumerate() {
+            if let Some(&second) = masks[i + 1..].iter().find(|&&m| m & first != 0) {
+                return Err(Error::OverlappingMasks(first, second));
+            }
+        }
+        let mut layout = Layout {
+            bits,
+            r: Channel::from_mask(r)?,
+            g: Channel::from_mask(g)?,
+            b: Channel::from_mask(b)?,
+            a: Channel::from_mask(a)?,
+            l: None,
+            alpha_mode: AlphaMode::default(),
+        };
+        if luminance {
+            layout.l = layout.r.take();
+        }
+        Ok(layout)
+    }
+
+    /// The bit masks of R (or `L`), G, B and A, zero for missing channels.
+    pub fn masks(&self) -> [u32; 4] {
+        let mask = |ch: Option<Channel>| ch.map_or(0, Channel::mask);
+        [
+            mask(self.r.or(self.l)),
+            mask(self.g),
+            mask(self.b),
+            mask(self.a),
+        ]
+    }
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        PixelFormat::default().layout()
+    }
+}
+
+impl From<PixelFormat> for Layout {
+    fn from(format: PixelFormat) -> Self {
+        format.layout()
+    }
+}
+
+impl fmt::Display for Layout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut channels: Vec<_> = self.channels().collect();
+        channels.sort_by_key(|&(_, ch)| std::cmp::Reverse(ch.shift));
+        let mut next = self.bits;
+        for (name, ch) in channels {
+            let top = ch.shift + ch.bits;
+            if top < next {
+                write!(f, "X{}", next - top)?;
+            }
+            write!(f, "{name}{}", ch.bits)?;
+            next = ch.shift;
+        }
+        if next > 0 {
+            write!(f, "X{next}")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Layout {
+    type Err = Error;
+
+    /// Accepts either a [`PixelFormat`] name or a layout spec.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.parse::<PixelFormat>() {
+            Ok(format) => Ok(format.layout()),
+            Err(_) => Layout::parse_spec(s),
+        }
+    }
+}
+
+/// What a set 1-bit alpha channel means to the target hardware.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AlphaPolarity {
+    /// The bit is set for opaque pixels.
+    #[default]
+    Opaque,
+    /// The bit is set for transparent pixels.
+    Transparent,
+    /// The bit marks semi-transparent pixels, PlayStation style: a value of
+    /// zero is fully transparent, the bit is set for partial alpha and left
+    /// clear for opaque pixels. As on the PlayStation, black with the bit
+    /// set is opaque black, so covered black always packs that way;
+    /// other semi-transparent pixels unpack with alpha 128.
+    SemiTransparent,
+}
+
+impl AlphaPolarity {
+    pub fn name(self) -> &'static str {
+        match self {
+            AlphaPolarity::Opaque => "opaque",
+            AlphaPolarity::Transparent => "transparent",
+            AlphaPolarity::SemiTransparent => "semi-transparent",
+        }
+    }
+}
+
+impl fmt::Display for AlphaPolarity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for AlphaPolarity {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        [
+            AlphaPolarity::Opaque,
+            AlphaPolarity::Transparent,
+            AlphaPolarity::SemiTransparent,
+        ]
+        .into_iter()
+        .find(|p| p.name().eq_ignore_ascii_case(s))
+        .ok_or_else(|| Error::UnknownAlphaPolarity(s.to_string()))
+    }
+}
+
+/// Rule for reducing 8-bit alpha to a 1-bit alpha channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct AlphaMode {
+    /// Pixels with alpha above this value count as covered.
+    pub threshold: u8,
+    pub polarity: AlphaPolarity,
+}
+
+impl AlphaMode {
+    /// Whether a pixel with alpha `a` counts as covered rather than transparent.
+    pub fn covers(&self, a: u8) -> bool {
+        a > self.threshold
+    }
+}
+
+/// An unsigned integer type that can hold packed pixels.
+pub trait Container: Copy + Default {
+    const BITS: u32;
+
+    fn from_packed(packed: u32) -> Self;
+
+    fn to_packed(self) -> u32;
+}
+
+macro_rules! impl_container {
+    ($($t:ty),*) => {$(
+        impl Container for $t {
+            const BITS: u32 = <$t>::BITS;
+
+            fn from_packed(packed: u32) -> Self {
+                packed as $t
+            }
+
+            fn to_packed(self) -> u32 {
+                self as u32
+            }
+        }
+    )*};
+}
+
+impl_container!(u8, u16, u32);
+
+/// The named 16-bit pixel formats. Names list channels from the most
+/// significant bit down, `X` marks an unused bit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PixelFormat {
+    #[default]
+    Argb1555,
+    Xrgb1555,
+    Rgba5551,
+    Abgr1555,
+    Xbgr1555,
+    Bgra5551,
+    Rgb565,
+    Bgr565,
+    Argb4444,
+    Rgba4444,
+    Abgr4444,
+    Bgra4444,
+}
+
+impl PixelFormat {
+    pub const ALL: [PixelFormat; 12] = [
+        PixelFormat::Argb1555,
+        PixelFormat::Xrgb1555,
+        PixelFormat::Rgba5551,
+        PixelFormat::Abgr1555,
+        PixelFormat::Xbgr1555,
+        PixelFormat::Bgra5551,
+        PixelFormat::Rgb565,
+        PixelFormat::Bgr565,
+        PixelFormat::Argb4444,
+        PixelFormat::Rgba4444,
+        PixelFormat::Abgr4444,
+        PixelFormat::Bgra4444,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            PixelFormat::Argb1555 => "argb1555",
+            PixelFormat::Xrgb1555 => "xrgb1555",
+            PixelFormat::Rgba5551 => "rgba5551",
+            PixelFormat::Abgr1555 => "abgr1555",
+            PixelFormat::Xbgr1555 => "xbgr1555",
+            PixelFormat::Bgra5551 => "bgra5551",
+            PixelFormat::Rgb565 => "rgb565",
+            PixelFormat::Bgr565 => "bgr565",
+            PixelFormat::Argb4444 => "argb4444",
+            PixelFormat::Rgba4444 => "rgba4444",
+            PixelFormat::Abgr4444 => "abgr4444",
+            PixelFormat::Bgra4444 => "bgra4444",
+        }
+    }
+
+    pub fn layout(self) -> Layout {
+        let c = |shift, bits| Some(Channel::new(shift, bits));
+        let (r, g, b, a) = match self {
+            PixelFormat::Argb1555 => (c(10, 5), c(5, 5), c(0, 5), c(15, 1)),
+            PixelFormat::Xrgb1555 => (c(10, 5), c(5, 5), c(0, 5), None),
+            PixelFormat::Rgba5551 => (c(11, 5), c(6, 5), c(1, 5), c(0, 1)),
+            PixelFormat::Abgr1555 => (c(0, 5), c(5, 5), c(10, 5), c(15, 1)),
+            PixelFormat::Xbgr1555 => (c(0, 5), c(5, 5), c(10, 5), None),
+            PixelFormat::Bgra5551 => (c(1, 5), c(6, 5), c(11, 5), c(0, 1)),
+            PixelFormat::Rgb565 => (c(11, 5), c(5, 6), c(0, 5), None),
+            PixelFormat::Bgr565 => (c(0, 5), c(5, 6), c(11, 5), None),
+            PixelFormat::Argb4444 => (c(8, 4), c(4, 4), c(0, 4), c(12, 4)),
+            PixelFormat::Rgba4444 => (c(12, 4), c(8, 4), c(4, 4), c(0, 4)),
+            PixelFormat::Abgr4444 => (c(0, 4), c(4, 4), c(8, 4), c(12, 4)),
+            PixelFormat::Bgra4444 => (c(4, 4), c(8, 4), c(12, 4), c(0, 4)),
+        };
+        Layout {
+            bits: 16,
+            r,
+            g,
+            b,
+            a,
+            l: None,
+            alpha_mode: AlphaMode::default(),
+        }
+    }
+}
+
+impl fmt::Display for PixelFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for PixelFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lower = s.to_ascii_lowercase();
+        let alias = match lower.as_str() {
+            "rgb555" => "xrgb1555",
+            "bgr555" => "xbgr1555",
+            other => other,
+        };
+        PixelFormat::ALL
+            .into_iter()
+            .find(|f| f.name() == alias)
+            .ok_or_else(|| Error::UnknownFormat(s.to_string()))
+    }
+}
+
+/// Rec. 601 luma of an 8-bit colour.
+fn luma(r: u8, g: u8, b: u8) -> u8 {
+    ((r as u32 * 77 + g as u32 * 150 + b as u32 * 29 + 128) >> 8) as u8
+}
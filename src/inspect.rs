@@ -0,0 +1,97 @@
+//! Statistics and bit-field breakdowns of packed pixels.
+
+use std::collections::{BTreeMap, HashSet};
+
+use crate::{Channel, Layout};
+
+/// Raw values seen in one channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChannelRange {
+    /// Spec letter of the channel.
+    pub name: char,
+    pub channel: Channel,
+    /// Smallest raw value.
+    pub min: u32,
+    /// Largest raw value.
+    pub max: u32,
+}
+
+/// Summary of a buffer of packed pixels.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Inspection {
+    /// Value range of each channel, in [`Layout::channels`] order.
+    pub ranges: Vec<ChannelRange>,
+    /// Number of pixels for each raw alpha value that occurs, in increasing
+    /// order; empty without an alpha channel.
+    pub alpha_histogram: Vec<(u32, usize)>,
+    /// Number of distinct packed values.
+    pub unique_colours: usize,
+    /// Number of pixels with any padding bit set.
+    pub padding_set: usize,
+}
+
+/// One channel of a single packed pixel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Field {
+    /// Spec letter of the channel.
+    pub name: char,
+    pub channel: Channel,
+    /// Raw bits of the channel.
+    pub raw: u32,
+    /// The raw value expanded to 8 bits.
+    pub expanded: u8,
+}
+
+/// Gathers channel ranges, the alpha histogram and colour counts of `values`.
+pub fn inspect(layout: &Layout, values: &[u32]) -> Inspection {
+    let mut ranges: Vec<ChannelRange> = layout
+        .channels()
+        .map(|(name, channel)| ChannelRange {
+            name,
+            channel,
+            min: channel.max(),
+            max: 0,
+        })
+        .collect();
+    let used = layout.channels().fold(0, |mask, (_, ch)| mask | ch.mask());
+    let mut alpha = BTreeMap::new();
+    let mut unique = HashSet::new();
+    let mut padding_set = 0;
+    for &value in values {
+        for range in &mut ranges {
+            let raw = range.channel.extract(value);
+            range.min = range.min.min(raw);
+            range.max = range.max.max(raw);
+        }
+        if let Some(a) = layout.a {
+            *alpha.entry(a.extract(value)).or_insert(0) += 1;
+        }
+        unique.insert(value);
+        padding_set += (value & !used != 0) as usize;
+    }
+    if values.is_empty() {
+        ranges.iter_mut().for_each(|range| range.min = 0);
+    }
+    Inspection {
+        ranges,
+        alpha_histogram: alpha.into_iter().collect(),
+        unique_colours: unique.len(),
+        padding_set,
+    }
+}
+
+/// Splits a packed value into its channels, in [`Layout::channels`] order.
+pub fn fields(layout: &Layout, value: u32) -> Vec<Field> {
+    layout
+        .channels()
+        .map(|(name, channel)| {
+            let raw = channel.extract(value);
+            Field {
+                name,
+                channel,
+                raw,
+                expanded: channel.expand(raw),
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,272 @@
+//! KTX 1 and KTX 2 texture containers for the packed formats that OpenGL
+//! and Vulkan define.
+
+use crate::{AlphaMode, Endian, Error, Layout, RawFormat, Result};
+
+const KTX1_IDENTIFIER: [u8; 12] = [
+    0xAB, b'K', b'T', b'X', b' ', b'1', b'1', 0xBB, b'\r', b'\n', 0x1A, b'\n',
+];
+const KTX2_IDENTIFIER: [u8; 12] = [
+    0xAB, b'K', b'T', b'X', b' ', b'2', b'0', 0xBB, b'\r', b'\n', 0x1A, b'\n',
+];
+const KTX1_ENDIANNESS: u32 = 0x0403_0201;
+
+const GL_RGB: u32 = 0x1907;
+const GL_RGBA: u32 = 0x1908;
+const GL_BGRA: u32 = 0x80E1;
+const GL_UNSIGNED_BYTE_3_3_2: u32 = 0x8032;
+const GL_UNSIGNED_SHORT_4_4_4_4: u32 = 0x8033;
+const GL_UNSIGNED_SHORT_5_5_5_1: u32 = 0x8034;
+const GL_UNSIGNED_SHORT_5_6_5: u32 = 0x8363;
+const GL_UNSIGNED_SHORT_5_6_5_REV: u32 = 0x8364;
+const GL_UNSIGNED_SHORT_4_4_4_4_REV: u32 = 0x8365;
+const GL_UNSIGNED_SHORT_1_5_5_5_REV: u32 = 0x8366;
+const GL_R3_G3_B2: u32 = 0x2A10;
+const GL_RGBA4: u32 = 0x8056;
+const GL_RGB5_A1: u32 = 0x8057;
+const GL_RGB565: u32 = 0x8D62;
+
+/// OpenGL `(type, format, internal format)` triples and the layout they store.
+const GL_FORMATS: [(u32, u32, u32, &str); 11] = [
+    (
+        GL_UNSIGNED_SHORT_1_5_5_5_REV,
+        GL_BGRA,
+        GL_RGB5_A1,
+        "A1R5G5B5",
+    ),
+    (
+        GL_UNSIGNED_SHORT_1_5_5_5_REV,
+        GL_RGBA,
+        GL_RGB5_A1,
+        "A1B5G5R5",
+    ),
+    (GL_UNSIGNED_SHORT_5_5_5_1, GL_RGBA, GL_RGB5_A1, "R5G5B5A1"),
+    (GL_UNSIGNED_SHORT_5_5_5_1, GL_BGRA, GL_RGB5_A1, "B5G5R5A1"),
+    (GL_UNSIGNED_SHORT_5_6_5, GL_RGB, GL_RGB565, "R5G6B5"),
+    (GL_UNSIGNED_SHORT_5_6_5_REV, GL_RGB, GL_RGB565, "B5G6R5"),
+    (GL_UNSIGNED_SHORT_4_4_4_4, GL_RGBA, GL_RGBA4, "R4G4B4A4"),
+    (GL_UNSIGNED_SHORT_4_4_4_4, GL_BGRA, GL_RGBA4, "B4G4R4A4"),
+    (GL_UNSIGNED_SHORT_4_4_4_4_REV, GL_BGRA, GL_RGBA4, "A4R4G4B4"),
+    (GL_UNSIGNED_SHORT_4_4_4_4_REV, GL_RGBA, GL_RGBA4, "A4B4G4R4"),
+    (GL_UNSIGNED_BYTE_3_3_2, GL_RGB, GL_R3_G3_B2, "R3G3B2"),
+];
+
+/// `VkFormat` values and the layout they store.
+const VK_FORMATS: [(u32, &str); 11] = [
+    (1, "R4G4"),              // VK_FORMAT_R4G4_UNORM_PACK8
+    (2, "R4G4B4A4"),          // VK_FORMAT_R4G4B4A4_UNORM_PACK16
+    (3, "B4G4R4A4"),          // VK_FORMAT_B4G4R4A4_UNORM_PACK16
+    (4, "R5G6B5"),            // VK_FORMAT_R5G6B5_UNORM_PACK16
+    (5, "B5G6R5"),            // VK_FORMAT_B5G6R5_UNORM_PACK16
+    (6, "R5G5B5A1"),          // VK_FORMAT_R5G5B5A1_UNORM_PACK16
+    (7, "B5G5R5A1"),          // VK_FORMAT_B5G5R5A1_UNORM_PACK16
+    (8, "A1R5G5B5"),          // VK_FORMAT_A1R5G5B5_UNORM_PACK16
+    (1000340000, "A4R4G4B4"), // VK_FORMAT_A4R4G4B4_UNORM_PACK16
+    (1000340001, "A4B4G4R4"), // VK_FORMAT_A4B4G4R4_UNORM_PACK16
+    (1000470000, "A1B5G5R5"), // VK_FORMAT_A1B5G5R5_UNORM_PACK16
+];
+
+// Data Format Descriptor constants from the Khronos Data Format specification.
+const KHR_DF_VERSION: u32 = 2;
+const KHR_DF_MODEL_RGBSDA: u32 = 1;
+const KHR_DF_PRIMARIES_BT709: u32 = 1;
+const KHR_DF_TRANSFER_LINEAR: u32 = 1;
+const KHR_DF_CHANNEL_RED: u32 = 0;
+const KHR_DF_CHANNEL_GREEN: u32 = 1;
+const KHR_DF_CHANNEL_BLUE: u32 = 2;
+const KHR_DF_CHANNEL_ALPHA: u32 = 15;
+
+fn spec_layout(spec: &str) -> Layout {
+    Layout::parse_spec(spec).expect("built-in layout specs are valid")
+}
+
+/// Whether `layout` stores the same bits as `spec`, whatever its alpha mode.
+fn matches(layout: &Layout, spec: &str) -> bool {
+    Layout {
+        alpha_mode: AlphaMode::default(),
+        ..*layout
+    } == spec_layout(spec)
+}
+
+fn unsupported(format: &'static str, layout: &Layout) -> Error {
+    Error::InvalidFile {
+        format,
+        reason: format!("no {format} format stores {layout}"),
+    }
+}
+
+/// Writes a single-level KTX 1 file for one of the packed OpenGL formats.
+pub fn encode_ktx(layout: &Layout, width: u32, height: u32, packed: &[u32]) -> Result<Vec<u8>> {
+    let &(gl_type, gl_format, internal, _) = GL_FORMATS
+        .iter()
+        .find(|(.., spec)| matches(layout, spec))
+        .ok_or_else(|| unsupported("KTX", layout))?;
+    let base = if gl_format == GL_RGB { GL_RGB } else { GL_RGBA };
+    // KTX 1 rows follow GL_UNPACK_ALIGNMENT 4.
+    let raw = RawFormat {
+        align: 4,
+        ..RawFormat::default()
+    };
+    let data = raw.encode(layout, width, packed)?;
+
+    let mut out = KTX1_IDENTIFIER.to_vec();
+    let mut put = |v: u32| out.extend_from_slice(&v.to_le_bytes());
+    put(KTX1_ENDIANNESS);
+    put(gl_type);
+    put(layout.bits / 8); // glTypeSize
+    put(gl_format);
+    put(internal);
+    put(base);
+    put(width);
+    put(height);
+    put(0); // pixelDepth
+    put(0); // numberOfArrayElements
+    put(1); // numberOfFaces
+    put(1); // numberOfMipmapLevels
+    put(0); // bytesOfKeyValueData
+    put(data.len() as u32);
+    out.extend(data);
+    Ok(out)
+}
+
+/// Reads the first level of a KTX 1 file in one of the packed OpenGL formats,
+/// returning its layout, width, height and row-major values.
+pub fn decode_ktx(bytes: &[u8]) -> Result<(Layout, u32, u32, Vec<u32>)> {
+    let invalid = |reason: &str| Error::InvalidFile {
+        format: "KTX",
+        reason: reason.to_string(),
+    };
+    if bytes.len() < 68 || bytes[..12] != KTX1_IDENTIFIER {
+        return Err(invalid("missing KTX 1 header"));
+    }
+    let endian = match u32::from_le_bytes(bytes[12..16].try_into().unwrap()) {
+        KTX1_ENDIANNESS => Endian::Little,
+        0x0102_0304 => Endian::Big,
+        _ => return Err(invalid("bad endianness marker")),
+    };
+    let word = |i: usize| {
+        let b: [u8; 4] = bytes[i..i + 4].try_into().unwrap();
+        match endian {
+            Endian::Little => u32::from_le_bytes(b),
+            Endian::Big => u32::from_be_bytes(b),
+        }
+    };
+    let (gl_type, gl_format) = (word(16), word(24));
+    let (width, height, kv_bytes) = (word(36), word(40), word(60) as usize);
+    let (.., spec) = GL_FORMATS
+        .iter()
+        .find(|&&(t, f, ..)| t == gl_type && f == gl_format)
+        .ok_or_else(|| invalid("unsupported OpenGL format"))?;
+    let layout = spec_layout(spec);
+    let raw = RawFormat {
+        endian,
+        offset: 64 + kv_bytes + 4,
+        stride: None,
+        align: 4,
+    };
+    let packed = raw.decode(&layout, width, height.max(1), bytes)?;
+    Ok((layout, width, height.max(1), packed))
+}
+
+/// Writes a single-level KTX 2 file with the matching `VkFormat` and a
+/// basic Data Format Descriptor.
+pub fn encode_ktx2(layout: &Layout, width: u32, height: u32, packed: &[u32]) -> Result<Vec<u8>> {
+    let &(vk_format, _) = VK_FORMATS
+        .iter()
+        .find(|(_, spec)| matches(layout, spec))
+        .ok_or_else(|| unsupported("KTX2", layout))?;
+    let bytes_per_pixel = layout.bits / 8;
+    let data = RawFormat::default().encode(layout, width, packed)?;
+    let dfd = data_format_descriptor(layout);
+
+    const LEVEL_INDEX_OFFSET: usize = 80;
+    let dfd_offset = LEVEL_INDEX_OFFSET + 24;
+    let data_offset = (dfd_offset + dfd.len()).next_multiple_of(4);
+
+    let mut out = KTX2_IDENTIFIER.to_vec();
+    let put = |out: &mut Vec<u8>, v: u32| out.extend_from_slice(&v.to_le_bytes());
+    let put64 = |out: &mut Vec<u8>, v: u64| out.extend_from_slice(&v.to_le_bytes());
+    put(&mut out, vk_format);
+    put(&mut out, bytes_per_pixel); // typeSize
+    put(&mut out, width);
+    put(&mut out, height);
+    put(&mut out, 0); // pixelDepth
+    put(&mut out, 0); // layerCount
+    put(&mut out, 1); // faceCount
+    put(&mut out, 1); // levelCount
+    put(&mut out, 0); // supercompressionScheme
+    put(&mut out, dfd_offset as u32);
+    put(&mut out, dfd.len() as u32);
+    put(&mut out, 0); // kvdByteOffset
+    put(&mut out, 0); // kvdByteLength
+    put64(&mut out, 0); // sgdByteOffset
+    put64(&mut out, 0); // sgdByteLength
+    put64(&mut out, data_offset as u64);
+    put64(&mut out, data.len() as u64);
+    put64(&mut out, data.len() as u64);
+    out.extend(dfd);
+    out.resize(data_offset, 0);
+    out.extend(data);
+    Ok(out)
+}
+
+/// Reads level 0 of a KTX 2 file in one of the packed `VkFormat`s, returning
+/// its layout, width, height and row-major values.
+pub fn decode_ktx2(bytes: &[u8]) -> Result<(Layout, u32, u32, Vec<u32>)> {
+    let invalid = |reason: &str| Error::InvalidFile {
+        format: "KTX2",
+        reason: reason.to_string(),
+    };
+    if bytes.len() < 104 || bytes[..12] != KTX2_IDENTIFIER {
+        return Err(invalid("missing KTX 2 header"));
+    }
+    let word = |i: usize| u32::from_le_bytes(bytes[i..i + 4].try_into().unwrap());
+    let (vk_format, width, height, scheme) = (word(12), word(20), word(24), word(44));
+    if scheme != 0 {
+        return Err(invalid("supercompressed files are not supported"));
+    }
+    let (_, spec) = VK_FORMATS
+        .iter()
+        .find(|&&(vk, _)| vk == vk_format)
+        .ok_or_else(|| invalid("unsupported VkFormat"))?;
+    let layout = spec_layout(spec);
+    let offset = u64::from_le_bytes(bytes[80..88].try_into().unwrap()) as usize;
+    let raw = RawFormat {
+        offset,
+        ..RawFormat::default()
+    };
+    let packed = raw.decode(&layout, width, height.max(1), bytes)?;
+    Ok((layout, width, height.max(1), packed))
+}
+
+/// A basic Data Format Descriptor with one sample per channel, in bit order.
+fn data_format_descriptor(layout: &Layout) -> Vec<u8> {
+    let mut samples: Vec<_> = [
+        (layout.r, KHR_DF_CHANNEL_RED),
+        (layout.g, KHR_DF_CHANNEL_GREEN),
+        (layout.b, KHR_DF_CHANNEL_BLUE),
+        (layout.a, KHR_DF_CHANNEL_ALPHA),
+    ]
+    .into_iter()
+    .filter_map(|(ch, id)| ch.map(|ch| (ch, id)))
+    .collect();
+    samples.sort_by_key(|(ch, _)| ch.shift);
+
+    let block_size = 24 + 16 * samples.len() as u32;
+    let mut words = vec![
+        4 + block_size,
+        0, // vendorId and descriptorType
+        KHR_DF_VERSION | block_size << 16,
+        KHR_DF_MODEL_RGBSDA | KHR_DF_PRIMARIES_BT709 << 8 | KHR_DF_TRANSFER_LINEAR << 16,
+        0,               // texel block dimensions, all 1
+        layout.bits / 8, // bytesPlane0
+        0,
+    ];
+    for (ch, id) in samples {
+        words.push(ch.shift | (ch.bits - 1) << 16 | id << 24);
+        words.push(0); // sample position
+        words.push(0); // sampleLower
+        words.push((1 << ch.bits) - 1); // sampleUpper
+    }
+    words.iter().flat_map(|w| w.to_le_bytes()).collect()
+}
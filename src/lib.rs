@@ -0,0 +1,1248 @@
+//! Packs 8-bit RGBA images into integer pixel formats such as ARGB 1555 and back.
+//!
+//! Formats are described by a [`Layout`], either one of the named
+//! [`PixelFormat`]s or a spec string like `R10G10B10A2` parsed at runtime.
+//!
+//! The functions come in three flavours: per-pixel ([`pack`], [`unpack`]),
+//! in-memory images and slices ([`pack_rgba`], [`unpack_to_rgba`], ...) and
+//! file helpers ([`pack_image`], [`unpack_image`]) used by the CLI.
+
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, Cursor, Read, Write};
+use std::path::{Path, PathBuf};
+
+use image::{DynamicImage, GrayImage, ImageBuffer, ImageFormat, ImageReader, Luma, RgbaImage};
+
+mod atlas;
+mod batch;
+mod bmp;
+mod carrier;
+mod dds;
+mod diff;
+mod dither;
+mod error;
+mod format;
+mod inspect;
+mod ktx;
+mod mipmap;
+mod palette;
+mod raw;
+mod sheet;
+mod slice;
+mod source;
+mod swizzle;
+mod tga;
+mod tiles;
+mod verify;
+
+pub use atlas::{
+    Atlas, AtlasOptions, Packer, Placement, Rotation, Sprite, build_atlas, page_path, trim_bounds,
+};
+pub use batch::{
+    BatchInput, BatchOptions, BatchReport, expand_inputs, is_pattern, output_path, run_batch,
+};
+pub use bmp::{decode as decode_bmp, encode as encode_bmp};
+pub use carrier::Carrier;
+pub use dds::{decode as decode_dds, encode as encode_dds};
+pub use diff::{alpha_mismatch, heatmap, side_by_side};
+pub use dither::{Dither, Kernel, ThresholdMap};
+pub use error::{Error, Result};
+pub use format::{AlphaMode, AlphaPolarity, Channel, Container, Layout, PixelFormat};
+pub use inspect::{ChannelRange, Field, Inspection, fields, inspect};
+pub use ktx::{decode_ktx, decode_ktx2, encode_ktx, encode_ktx2};
+pub use mipmap::{MipFilter, MipOptions, chain_layout, mip_levels, mip_sizes};
+pub use palette::{
+    Indexed, PaletteOptions, Quantizer, palette_path, quantize_indexed, read_indices,
+};
+pub use raw::{Endian, RawFormat};
+pub use sheet::{SheetFormat, SheetOptions, render as render_sheet, sheet_path};
+pub use slice::{
+    FrameSource, Grid, SheetFrames, SliceOptions, detect_format as detect_sheet_format,
+    extract_frame, frame_path, grid_frames, parse_sheet, relayout,
+};
+pub use source::{
+    Language, SourceOptions, render as render_source, render_indexed as render_indexed_source,
+    render_tiled as render_tiled_source, sanitize_symbol,
+};
+pub use swizzle::Swizzle;
+pub use tga::{decode as decode_tga, encode as encode_tga, encode_indexed as encode_indexed_tga};
+pub use tiles::{
+    Flip, TILE_SIZE, TileFormat, TileOptions, Tiled, decode_map, encode_map, make_tiles,
+    tilemap_path, untile,
+};
+pub use verify::{CHANNEL_NAMES, ChannelError, Metrics, Thresholds, compare};
+
+/// A 16-bit greyscale image whose samples hold packed pixels.
+pub type PackedImage = ImageBuffer<Luma<u16>, Vec<u16>>;
+
+/// Settings for packing an image.
+#[derive(Debug, Clone, Default)]
+pub struct PackOptions {
+    /// How the output is stored; `None` picks it from the file extension.
+    pub carrier: Option<Carrier>,
+    /// Byte layout used by [`Carrier::Raw`].
+    pub raw: RawFormat,
+    /// Settings used by [`Carrier::Source`].
+    pub source: SourceOptions,
+    /// Dithering applied to the colour channels before quantization.
+    pub dither: Dither,
+    /// Dithering applied to the alpha channel, turning partial coverage into
+    /// a stippled pattern for 1-bit alpha.
+    pub alpha_dither: Dither,
+    /// Alternate the scan direction on every row during error diffusion.
+    pub serpentine: bool,
+    /// Keep pixels that pack as fully transparent out of error diffusion, so
+    /// they neither receive nor spread error.
+    pub protect_transparent: bool,
+    /// Run-length encode [`Carrier::Tga`] output.
+    pub rle: bool,
+    /// Extension naming the output format, such as `png` or `dds`, used
+    /// instead of the output file's. Required when writing to standard output.
+    pub output_format: Option<String>,
+    /// Quantize to a palette and write indices plus palette instead of
+    /// packed pixels.
+    pub palette: Option<PaletteOptions>,
+    /// Where image and raw carriers write the palette; defaults to
+    /// [`palette_path`] of the output.
+    pub palette_file: Option<PathBuf>,
+    /// Write 8x8 tiles, combined with [`PackOptions::palette`] for indexed
+    /// tiles, instead of scanlines.
+    pub tiles: Option<TileOptions>,
+    /// Where image and raw carriers write the tilemap; defaults to
+    /// [`tilemap_path`] of the output.
+    pub tilemap_file: Option<PathBuf>,
+    /// Reorder the packed pixels into a GPU texture layout. The output then
+    /// holds the padded swizzled buffer, see [`Swizzle::padded_size`].
+    pub swizzle: Option<Swizzle>,
+    /// Write a mipmap chain, each level quantized on its own, instead of
+    /// the image alone.
+    pub mipmaps: Option<MipOptions>,
+}
+
+/// Settings for unpacking an image.
+#[derive(Debug, Clone, Default)]
+pub struct UnpackOptions {
+    /// How the input is stored; `None` picks it from the file extension, or
+    /// from the data itself for standard input.
+    pub carrier: Option<Carrier>,
+    /// Byte layout used by [`Carrier::Raw`].
+    pub raw: RawFormat,
+    /// Image width, required for headerless input.
+    pub width: Option<u32>,
+    /// Image height, required for headerless input.
+    pub height: Option<u32>,
+    /// Extension naming the output image format, such as `png`, used
+    /// instead of the output file's. Required when writing to standard output.
+    pub output_format: Option<String>,
+    /// Bits per palette index of image and raw input holding indices rather
+    /// than packed pixels; `None` reads packed pixels.
+    pub indexed: Option<u32>,
+    /// Palette of indexed input; defaults to [`palette_path`] of the input.
+    pub palette_file: Option<PathBuf>,
+    /// Tile format of image and raw input holding a tileset rather than
+    /// scanlines; combined with [`UnpackOptions::indexed`] for indexed tiles.
+    pub tiles: Option<TileFormat>,
+    /// Tilemap of tiled input; defaults to [`tilemap_path`] of the input.
+    pub tilemap_file: Option<PathBuf>,
+    /// Texture layout the packed pixels were swizzled into. The width and
+    /// height then give the image size before padding; without them the
+    /// whole padded buffer is unswizzled.
+    pub swizzle: Option<Swizzle>,
+}
+
+/// Packed pixels read by [`read_packed`].
+#[derive(Debug, Clone)]
+pub struct PackedFile {
+    /// How the pixels were stored.
+    pub carrier: Carrier,
+    /// The layout stored in the file for self-describing carriers, otherwise
+    /// the one the file was read with.
+    pub layout: Layout,
+    pub width: u32,
+    pub height: u32,
+    /// Row-major packed values.
+    pub values: Vec<u32>,
+}
+
+/// Images written by [`diff_image`]; each is skipped when `None`.
+#[derive(Debug, Clone, Default)]
+pub struct DiffOptions {
+    /// Per-pixel colour error, see [`heatmap`].
+    pub heatmap: Option<PathBuf>,
+    /// Error mapped to the hottest heatmap colour; `None` uses the largest.
+    pub heatmap_max: Option<u8>,
+    /// Pixels whose alpha changed, see [`alpha_mismatch`].
+    pub alpha_mask: Option<PathBuf>,
+    /// Original, round trip and heatmap side by side.
+    pub strip: Option<PathBuf>,
+}
+
+/// Expands a 16-bit ARGB 1555 value to 8-bit `(r, g, b, a)`.
+pub fn unpack(argb_1555: u16) -> (u8, u8, u8, u8) {
+    // Extract individual components from the 16-bit value
+    let a = if (argb_1555 >> 15) & 1 == 1 { 255 } else { 0 };
+    let r5 = (argb_1555 >> 10) & 0x1F;
+    let g5 = (argb_1555 >> 5) & 0x1F;
+    let b5 = argb_1555 & 0x1F;
+
+    // Convert 5-bit color values to 8-bit by scaling
+    let r = (r5 as u32 * 255 + 15) / 31;
+    let g = (g5 as u32 * 255 + 15) / 31;
+    let b = (b5 as u32 * 255 + 15) / 31;
+    (r as u8, g as u8, b as u8, a)
+}
+
+/// Quantizes 8-bit RGB and an opacity flag to a 16-bit ARGB 1555 value.
+pub fn pack(r: u8, g: u8, b: u8, a: bool) -> u16 {
+    let a_bit = if a { 1 } else { 0 };
+    let r5 = (r as u16 * 31 + 127) / 255;
+    let g5 = (g as u16 * 31 + 127) / 255;
+    let b5 = (b as u16 * 31 + 127) / 255;
+    (a_bit << 15) | (r5 << 10) | (g5 << 5) | b5
+}
+
+/// Packs interleaved RGBA8 bytes into `out`, one value per pixel.
+pub fn pack_slice<T: Container>(layout: &Layout, rgba: &[u8], out: &mut [T]) -> Result<()> {
+    check_container::<T>(layout)?;
+    check_len(out.len() * 4, rgba.len())?;
+    for (px, dst) in rgba.chunks_exact(4).zip(out.iter_mut()) {
+        *dst = T::from_packed(layout.pack([px[0], px[1], px[2], px[3]]));
+    }
+    Ok(())
+}
+
+/// Unpacks `packed` into interleaved RGBA8 bytes in `out`.
+pub fn unpack_slice<T: Container>(layout: &Layout, packed: &[T], out: &mut [u8]) -> Result<()> {
+    check_container::<T>(layout)?;
+    check_len(packed.len() * 4, out.len())?;
+    for (&val, dst) in packed.iter().zip(out.chunks_exact_mut(4)) {
+        dst.copy_from_slice(&layout.unpack(val.to_packed()));
+    }
+    Ok(())
+}
+
+/// Packs an RGBA image into a row-major buffer.
+pub fn pack_rgba<T: Container>(
+    layout: &Layout,
+    img: &RgbaImage,
+    options: &PackOptions,
+) -> Result<Vec<T>> {
+    let mut packed = vec![T::default(); (img.width() * img.height()) as usize];
+    if options.dither == Dither::None && options.alpha_dither == Dither::None {
+        pack_slice(layout, img.as_raw(), &mut packed)?;
+    } else {
+        let mut dithered = img.clone();
+        dither::dither_image(layout, &mut dithered, options);
+        pack_slice(layout, dithered.as_raw(), &mut packed)?;
+    }
+    Ok(packed)
+}
+
+/// Packs an RGBA image and unpacks it again, showing what packing keeps.
+pub fn round_trip(layout: &Layout, img: &RgbaImage, options: &PackOptions) -> Result<RgbaImage> {
+    let packed: Vec<u32> = match &options.palette {
+        Some(palette) => quantize_indexed(layout, img, options, palette)?.resolve()?,
+        None => pack_rgba(layout, img, options)?,
+    };
+    unpack_to_rgba(layout, img.width(), img.height(), &packed)
+}
+
+/// Packs any image, converting it to RGBA8 first.
+pub fn pack_dynamic<T: Container>(
+    layout: &Layout,
+    img: &DynamicImage,
+    options: &PackOptions,
+) -> Result<Vec<T>> {
+    match img {
+        DynamicImage::ImageRgba8(rgba) => pack_rgba(layout, rgba, options),
+        other => pack_rgba(layout, &other.to_rgba8(), options),
+    }
+}
+
+/// Packs any image with a 16-bit layout into a 16-bit greyscale image.
+pub fn pack_to_luma16(
+    layout: &Layout,
+    img: &DynamicImage,
+    options: &PackOptions,
+) -> Result<PackedImage> {
+    let packed = pack_dynamic(layout, img, options)?;
+    Ok(PackedImage::from_vec(img.width(), img.height(), packed)
+        .expect("buffer sized from image dimensions"))
+}
+
+/// Packs any image into the image type that carries the layout's container:
+/// 8-bit and 16-bit greyscale, or RGBA8 holding big-endian bytes for 32-bit
+/// containers.
+pub fn pack_to_dynamic(
+    layout: &Layout,
+    img: &DynamicImage,
+    options: &PackOptions,
+) -> Result<DynamicImage> {
+    let packed: Vec<u32> = pack_dynamic(layout, img, options)?;
+    Ok(values_to_dynamic(
+        layout,
+        img.width(),
+        img.height(),
+        &packed,
+    ))
+}
+
+/// Stores packed values in the image type that carries the layout's
+/// container, as [`pack_to_dynamic`] does.
+fn values_to_dynamic(layout: &Layout, width: u32, height: u32, values: &[u32]) -> DynamicImage {
+    match layout.bits {
+        8 => DynamicImage::ImageLuma8(
+            GrayImage::from_vec(width, height, values.iter().map(|&v| v as u8).collect())
+                .expect("buffer sized from image dimensions"),
+        ),
+        16 => DynamicImage::ImageLuma16(
+            PackedImage::from_vec(width, height, values.iter().map(|&v| v as u16).collect())
+                .expect("buffer sized from image dimensions"),
+        ),
+        _ => {
+            let bytes = values.iter().flat_map(|v| v.to_be_bytes()).collect();
+            DynamicImage::ImageRgba8(
+                RgbaImage::from_vec(width, height, bytes)
+                    .expect("buffer sized from image dimensions"),
+            )
+        }
+    }
+}
+
+/// Unpacks a row-major buffer of `width * height` values to an RGBA image.
+pub fn unpack_to_rgba<T: Container>(
+    layout: &Layout,
+    width: u32,
+    height: u32,
+    packed: &[T],
+) -> Result<RgbaImage> {
+    check_len(width as usize * height as usize, packed.len())?;
+    let mut rgba = vec![0u8; packed.len() * 4];
+    unpack_slice(layout, packed, &mut rgba)?;
+    Ok(RgbaImage::from_vec(width, height, rgba).expect("buffer sized from image dimensions"))
+}
+
+/// Unpacks a 16-bit greyscale image to RGBA.
+pub fn unpack_luma16(layout: &Layout, img: &PackedImage) -> Result<RgbaImage> {
+    unpack_to_rgba(layout, img.width(), img.height(), img.as_raw())
+}
+
+/// Unpacks an image produced by [`pack_to_dynamic`] for the same layout.
+pub fn unpack_dynamic(layout: &Layout, img: &DynamicImage) -> Result<RgbaImage> {
+    unpack_to_rgba(
+        layout,
+        img.width(),
+        img.height(),
+        &dynamic_values(layout, img)?,
+    )
+}
+
+/// Reads the packed values out of an image produced by [`pack_to_dynamic`].
+fn dynamic_values(layout: &Layout, img: &DynamicImage) -> Result<Vec<u32>> {
+    let values = match (layout.bits, img) {
+        (8, DynamicImage::ImageLuma8(luma)) => luma.iter().map(|&v| v as u32).collect(),
+        (16, DynamicImage::ImageLuma16(luma)) => luma.iter().map(|&v| v as u32).collect(),
+        (32, DynamicImage::ImageRgba8(rgba)) => rgba
+            .as_raw()
+            .chunks_exact(4)
+            .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+            .collect(),
+        (_, other) => return Err(Error::UnsupportedColorType(other.color())),
+    };
+    Ok(values)
+}
+
+/// Packs the image at `input_file` and saves it to `output_file`.
+///
+/// Either path may be `-` for standard input or output. Input formats are
+/// detected from the data; the output format comes from
+/// [`PackOptions::output_format`] or the output file's extension.
+pub fn pack_image(
+    input_file: &Path,
+    output_file: &Path,
+    layout: &Layout,
+    options: &PackOptions,
+) -> Result<()> {
+    let img = decode_image(input_file, &read_input(input_file)?)?;
+    write_packed(&img, output_file, layout, options)
+}
+
+/// Packs an image in memory and writes it like [`pack_image`].
+fn write_packed(
+    img: &DynamicImage,
+    output_file: &Path,
+    layout: &Layout,
+    options: &PackOptions,
+) -> Result<()> {
+    if let Some(mipmaps) = &options.mipmaps {
+        return write_mipmaps(img, output_file, layout, options, mipmaps);
+    }
+    let carrier = output_carrier(output_file, options);
+    if options.swizzle.is_some() && (options.tiles.is_some() || options.palette.is_some()) {
+        return Err(Error::SwizzleConflict);
+    }
+    if let Some(tiles) = &options.tiles {
+        return write_tiled(
+            &img.to_rgba8(),
+            output_file,
+            carrier,
+            layout,
+            options,
+            tiles,
+        );
+    }
+    if let Some(palette) = &options.palette {
+        let indexed = quantize_indexed(layout, &img.to_rgba8(), options, palette)?;
+        return write_indexed(&indexed, output_file, carrier, layout, options);
+    }
+
+    let packed: Vec<u32> = pack_dynamic(layout, img, options)?;
+    write_values(
+        img.width(),
+        img.height(),
+        packed,
+        output_file,
+        carrier,
+        layout,
+        options,
+    )
+}
+
+/// The carrier of output written to `output_file`.
+fn output_carrier(output_file: &Path, options: &PackOptions) -> Carrier {
+    let extension = output_extension(output_file, options.output_format.as_deref());
+    options
+        .carrier
+        .unwrap_or_else(|| Carrier::from_extension(extension.unwrap_or_default()))
+}
+
+/// Swizzles packed values if asked to and writes them with `carrier`.
+fn write_values(
+    width: u32,
+    height: u32,
+    packed: Vec<u32>,
+    output_file: &Path,
+    carrier: Carrier,
+    layout: &Layout,
+    options: &PackOptions,
+) -> Result<()> {
+    let (width, height, packed) = match options.swizzle {
+        Some(swizzle) => swizzle.swizzle(layout.bits, width, height, &packed)?,
+        None => (width, height, packed),
+    };
+    if carrier == Carrier::Image {
+        let packed = values_to_dynamic(layout, width, height, &packed);
+        return save_image(&packed, output_file, options.output_format.as_deref());
+    }
+    let bytes = match carrier {
+        Carrier::Image => unreachable!("image carrier handled above"),
+        Carrier::Raw => options.raw.encode(layout, width, &packed)?,
+        Carrier::Source(language) => render_source(
+            language,
+            &source_symbol(output_file, options),
+            layout,
+            width,
+            height,
+            &packed,
+            &options.source,
+        )
+        .into_bytes(),
+        Carrier::Dds => dds::encode(layout, width, height, &packed)?,
+        Carrier::Ktx => encode_ktx(layout, width, height, &packed)?,
+        Carrier::Ktx2 => encode_ktx2(layout, width, height, &packed)?,
+        Carrier::Tga => tga::encode(layout, width, height, &packed, options.rle)?,
+        Carrier::Bmp => bmp::encode(layout, width, height, &packed)?,
+    };
+    write_output(output_file, &bytes)
+}
+
+/// Makes the mipmap levels of `img` and packs each one on its own: to the
+/// [`page_path`]s of `output_file`, or into a single image laid out by
+/// [`chain_layout`].
+fn write_mipmaps(
+    img: &DynamicImage,
+    output_file: &Path,
+    layout: &Layout,
+    options: &PackOptions,
+    mipmaps: &MipOptions,
+) -> Result<()> {
+    // Only a 1-bit alpha channel is alpha-tested.
+    let alpha_test = layout
+        .a
+        .filter(|ch| ch.bits == 1)
+        .map(|_| layout.alpha_mode);
+    let levels = mip_levels(img, alpha_test, mipmaps);
+    let options = PackOptions {
+        mipmaps: None,
+        ..options.clone()
+    };
+    if !mipmaps.chain {
+        let count = levels.len();
+        if count > 1 && is_stdio(output_file) {
+            return Err(Error::MipLevels(count));
+        }
+        for (i, level) in levels.into_iter().enumerate() {
+            write_packed(
+                &level.into(),
+                &page_path(output_file, i, count),
+                layout,
+                &options,
+            )?;
+        }
+        return Ok(());
+    }
+
+    if options.palette.is_some() || options.tiles.is_some() {
+        return Err(Error::MipChainConflict);
+    }
+    let sizes: Vec<(u32, u32)> = levels.iter().map(|level| level.dimensions()).collect();
+    let (width, height, offsets) = chain_layout(&sizes);
+    let mut chain = vec![0; (width * height) as usize];
+    for (level, (x, y)) in levels.iter().zip(offsets) {
+        let packed: Vec<u32> = pack_rgba(layout, level, &options)?;
+        for (row, values) in packed.chunks(level.width() as usize).enumerate() {
+            let start = ((y + row as u32) * width + x) as usize;
+            chain[start..start + values.len()].copy_from_slice(values);
+        }
+    }
+    let carrier = output_carrier(output_file, &options);
+    write_values(width, height, chain, output_file, carrier, layout, &options)
+}
+
+/// Writes a paletted image. TGA and source carriers hold the palette
+/// themselves; image and raw carriers write it to its own file.
+fn write_indexed(
+    indexed: &Indexed,
+    output_file: &Path,
+    carrier: Carrier,
+    layout: &Layout,
+    options: &PackOptions,
+) -> Result<()> {
+    let bytes = match carrier {
+        Carrier::Tga => tga::encode_indexed(layout, indexed, options.rle)?,
+        Carrier::Source(language) => render_indexed_source(
+            language,
+            &source_symbol(output_file, options),
+            layout,
+            indexed,
+            &options.source,
+        )
+        .into_bytes(),
+        Carrier::Image | Carrier::Raw => {
+            write_palette(&indexed.palette, output_file, carrier, layout, options)?;
+            if carrier == Carrier::Image {
+                let indices =
+                    GrayImage::from_vec(indexed.width, indexed.height, indexed.indices.clone())
+                        .expect("buffer sized from image dimensions");
+                return save_image(
+                    &indices.into(),
+                    output_file,
+                    options.output_format.as_deref(),
+                );
+            }
+            indexed.index_bytes()
+        }
+        carrier => return Err(Error::IndexedCarrier(carrier)),
+    };
+    write_output(output_file, &bytes)
+}
+
+/// Writes the palette of image or raw output to its own file, as a one-row
+/// image or raw entries.
+fn write_palette(
+    palette: &[u32],
+    output_file: &Path,
+    carrier: Carrier,
+    layout: &Layout,
+    options: &PackOptions,
+) -> Result<()> {
+    let palette_file = match &options.palette_file {
+        Some(path) => path.clone(),
+        None if is_stdio(output_file) => return Err(Error::MissingPalette),
+        None => palette_path(output_file),
+    };
+    let entries = palette.len() as u32;
+    if carrier == Carrier::Image {
+        let palette = values_to_dynamic(layout, entries, 1, palette);
+        return save_image(&palette, &palette_file, None);
+    }
+    let raw = RawFormat {
+        endian: options.raw.endian,
+        ..RawFormat::default()
+    };
+    write_output(&palette_file, &raw.encode(layout, entries, palette)?)
+}
+
+/// Writes an image as 8x8 tiles plus a tilemap. Source carriers hold both;
+/// image and raw carriers write the tilemap, and the palette of indexed
+/// tiles, to their own files. Image tilesets stack the tiles in a column.
+fn write_tiled(
+    img: &RgbaImage,
+    output_file: &Path,
+    carrier: Carrier,
+    layout: &Layout,
+    options: &PackOptions,
+    tile_options: &TileOptions,
+) -> Result<()> {
+    let (values, indexed) = match &options.palette {
+        Some(palette) => {
+            let indexed = quantize_indexed(layout, img, options, palette)?;
+            let indices = indexed.indices.iter().map(|&i| i as u32).collect();
+            (indices, Some(indexed))
+        }
+        None => (pack_rgba(layout, img, options)?, None),
+    };
+    let tiled = make_tiles(&values, img.width(), img.height(), tile_options)?;
+    let format = tile_options.format;
+    let bytes = match carrier {
+        Carrier::Source(language) => render_tiled_source(
+            language,
+            &source_symbol(output_file, options),
+            layout,
+            &tiled,
+            indexed.as_ref(),
+            format,
+            &options.source,
+        )
+        .into_bytes(),
+        Carrier::Image | Carrier::Raw => {
+            let tilemap_file = match &options.tilemap_file {
+                Some(path) => path.clone(),
+                None if is_stdio(output_file) => return Err(Error::MissingTilemap),
+                None => tilemap_path(output_file),
+            };
+            if let Some(indexed) = &indexed {
+                write_palette(&indexed.palette, output_file, carrier, layout, options)?;
+            }
+            let tile_rows = tiled.tile_count() as u32 * TILE_SIZE;
+            if carrier == Carrier::Image {
+                let (columns, rows) = (tiled.width / TILE_SIZE, tiled.height / TILE_SIZE);
+                let map = PackedImage::from_vec(columns, rows, tiled.map.clone())
+                    .expect("buffer sized from tile counts");
+                save_image(&map.into(), &tilemap_file, None)?;
+                let tiles = match indexed {
+                    Some(_) => {
+                        let indices = tiled.tiles.iter().map(|&i| i as u8).collect();
+                        GrayImage::from_vec(TILE_SIZE, tile_rows, indices)
+                            .expect("buffer sized from tile counts")
+                            .into()
+                    }
+                    None => values_to_dynamic(layout, TILE_SIZE, tile_rows, &tiled.tiles),
+                };
+                return save_image(&tiles, output_file, options.output_format.as_deref());
+            }
+            write_output(&tilemap_file, &encode_map(&tiled.map, options.raw.endian))?;
+            match &indexed {
+                Some(indexed) => tiled
+                    .tiles
+                    .chunks(TILE_SIZE as usize * TILE_SIZE as usize)
+                    .flat_map(|tile| format.encode_indices(tile, indexed.index_bits))
+                    .collect(),
+                None => RawFormat {
+                    endian: options.raw.endian,
+                    ..RawFormat::default()
+                }
+                .encode(layout, TILE_SIZE, &tiled.tiles)?,
+            }
+        }
+        carrier => return Err(Error::TiledCarrier(carrier)),
+    };
+    write_output(output_file, &bytes)
+}
+
+/// Symbol of source output: the explicit one, or one made from the output
+/// file name.
+fn source_symbol(output_file: &Path, options: &PackOptions) -> String {
+    match &options.source.symbol {
+        Some(symbol) => symbol.clone(),
+        None if is_stdio(output_file) => "image".to_string(),
+        None => sanitize_symbol(
+            &output_file
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy(),
+        ),
+    }
+}
+
+/// Unpacks the packed image at `input_file` and saves it as RGBA to `output_file`.
+///
+/// Either path may be `-` for standard input or output. The input is read
+/// as by [`read_packed`].
+pub fn unpack_image(
+    input_file: &Path,
+    output_file: &Path,
+    layout: &Layout,
+    options: &UnpackOptions,
+) -> Result<()> {
+    let file = read_packed(input_file, layout, options)?;
+    let rgba = unpack_to_rgba(&file.layout, file.width, file.height, &file.values)?;
+    save_image(&rgba.into(), output_file, options.output_format.as_deref())
+}
+
+/// Unpacks the sprite sheet at `input_file` and writes each frame found by
+/// `slice` as RGBA to its own file under `output`, returning the number of
+/// frames.
+///
+/// Frames from metadata are restored to their source size and orientation
+/// and named as the metadata names them, libGDX animation frames with
+/// their index appended; grid cells are numbered row by row. Frames that
+/// would be written to the same file are an error. When the metadata lists several pages, the frames of the page
+/// named like `input_file` are taken. With [`SliceOptions::relayout`],
+/// `output` is instead a single image, which may be `-` for standard
+/// output.
+pub fn slice_image(
+    input_file: &Path,
+    output: &Path,
+    layout: &Layout,
+    options: &UnpackOptions,
+    slice: &SliceOptions,
+) -> Result<usize> {
+    let file = read_packed(input_file, layout, options)?;
+    let page = unpack_to_rgba(&file.layout, file.width, file.height, &file.values)?;
+    let (frames, rotation) = match &slice.frames {
+        FrameSource::Grid(grid) => (
+            grid_frames(page.width(), page.height(), grid)?,
+            Rotation::default(),
+        ),
+        FrameSource::Sheet(path) => {
+            let text = String::from_utf8_lossy(&read_input(path)?).into_owned();
+            let sheet = parse_sheet(&text, detect_sheet_format(path, &text))?;
+            let name = input_file.file_name().unwrap_or_default();
+            let index = sheet
+                .images
+                .iter()
+                .position(|image| Path::new(image).file_name() == Some(name));
+            let index = match index {
+                Some(index) => index,
+                None if sheet.images.len() <= 1 => 0,
+                None => return Err(Error::SheetPage(name.to_string_lossy().into_owned())),
+            };
+            let frames = sheet
+                .frames
+                .into_iter()
+                .filter(|frame| frame.page == index)
+                .collect();
+            (frames, sheet.rotation)
+        }
+    };
+    let images = frames
+        .iter()
+        .map(|frame| extract_frame(&page, frame, rotation))
+        .collect::<Result<Vec<_>>>()?;
+    if let Some(columns) = slice.relayout {
+        let sheet = relayout(&images, columns);
+        save_image(&sheet.into(), output, options.output_format.as_deref())?;
+        return Ok(frames.len());
+    }
+    if is_stdio(output) {
+        return Err(Error::SliceStdout);
+    }
+    let extension = options.output_format.as_deref().unwrap_or("png");
+    let paths: Vec<PathBuf> = frames
+        .iter()
+        .map(|frame| frame_path(output, &frame.name, extension))
+        .collect();
+    let mut seen = HashSet::new();
+    if let Some((frame, _)) = frames
+        .iter()
+        .zip(&paths)
+        .find(|&(_, path)| !seen.insert(path))
+    {
+        return Err(Error::DuplicateFrame(frame.name.clone()));
+    }
+    for (path, image) in paths.iter().zip(images) {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        save_image(&image.into(), path, None)?;
+    }
+    Ok(frames.len())
+}
+
+/// Reads the packed values of `input_file`, which may be `-` for standard
+/// input, without unpacking them.
+///
+/// Self-describing carriers such as DDS, KTX, TGA and BMP use the layout
+/// stored in the file and only take the alpha mode from `layout`. The
+/// carrier of standard input is detected from its data. With
+/// [`UnpackOptions::indexed`], image and raw input holds palette indices
+/// that are looked up in the palette file; colour-mapped TGA files are
+/// always resolved through their own palette. With
+/// [`UnpackOptions::tiles`], image and raw input holds a tileset that is
+/// laid out by the tilemap file. With [`UnpackOptions::swizzle`], the
+/// values are unswizzled after reading.
+pub fn read_packed(
+    input_file: &Path,
+    layout: &Layout,
+    options: &UnpackOptions,
+) -> Result<PackedFile> {
+    let bytes = read_input(input_file)?;
+    let carrier = options
+        .carrier
+        .unwrap_or_else(|| match is_stdio(input_file) {
+            true => Carrier::detect(&bytes),
+            false => Carrier::from_path(input_file),
+        });
+    let (layout, width, height, values) = match carrier {
+        Carrier::Image | Carrier::Raw if options.tiles.is_some() => {
+            let (width, height, values) = read_tiled(input_file, &bytes, carrier, layout, options)?;
+            (*layout, width, height, values)
+        }
+        Carrier::Image | Carrier::Raw if options.indexed.is_some() => {
+            let (width, height, values) =
+                read_indexed(input_file, &bytes, carrier, layout, options)?;
+            (*layout, width, height, values)
+        }
+        Carrier::Image => {
+            let img = decode_image(input_file, &bytes)?;
+            let values = dynamic_values(layout, &img)?;
+            (*layout, img.width(), img.height(), values)
+        }
+        Carrier::Raw => {
+            let (Some(width), Some(height)) = (options.width, options.height) else {
+                return Err(Error::MissingDimensions);
+            };
+            let (width, height) = match options.swizzle {
+                Some(swizzle) => swizzle.padded_size(layout.bits, width, height)?,
+                None => (width, height),
+            };
+            let values = options.raw.decode(layout, width, height, &bytes)?;
+            (*layout, width, height, values)
+        }
+        carrier @ Carrier::Source(_) => return Err(Error::UnreadableCarrier(carrier)),
+        carrier => {
+            let decode = match carrier {
+                Carrier::Dds => dds::decode,
+                Carrier::Ktx => decode_ktx,
+                Carrier::Ktx2 => decode_ktx2,
+                Carrier::Tga => tga::decode,
+                _ => bmp::decode,
+            };
+            let (stored, width, height, values) = decode(&bytes)?;
+            let stored = Layout {
+                alpha_mode: layout.alpha_mode,
+                ..stored
+            };
+            (stored, width, height, values)
+        }
+    };
+    let (width, height, values) = match options.swizzle {
+        Some(swizzle) => unswizzle(swizzle, &layout, width, height, values, options)?,
+        None => (width, height, values),
+    };
+    Ok(PackedFile {
+        carrier,
+        layout,
+        width,
+        height,
+        values,
+    })
+}
+
+/// Unswizzles a `width` by `height` buffer read from a file, cropping it to
+/// the size in `options` if given.
+fn unswizzle(
+    swizzle: Swizzle,
+    layout: &Layout,
+    width: u32,
+    height: u32,
+    values: Vec<u32>,
+    options: &UnpackOptions,
+) -> Result<(u32, u32, Vec<u32>)> {
+    let (image_width, image_height) = match (options.width, options.height) {
+        (Some(w), Some(h)) => (w, h),
+        _ => (width, height),
+    };
+    let padded = swizzle.padded_size(layout.bits, image_width, image_height)?;
+    if padded != (width, height) {
+        return Err(Error::DimensionMismatch {
+            expected: padded,
+            actual: (width, height),
+        });
+    }
+    let values = swizzle.unswizzle(layout.bits, image_width, image_height, &values)?;
+    Ok((image_width, image_height, values))
+}
+
+/// Reads image or raw palette indices and looks them up in their palette.
+fn read_indexed(
+    input_file: &Path,
+    bytes: &[u8],
+    carrier: Carrier,
+    layout: &Layout,
+    options: &UnpackOptions,
+) -> Result<(u32, u32, Vec<u32>)> {
+    let (width, height, indices) = if carrier == Carrier::Image {
+        let img = decode_image(input_file, bytes)?;
+        let DynamicImage::ImageLuma8(indices) = img else {
+            return Err(Error::UnsupportedColorType(img.color()));
+        };
+        (indices.width(), indices.height(), indices.into_raw())
+    } else {
+        let (Some(width), Some(height)) = (options.width, options.height) else {
+            return Err(Error::MissingDimensions);
+        };
+        let index_bits = options.indexed.unwrap_or(8);
+        let data = bytes.get(options.raw.offset..).unwrap_or_default();
+        (
+            width,
+            height,
+            read_indices(data, index_bits, width, height)?,
+        )
+    };
+    let palette = read_palette(input_file, carrier, layout, options)?;
+    Ok((width, height, palette::resolve(&indices, &palette)?))
+}
+
+/// Reads the palette file of indexed image or raw input.
+fn read_palette(
+    input_file: &Path,
+    carrier: Carrier,
+    layout: &Layout,
+    options: &UnpackOptions,
+) -> Result<Vec<u32>> {
+    let palette_file = match &options.palette_file {
+        Some(path) => path.clone(),
+        None if is_stdio(input_file) => return Err(Error::MissingPalette),
+        None => palette_path(input_file),
+    };
+    let bytes = read_input(&palette_file)?;
+    if carrier == Carrier::Image {
+        return dynamic_values(layout, &decode_image(&palette_file, &bytes)?);
+    }
+    let raw = RawFormat {
+        endian: options.raw.endian,
+        ..RawFormat::default()
+    };
+    let entries = (bytes.len() / (layout.bits as usize / 8)) as u32;
+    raw.decode(layout, entries, 1, &bytes)
+}
+
+/// Reads an image or raw tileset and lays its tiles out by the tilemap,
+/// looking indexed tiles up in their palette.
+fn read_tiled(
+    input_file: &Path,
+    bytes: &[u8],
+    carrier: Carrier,
+    layout: &Layout,
+    options: &UnpackOptions,
+) -> Result<(u32, u32, Vec<u32>)> {
+    let format = options.tiles.unwrap_or_default();
+    let tilemap_file = match &options.tilemap_file {
+        Some(path) => path.clone(),
+        None if is_stdio(input_file) => return Err(Error::MissingTilemap),
+        None => tilemap_path(input_file),
+    };
+    let map_bytes = read_input(&tilemap_file)?;
+    let (width, height, tiles, map) = if carrier == Carrier::Image {
+        let img = decode_image(input_file, bytes)?;
+        let tiles = match (options.indexed, img) {
+            (Some(_), DynamicImage::ImageLuma8(indices)) => {
+                indices.iter().map(|&i| i as u32).collect()
+            }
+            (Some(_), img) => return Err(Error::UnsupportedColorType(img.color())),
+            (None, img) => dynamic_values(layout, &img)?,
+        };
+        let map = match decode_image(&tilemap_file, &map_bytes)? {
+            DynamicImage::ImageLuma16(map) => map,
+            other => return Err(Error::UnsupportedColorType(other.color())),
+        };
+        let (width, height) = (map.width() * TILE_SIZE, map.height() * TILE_SIZE);
+        (width, height, tiles, map.into_raw())
+    } else {
+        let (Some(width), Some(height)) = (options.width, options.height) else {
+            return Err(Error::MissingDimensions);
+        };
+        let data = bytes.get(options.raw.offset..).unwrap_or_default();
+        let tile_pixels = (TILE_SIZE * TILE_SIZE) as usize;
+        let tiles = match options.indexed {
+            Some(bits @ (4 | 8)) => data
+                .chunks_exact(tile_pixels * bits as usize / 8)
+                .flat_map(|tile| format.decode_indices(tile, bits))
+                .collect(),
+            Some(bits) => return Err(Error::InvalidIndexBits(bits)),
+            None => {
+                let count = data.len() / (tile_pixels * layout.bits as usize / 8);
+                let raw = RawFormat {
+                    endian: options.raw.endian,
+                    ..RawFormat::default()
+                };
+                raw.decode(layout, TILE_SIZE, count as u32 * TILE_SIZE, data)?
+            }
+        };
+        (
+            width,
+            height,
+            tiles,
+            decode_map(&map_bytes, options.raw.endian),
+        )
+    };
+
+    let values = untile(&tiles, &map, width, height, format)?;
+    if options.indexed.is_none() {
+        return Ok((width, height, values));
+    }
+    let indices: Vec<u8> = values.iter().map(|&i| i as u8).collect();
+    let palette = read_palette(input_file, carrier, layout, options)?;
+    Ok((width, height, palette::resolve(&indices, &palette)?))
+}
+
+/// Packs and unpacks the image at `input_file`, which may be `-` for
+/// standard input, in memory and measures what was lost.
+pub fn verify_image(input_file: &Path, layout: &Layout, options: &PackOptions) -> Result<Metrics> {
+    let img = decode_image(input_file, &read_input(input_file)?)?.to_rgba8();
+    compare(&img, &round_trip(layout, &img, options)?)
+}
+
+/// Compares the image at `original_file` with its round trip and writes the
+/// images requested in `outputs`, returning the same metrics as
+/// [`verify_image`].
+///
+/// The round trip is read from `unpacked_file`, typically an
+/// [`unpack_image`] result, or made in memory by packing with `layout` and
+/// `options` when it is `None`.
+pub fn diff_image(
+    original_file: &Path,
+    unpacked_file: Option<&Path>,
+    layout: &Layout,
+    options: &PackOptions,
+    outputs: &DiffOptions,
+) -> Result<Metrics> {
+    let original = decode_image(original_file, &read_input(original_file)?)?.to_rgba8();
+    let unpacked = match unpacked_file {
+        Some(path) => decode_image(path, &read_input(path)?)?.to_rgba8(),
+        None => round_trip(layout, &original, options)?,
+    };
+    let metrics = compare(&original, &unpacked)?;
+
+    let heat = DynamicImage::from(heatmap(&original, &unpacked, outputs.heatmap_max)?);
+    if let Some(path) = &outputs.heatmap {
+        save_image(&heat, path, None)?;
+    }
+    if let Some(path) = &outputs.alpha_mask {
+        save_image(&alpha_mismatch(&original, &unpacked)?.into(), path, None)?;
+    }
+    if let Some(path) = &outputs.strip {
+        let strip = side_by_side(&[&original, &unpacked, &heat.to_rgba8()], 4);
+        save_image(&strip.into(), path, None)?;
+    }
+    Ok(metrics)
+}
+
+/// Packs every image found in `inputs`, which may name files, directories
+/// and glob patterns, into `out_dir` as named by `batch.template`.
+///
+/// Only files the `image` crate can read are taken from directories. A file
+/// that fails is recorded in the report and does not stop the others.
+pub fn pack_batch(
+    inputs: &[PathBuf],
+    out_dir: &Path,
+    layout: &Layout,
+    options: &PackOptions,
+    batch: &BatchOptions,
+) -> Result<BatchReport> {
+    let inputs = expand_inputs(inputs, |path| ImageFormat::from_path(path).is_ok())?;
+    run_batch(&inputs, out_dir, batch, |input, output| {
+        pack_image(input, output, layout, options)
+    })
+}
+
+/// Places every image found in `inputs`, expanded like [`pack_batch`], on
+/// atlas pages and packs each page like [`pack_image`].
+///
+/// A single page is written to `output_file`, several to the
+/// [`page_path`]s next to it. Sprites are named by their path below the
+/// directory or pattern they were found under. With `sheet`, frame
+/// metadata is written too, and rotated sprites are turned the way its
+/// format expects whatever [`AtlasOptions::rotation`] says.
+pub fn pack_atlas(
+    inputs: &[PathBuf],
+    output_file: &Path,
+    layout: &Layout,
+    options: &PackOptions,
+    atlas: &AtlasOptions,
+    sheet: Option<&SheetOptions>,
+) -> Result<Atlas> {
+    let inputs = expand_inputs(inputs, |path| ImageFormat::from_path(path).is_ok())?;
+    let sprites = inputs
+        .iter()
+        .map(|input| {
+            let image = decode_image(&input.path, &read_input(&input.path)?)?.to_rgba8();
+            let name = input.dir.join(input.path.file_name().unwrap_or_default());
+            Ok(Sprite {
+                name: name.to_string_lossy().replace('\\', "/"),
+                image,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let atlas = AtlasOptions {
+        rotation: sheet.map_or(atlas.rotation, |sheet| sheet.format.rotation()),
+        ..atlas.clone()
+    };
+    let built = build_atlas(&sprites, &atlas)?;
+    let pages = built.pages.len();
+    if pages > 1 && is_stdio(output_file) {
+        return Err(Error::AtlasPages(pages));
+    }
+    for (i, page) in built.pages.iter().enumerate() {
+        let page_file = page_path(output_file, i, pages);
+        write_packed(&page.clone().into(), &page_file, layout, options)?;
+    }
+    if let Some(sheet) = sheet {
+        write_sheet(&built, output_file, layout, options, sheet)?;
+    }
+    Ok(built)
+}
+
+/// Writes the metadata of an atlas packed to `output_file`, naming the
+/// pages by file name as they sit next to it.
+fn write_sheet(
+    atlas: &Atlas,
+    output_file: &Path,
+    layout: &Layout,
+    options: &PackOptions,
+    sheet: &SheetOptions,
+) -> Result<()> {
+    let sheet_file = match &sheet.file {
+        Some(path) => path.clone(),
+        None if is_stdio(output_file) => return Err(Error::MissingSheetFile),
+        None => sheet_path(output_file, sheet.format),
+    };
+    let pages = atlas.pages.len();
+    let file_name = |path: &Path| {
+        path.file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned()
+    };
+    let images: Vec<String> = match is_stdio(output_file) {
+        // Standard output holds a single page, named after the metadata.
+        true => {
+            let extension = options.output_format.as_deref().unwrap_or_default();
+            vec![file_name(&sheet_file.with_extension(extension))]
+        }
+        false => (0..pages)
+            .map(|page| file_name(&page_path(output_file, page, pages)))
+            .collect(),
+    };
+    let documents = sheet::render(atlas, &images, &file_name(&sheet_file), layout, sheet);
+    let count = documents.len();
+    for (page, document) in documents.iter().enumerate() {
+        write_output(&page_path(&sheet_file, page, count), document.as_bytes())?;
+    }
+    Ok(())
+}
+
+/// Unpacks every packed file found in `inputs` into `out_dir`, like
+/// [`pack_batch`]. Directories contribute the files whose extension names
+/// a readable carrier, leaving out palette and tilemap files of indexed and
+/// tiled input.
+pub fn unpack_batch(
+    inputs: &[PathBuf],
+    out_dir: &Path,
+    layout: &Layout,
+    options: &UnpackOptions,
+    batch: &BatchOptions,
+) -> Result<BatchReport> {
+    let mut companions = Vec::new();
+    if options.indexed.is_some() {
+        companions.push("pal");
+    }
+    if options.tiles.is_some() {
+        companions.push("map");
+    }
+    let readable = |path: &Path| match options.carrier.unwrap_or_else(|| Carrier::from_path(path)) {
+        _ if palette::is_companion_path(path, &companions) => false,
+        Carrier::Image => ImageFormat::from_path(path).is_ok(),
+        Carrier::Source(_) => false,
+        _ => true,
+    };
+    let inputs = expand_inputs(inputs, readable)?;
+    run_batch(&inputs, out_dir, batch, |input, output| {
+        unpack_image(input, output, layout, options)
+    })
+}
+
+/// Whether `path` stands for standard input or output.
+pub fn is_stdio(path: &Path) -> bool {
+    path.as_os_str() == "-"
+}
+
+fn read_input(path: &Path) -> Result<Vec<u8>> {
+    if is_stdio(path) {
+        let mut bytes = Vec::new();
+        io::stdin().lock().read_to_end(&mut bytes)?;
+        Ok(bytes)
+    } else {
+        Ok(fs::read(path)?)
+    }
+}
+
+fn write_output(path: &Path, bytes: &[u8]) -> Result<()> {
+    if is_stdio(path) {
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(bytes)?;
+        stdout.flush()?;
+    } else {
+        fs::write(path, bytes)?;
+    }
+    Ok(())
+}
+
+/// Encodes `img` in the format named by `format` or the path's extension
+/// and writes it to `path`, which may be `-` for standard output.
+fn save_image(img: &DynamicImage, path: &Path, format: Option<&str>) -> Result<()> {
+    let format = image_format(path, output_extension(path, format))?;
+    let mut bytes = Cursor::new(Vec::new());
+    img.write_to(&mut bytes, format)?;
+    write_output(path, &bytes.into_inner())
+}
+
+/// Decodes an image, trusting the file extension when there is one and
+/// sniffing the data otherwise.
+fn decode_image(path: &Path, bytes: &[u8]) -> Result<DynamicImage> {
+    let reader = match ImageFormat::from_path(path) {
+        Ok(format) if !is_stdio(path) => ImageReader::with_format(Cursor::new(bytes), format),
+        _ => ImageReader::new(Cursor::new(bytes)).with_guessed_format()?,
+    };
+    Ok(reader.decode()?)
+}
+
+/// The extension naming the output format: the explicit one if given,
+/// otherwise that of the output file.
+fn output_extension<'a>(path: &'a Path, explicit: Option<&'a str>) -> Option<&'a str> {
+    explicit.or_else(|| match is_stdio(path) {
+        true => None,
+        false => path.extension().and_then(|ext| ext.to_str()),
+    })
+}
+
+fn image_format(path: &Path, extension: Option<&str>) -> Result<ImageFormat> {
+    match extension {
+        Some(ext) => ImageFormat::from_extension(ext)
+            .ok_or_else(|| Error::UnknownImageFormat(ext.to_string())),
+        None if is_stdio(path) => Err(Error::MissingOutputFormat),
+        None => Err(Error::UnknownImageFormat(String::new())),
+    }
+}
+
+fn check_container<T: Container>(layout: &Layout) -> Result<()> {
+    if layout.bits <= T::BITS {
+        Ok(())
+    } else {
+        Err(Error::ContainerTooSmall {
+            layout: layout.bits,
+            container: T::BITS,
+        })
+    }
+}
+
+fn check_len(expected: usize, actual: usize) -> Result<()> {
+    if expected == actual {
+        Ok(())
+    } else {
+        Err(Error::BufferSize { expected, actual })
+    }
+}
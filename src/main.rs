@@ -1,10 +1,14 @@
-use std::path::Path;
-
-use color_eyre::Result;
-use image::{GenericImageView, ImageReader};
-use image::{ImageBuffer, Luma};
-use clap::{Parser};
-use std::path::PathBuf;
+use clap::Parser;
+use color_eyre::{Result, eyre::eyre};
+use image_packer::{
+    AlphaMode, AlphaPolarity, AtlasOptions, BatchOptions, BatchReport, CHANNEL_NAMES, Carrier,
+    DiffOptions, Dither, Endian, FrameSource, Grid, Layout, Metrics, MipFilter, MipOptions,
+    PackOptions, PackedFile, Packer, PaletteOptions, Quantizer, RawFormat, SheetFormat,
+    SheetOptions, SliceOptions, SourceOptions, Swizzle, Thresholds, TileFormat, TileOptions,
+    UnpackOptions, diff_image, fields, inspect, is_pattern, pack_atlas, pack_batch, pack_image,
+    read_packed, slice_image, unpack_batch, unpack_image, verify_image,
+};
+use std::path::{Path, PathBuf};
 
 /// Usage: image_packer <command> [options]
 /// Example: image_packer pack input.png output.png
@@ -12,87 +16,854 @@ use std::path::PathBuf;
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 pub enum Args {
-    /// Pack an image to 16-bit ARGB 1555 format.
+    /// Pack an image to a 16-bit format, ARGB 1555 by default.
+    // The atlas command shares AtlasArgs without needing --sheet.
+    #[command(mut_group("AtlasArgs", |group| group.requires("sheet")))]
     Pack {
-        /// Input file path
-        input: PathBuf,
-        /// Output file path
+        /// Input file path, or - for standard input; several files, directories or glob
+        /// patterns pack into the output directory
+        #[arg(required = true)]
+        input: Vec<PathBuf>,
+        /// Output file path, - for standard output, or output directory for several inputs
+        output: PathBuf,
+        /// Output format as a file extension, such as png or dds; required for standard output
+        #[arg(long)]
+        output_format: Option<String>,
+        #[command(flatten)]
+        quantize: QuantizeArgs,
+        /// Output carrier: image, raw, c, cpp, rust, asm, dds, ktx, ktx2, tga or bmp; picked from
+        /// the extension if omitted (.raw/.bin, .h/.c, .hpp/.cpp, .rs, .s/.asm, .dds, .ktx,
+        /// .ktx2, .tga, .bmp)
+        #[arg(long)]
+        carrier: Option<Carrier>,
+        /// Run-length encode TGA output
+        #[arg(long)]
+        rle: bool,
+        /// Palette file of --indexed image and raw output; defaults to NAME.pal.EXT next to it
+        #[arg(long)]
+        palette: Option<PathBuf>,
+        /// Write unique 8x8 tiles plus a tilemap for gba, nds or snes instead of scanlines;
+        /// with --indexed the tiles hold palette indices. Image and raw output write the tilemap
+        /// to its own file
+        #[arg(long)]
+        tiles: Option<TileFormat>,
+        /// Only merge identical tiles, not mirrored ones, for hardware without tile flipping
+        #[arg(long)]
+        no_tile_flips: bool,
+        /// Tilemap file of --tiles image and raw output; defaults to NAME.map.EXT next to it
+        #[arg(long)]
+        tilemap: Option<PathBuf>,
+        /// Reorder packed pixels into a texture layout: morton, psp, ps2, 3ds or switch. The
+        /// output is padded to the layout's block size
+        #[arg(long)]
+        swizzle: Option<Swizzle>,
+        #[command(flatten)]
+        mipmaps: MipArgs,
+        /// Lay all inputs out on one sprite sheet, as the atlas command does, instead of packing
+        /// each on its own
+        #[arg(long)]
+        sheet: bool,
+        #[command(flatten)]
+        atlas: AtlasArgs,
+        #[command(flatten)]
+        raw: RawArgs,
+        #[command(flatten)]
+        source: SourceArgs,
+        #[command(flatten)]
+        batch: BatchArgs,
+    },
+    /// Place many images on one texture, or several pages, and pack it like pack.
+    Atlas {
+        /// Input image files, directories or glob patterns
+        #[arg(required = true)]
+        input: Vec<PathBuf>,
+        /// Output file path, or - for standard output; an atlas of several pages is written to
+        /// NAME-0.EXT, NAME-1.EXT and so on
         output: PathBuf,
+        /// Output format as a file extension, such as png or dds; required for standard output
+        #[arg(long)]
+        output_format: Option<String>,
+        #[command(flatten)]
+        quantize: QuantizeArgs,
+        /// Output carrier, as for pack; picked from the extension if omitted
+        #[arg(long)]
+        carrier: Option<Carrier>,
+        /// Run-length encode TGA output
+        #[arg(long)]
+        rle: bool,
+        #[command(flatten)]
+        atlas: AtlasArgs,
+        #[command(flatten)]
+        raw: RawArgs,
+        #[command(flatten)]
+        source: SourceArgs,
     },
-    /// Unpack a 16-bit ARGB 1555 image to 8-bit RGB.
+    /// Unpack a 16-bit packed image to 8-bit RGBA.
     Unpack {
-        /// Input file path
-        input: PathBuf,
-        /// Output file path
+        /// Input file path, or - for standard input; several files, directories or glob
+        /// patterns unpack into the output directory
+        #[arg(required = true)]
+        input: Vec<PathBuf>,
+        /// Output file path, - for standard output, or output directory for several inputs
         output: PathBuf,
+        /// Output format as a file extension, such as png; required for standard output
+        #[arg(long)]
+        output_format: Option<String>,
+        #[command(flatten)]
+        packed: PackedArgs,
+        #[command(flatten)]
+        slice: SliceArgs,
+        #[command(flatten)]
+        batch: BatchArgs,
+    },
+    /// Describe a packed image: its layout, channel ranges, alpha and colours.
+    Inspect {
+        /// Input file path, or - for standard input
+        input: PathBuf,
+        #[command(flatten)]
+        packed: PackedArgs,
+        /// Print the raw value of the pixel at x,y and its bit fields instead
+        #[arg(long, value_parser = parse_point)]
+        pixel: Option<(u32, u32)>,
+    },
+    /// Pack and unpack an image in memory and report what was lost.
+    Verify {
+        /// Input file path, or - for standard input
+        input: PathBuf,
+        #[command(flatten)]
+        quantize: QuantizeArgs,
+        /// Fail if any channel differs by more than this anywhere
+        #[arg(long)]
+        max_error: Option<u8>,
+        /// Fail if any channel's mean error exceeds this
+        #[arg(long)]
+        max_mean_error: Option<f64>,
+        /// Fail if the colour PSNR in dB falls below this
+        #[arg(long)]
+        min_psnr: Option<f64>,
+        /// Fail if the colour SSIM falls below this
+        #[arg(long)]
+        min_ssim: Option<f64>,
+        /// Fail if more than this many pixels change alpha
+        #[arg(long)]
+        max_alpha_changed: Option<usize>,
+    },
+    /// Write images showing where an image and its round trip differ.
+    Diff {
+        /// Original image path, or - for standard input
+        original: PathBuf,
+        /// Unpacked image to compare against; packed and unpacked in memory if omitted
+        unpacked: Option<PathBuf>,
+        #[command(flatten)]
+        quantize: QuantizeArgs,
+        /// Write a heatmap of per-pixel colour error
+        #[arg(long)]
+        heatmap: Option<PathBuf>,
+        /// Error shown as the hottest heatmap colour; defaults to the largest error
+        #[arg(long)]
+        heatmap_max: Option<u8>,
+        /// Write a mask of pixels whose alpha changed: red where lost, green where gained
+        #[arg(long)]
+        alpha_mask: Option<PathBuf>,
+        /// Write the original, the round trip and the heatmap side by side
+        #[arg(long)]
+        strip: Option<PathBuf>,
     },
 }
 
-fn unpack(argb_1555: u16) -> (u8, u8, u8, u8) {
-    // Extract individual components from the 16-bit value
-    let a = if (argb_1555 >> 15) & 1 == 1 { 255 } else { 0 };
-    let r5 = (argb_1555 >> 10) & 0x1F;
-    let g5 = (argb_1555 >> 5) & 0x1F;
-    let b5 = argb_1555 & 0x1F;
-
-    // Convert 5-bit color values to 8-bit by scaling
-    let r = (r5 as u32 * 255 + 15) / 31;
-    let g = (g5 as u32 * 255 + 15) / 31;
-    let b = (b5 as u32 * 255 + 15) / 31;
-    (r as u8, g as u8, b as u8, a)
-}
-
-fn pack(r: u8, g: u8, b: u8, a: bool) -> u16 {
-    let a_bit = if a { 1 } else { 0 };
-    let r5 = (r as u16 * 31 + 127) / 255;
-    let g5 = (g as u16 * 31 + 127) / 255;
-    let b5 = (b as u16 * 31 + 127) / 255;
-    (a_bit << 15) | (r5 << 10) | (g5 << 5) | b5
-}
-
-// packs rgb 8-bit image to 16-bit argb 1555
-fn pack_image(input_file: &Path, output_file: &Path) -> Result<(), color_eyre::eyre::Error> {
-    let img = ImageReader::open(input_file)?.decode()?;
-    let (width, height) = img.dimensions();
-    let mut argb_1555 = vec![0u16; (width * height) as usize];
-    for (x, y, pixel) in img.pixels() {
-        let [r, g, b, a] = pixel.0;
-        let packed = pack(r, g, b, a > 0);
-        argb_1555[(y * width + x) as usize] = packed;
-    }
-    let out_img = ImageBuffer::<Luma<u16>, Vec<u16>>::from_vec(width, height, argb_1555)
-        .expect("Failed to create image buffer");
-    out_img.save(output_file)?;
-    Ok(())
+/// How sprites are laid out on atlas pages and the metadata describing them.
+#[derive(clap::Args, Debug)]
+pub struct AtlasArgs {
+    /// Bin-packing algorithm: maxrects or skyline
+    #[arg(long, default_value_t)]
+    packer: Packer,
+    /// Largest page width in pixels; sprites that do not fit go on further pages
+    #[arg(long, default_value_t = 2048)]
+    max_width: u32,
+    /// Largest page height in pixels
+    #[arg(long, default_value_t = 2048)]
+    max_height: u32,
+    /// Transparent pixels between sprites
+    #[arg(long, default_value_t = 2)]
+    padding: u32,
+    /// Repeat each sprite's edge pixels outwards this many times
+    #[arg(long, default_value_t = 0)]
+    extrude: u32,
+    /// Allow sprites to be turned 90 degrees: clockwise, or counter-clockwise for libgdx metadata
+    #[arg(long)]
+    rotate: bool,
+    /// Round page sizes up to powers of two
+    #[arg(long)]
+    pot: bool,
+    /// Crop borders that pack as fully transparent in --format off each sprite; the metadata
+    /// records where the sprite sat in its source
+    #[arg(long)]
+    trim: bool,
+    /// Frame metadata format: json-hash (TexturePacker and PixiJS), json-array, phaser, libgdx
+    /// or csv
+    #[arg(long, default_value_t)]
+    metadata: SheetFormat,
+    /// Metadata file; defaults to the output with the format's extension (.json, .atlas or
+    /// .csv). JSON hash and array metadata is numbered per page like the pages
+    #[arg(long)]
+    metadata_file: Option<PathBuf>,
+    /// Pivot of every sprite as x,y fractions of its size from the top left
+    #[arg(long, value_parser = parse_pivot, default_value = "0.5,0.5")]
+    pivot: (f64, f64),
 }
 
-// unpacks 16-bit argb 1555 image to 8-bit rgb
-fn unpack_image(input_file: &Path, output_file: &Path) -> Result<(), color_eyre::eyre::Error> {
-    let dyn_img = ImageReader::open(input_file)?.decode()?;
-    let (width, height) = dyn_img.dimensions();
+impl AtlasArgs {
+    fn options(&self, layout: &Layout) -> AtlasOptions {
+        let trim = self.trim.then_some(*layout);
+        AtlasOptions {
+            packer: self.packer,
+            max_width: self.max_width,
+            max_height: self.max_height,
+            padding: self.padding,
+            extrude: self.extrude,
+            rotate: self.rotate,
+            rotation: self.metadata.rotation(),
+            power_of_two: self.pot,
+            trim,
+        }
+    }
 
-    let luma_img = match dyn_img {
-        image::DynamicImage::ImageLuma16(img) => img,
-        _ => return Err(color_eyre::eyre::eyre!("Expected a 16-bit Luma image")),
-    };
+    fn sheet(self) -> SheetOptions {
+        SheetOptions {
+            format: self.metadata,
+            file: self.metadata_file,
+            pivot: self.pivot,
+        }
+    }
+}
+
+/// Mipmap levels written alongside the full-size image.
+#[derive(clap::Args, Debug)]
+pub struct MipArgs {
+    /// Also write mipmap levels, each resampled in linear light from the full-size input and
+    /// quantized on its own, numbered NAME-0.EXT, NAME-1.EXT and so on
+    #[arg(long)]
+    mipmaps: bool,
+    /// Mipmap resampling filter: box, triangle, mitchell or lanczos
+    #[arg(long, default_value_t, requires = "mipmaps")]
+    mip_filter: MipFilter,
+    /// Number of mipmap levels including the full-size one; down to 1x1 if omitted
+    #[arg(long, requires = "mipmaps")]
+    mip_levels: Option<u32>,
+    /// Write every level into one image, the full-size level on the left and the smaller ones
+    /// stacked on its right, instead of a file each
+    #[arg(long, requires = "mipmaps")]
+    mip_chain: bool,
+    /// Let a 1-bit alpha channel thin out in smaller levels instead of keeping the share of
+    /// pixels that pass the --alpha-threshold test
+    #[arg(long, requires = "mipmaps")]
+    no_mip_coverage: bool,
+}
 
-    let mut rgba_img = image::RgbaImage::new(width, height);
-    for (x, y, pixel) in luma_img.enumerate_pixels() {
-        let val = pixel.0[0];
-        let (r, g, b, a) = unpack(val);
-        rgba_img.put_pixel(x, y, image::Rgba([r, g, b, a]));
+impl MipArgs {
+    fn options(&self) -> Option<MipOptions> {
+        self.mipmaps.then_some(MipOptions {
+            filter: self.mip_filter,
+            levels: self.mip_levels,
+            chain: self.mip_chain,
+            preserve_coverage: !self.no_mip_coverage,
+        })
+    }
+}
+
+/// Pixel layout and how colours are quantized to it.
+#[derive(clap::Args, Debug)]
+pub struct QuantizeArgs {
+    /// Pixel layout: argb1555, xrgb1555 (rgb555), rgba5551, abgr1555, xbgr1555 (bgr555),
+    /// bgra5551, rgb565, bgr565, argb4444, rgba4444, abgr4444, bgra4444, or a spec
+    /// such as R3G3B2, A2R10G10B10, L6A2 or X1R5G5B5
+    #[arg(short, long, default_value = "argb1555")]
+    format: Layout,
+    /// Pixels with alpha above this value set a 1-bit alpha channel
+    #[arg(long, default_value_t = 0)]
+    alpha_threshold: u8,
+    /// Meaning of a set 1-bit alpha channel: opaque, transparent or semi-transparent
+    #[arg(long, default_value_t)]
+    alpha_polarity: AlphaPolarity,
+    /// Dithering for colour channels: none, bayer2, bayer4, bayer8, bayer16, blue-noise,
+    /// floyd-steinberg, atkinson, sierra, sierra2 or sierra-lite
+    #[arg(long, default_value_t)]
+    dither: Dither,
+    /// Dithering for the alpha channel, taking the same modes as --dither
+    #[arg(long, default_value_t)]
+    alpha_dither: Dither,
+    /// Alternate the scan direction on every row for error diffusion
+    #[arg(long)]
+    serpentine: bool,
+    /// Keep fully transparent pixels out of error diffusion
+    #[arg(long)]
+    protect_transparent: bool,
+    /// Quantize to a palette and write 4 or 8-bit indices plus the palette in --format; TGA
+    /// and source output hold both, image and raw output write the palette to its own file
+    #[arg(long)]
+    indexed: Option<u32>,
+    /// Palette quantizer for --indexed: median-cut or k-means
+    #[arg(long, default_value_t)]
+    quantizer: Quantizer,
+    /// Reserve palette index 0 for fully transparent pixels with --indexed
+    #[arg(long)]
+    transparent_index: bool,
+}
+
+impl QuantizeArgs {
+    fn layout(&self) -> Layout {
+        let alpha_mode = AlphaMode {
+            threshold: self.alpha_threshold,
+            polarity: self.alpha_polarity,
+        };
+        Layout {
+            alpha_mode,
+            ..self.format
+        }
+    }
+
+    fn options(&self) -> PackOptions {
+        PackOptions {
+            dither: self.dither,
+            alpha_dither: self.alpha_dither,
+            serpentine: self.serpentine,
+            protect_transparent: self.protect_transparent,
+            palette: self.indexed.map(|index_bits| PaletteOptions {
+                index_bits,
+                quantizer: self.quantizer,
+                transparent_index: self.transparent_index,
+            }),
+            ..PackOptions::default()
+        }
+    }
+}
+
+/// How packed input is stored and which layout it holds.
+#[derive(clap::Args, Debug)]
+pub struct PackedArgs {
+    /// Pixel layout the input was packed with
+    #[arg(short, long, default_value = "argb1555")]
+    format: Layout,
+    /// Meaning of a set 1-bit alpha channel: opaque, transparent or semi-transparent
+    #[arg(long, default_value_t)]
+    alpha_polarity: AlphaPolarity,
+    /// Input carrier: image, raw, dds, ktx, ktx2, tga or bmp; picked from the extension if
+    /// omitted (.raw/.bin, .dds, .ktx, .ktx2, .tga, .bmp), or detected on standard input.
+    /// All but image and raw files carry their own layout
+    #[arg(long)]
+    carrier: Option<Carrier>,
+    #[command(flatten)]
+    raw: RawArgs,
+    /// Width of raw input in pixels, or of --swizzle input before padding
+    #[arg(long)]
+    width: Option<u32>,
+    /// Height of raw input in pixels, or of --swizzle input before padding
+    #[arg(long)]
+    height: Option<u32>,
+    /// Bytes to skip before the first row of raw input
+    #[arg(long, default_value_t = 0)]
+    offset: usize,
+    /// Image or raw input holds 4 or 8-bit palette indices; raw input packs 4-bit indices two
+    /// to a byte
+    #[arg(long)]
+    indexed: Option<u32>,
+    /// Palette file of --indexed input in --format; defaults to NAME.pal.EXT next to it
+    #[arg(long)]
+    palette: Option<PathBuf>,
+    /// Image or raw input holds 8x8 tiles for gba, nds or snes, laid out by a tilemap
+    #[arg(long)]
+    tiles: Option<TileFormat>,
+    /// Tilemap file of --tiles input; defaults to NAME.map.EXT next to it
+    #[arg(long)]
+    tilemap: Option<PathBuf>,
+    /// Texture layout the input was swizzled into: morton, psp, ps2, 3ds or switch
+    #[arg(long)]
+    swizzle: Option<Swizzle>,
+}
+
+impl PackedArgs {
+    fn layout(&self) -> Layout {
+        let mut layout = self.format;
+        layout.alpha_mode.polarity = self.alpha_polarity;
+        layout
+    }
+
+    fn options(&self, output_format: Option<String>) -> UnpackOptions {
+        UnpackOptions {
+            carrier: self.carrier,
+            raw: self.raw.format(self.offset),
+            width: self.width,
+            height: self.height,
+            output_format,
+            indexed: self.indexed,
+            palette_file: self.palette.clone(),
+            tiles: self.tiles,
+            tilemap_file: self.tilemap.clone(),
+            swizzle: self.swizzle,
+        }
+    }
+}
+
+/// How a sprite sheet is cut into frames when unpacking.
+#[derive(clap::Args, Debug)]
+pub struct SliceArgs {
+    /// Cut the sheet into cells of WxH pixels, numbered row by row, each written to its own
+    /// file in the output directory
+    #[arg(long, value_parser = parse_size, group = "frame_source")]
+    grid: Option<(u32, u32)>,
+    /// Pixels between the sheet edge and the outer --grid cells
+    #[arg(long, default_value_t = 0, requires = "grid")]
+    margin: u32,
+    /// Pixels between neighbouring --grid cells
+    #[arg(long, default_value_t = 0, requires = "grid")]
+    spacing: u32,
+    /// Cut the sheet into the frames listed in this metadata file (TexturePacker or Phaser
+    /// JSON, libGDX .atlas or CSV), restoring trimmed and rotated frames
+    #[arg(long, group = "frame_source")]
+    frames: Option<PathBuf>,
+    /// Write the frames as one image in a grid this many columns wide instead of one file each
+    #[arg(long, requires = "frame_source")]
+    relayout: Option<u32>,
+}
+
+impl SliceArgs {
+    fn options(self) -> Option<SliceOptions> {
+        let frames = match (self.grid, self.frames) {
+            (Some((cell_width, cell_height)), _) => FrameSource::Grid(Grid {
+                cell_width,
+                cell_height,
+                margin: self.margin,
+                spacing: self.spacing,
+            }),
+            (None, Some(path)) => FrameSource::Sheet(path),
+            (None, None) => return None,
+        };
+        Some(SliceOptions {
+            frames,
+            relayout: self.relayout,
+        })
+    }
+}
+
+/// Byte layout of raw, headerless data.
+#[derive(clap::Args, Debug)]
+pub struct RawArgs {
+    /// Byte order of raw data: little or big
+    #[arg(long, default_value_t)]
+    endian: Endian,
+    /// Bytes from the start of one row to the next in raw data
+    #[arg(long)]
+    stride: Option<usize>,
+    /// Row alignment in bytes for raw data without an explicit stride
+    #[arg(long, default_value_t = 1)]
+    align: usize,
+}
+
+/// Settings for source array output.
+#[derive(clap::Args, Debug)]
+pub struct SourceArgs {
+    /// Symbol name of the generated array; defaults to the output file name
+    #[arg(long)]
+    symbol: Option<String>,
+    /// Alignment of the generated array in bytes, a power of two
+    #[arg(long, value_parser = parse_data_align)]
+    data_align: Option<u32>,
+    /// Number of values per line in the generated source
+    #[arg(long, default_value_t = 16)]
+    values_per_line: usize,
+}
+
+/// Settings for converting several files at once.
+#[derive(clap::Args, Debug)]
+pub struct BatchArgs {
+    /// Output file name template for several inputs; {dir} is the input's subdirectory,
+    /// {stem} its name without extension, {ext} its extension and {name} its file name
+    #[arg(long, default_value = "{dir}/{stem}.png")]
+    template: String,
+    /// Number of files converted in parallel; 0 uses one thread per CPU
+    #[arg(short, long, default_value_t = 0)]
+    jobs: usize,
+}
+
+impl BatchArgs {
+    fn options(self) -> BatchOptions {
+        BatchOptions {
+            template: self.template,
+            jobs: self.jobs,
+        }
+    }
+}
+
+impl SourceArgs {
+    fn options(self) -> SourceOptions {
+        SourceOptions {
+            symbol: self.symbol,
+            align: self.data_align,
+            per_line: self.values_per_line,
+        }
+    }
+}
+
+impl RawArgs {
+    fn format(&self, offset: usize) -> RawFormat {
+        RawFormat {
+            endian: self.endian,
+            offset,
+            stride: self.stride,
+            align: self.align,
+        }
     }
-    rgba_img.save(output_file)?;
-    Ok(())
 }
 
 fn main() -> Result<()> {
     let args = Args::parse();
 
     match args {
-        Args::Pack { input, output } => pack_image(&input, &output)?,
-        Args::Unpack { input, output } => unpack_image(&input, &output)?,
+        Args::Pack {
+            input,
+            output,
+            output_format,
+            quantize,
+            carrier,
+            rle,
+            palette,
+            tiles,
+            no_tile_flips,
+            tilemap,
+            swizzle,
+            mipmaps,
+            sheet,
+            atlas,
+            raw,
+            source,
+            batch,
+        } => {
+            let format = quantize.layout();
+            let options = PackOptions {
+                carrier,
+                raw: raw.format(0),
+                source: source.options(),
+                rle,
+                output_format,
+                palette_file: palette,
+                tiles: tiles.map(|format| TileOptions {
+                    format,
+                    flips: !no_tile_flips,
+                }),
+                tilemap_file: tilemap,
+                swizzle,
+                mipmaps: mipmaps.options(),
+                ..quantize.options()
+            };
+            match single(&input) {
+                _ if sheet => {
+                    pack_atlas(
+                        &input,
+                        &output,
+                        &format,
+                        &options,
+                        &atlas.options(&format),
+                        Some(&atlas.sheet()),
+                    )?;
+                }
+                Some(input) => pack_image(input, &output, &format, &options)?,
+                None => report(pack_batch(
+                    &input,
+                    &output,
+                    &format,
+                    &options,
+                    &batch.options(),
+                )?)?,
+            }
+        }
+        Args::Atlas {
+            input,
+            output,
+            output_format,
+            quantize,
+            carrier,
+            rle,
+            atlas,
+            raw,
+            source,
+        } => {
+            let format = quantize.layout();
+            let options = PackOptions {
+                carrier,
+                raw: raw.format(0),
+                source: source.options(),
+                rle,
+                output_format,
+                ..quantize.options()
+            };
+            pack_atlas(
+                &input,
+                &output,
+                &format,
+                &options,
+                &atlas.options(&format),
+                Some(&atlas.sheet()),
+            )?;
+        }
+        Args::Unpack {
+            input,
+            output,
+            output_format,
+            packed,
+            slice,
+            batch,
+        } => {
+            let format = packed.layout();
+            let options = packed.options(output_format);
+            match (slice.options(), single(&input)) {
+                (Some(slice), Some(input)) => {
+                    slice_image(input, &output, &format, &options, &slice)?;
+                }
+                (Some(_), None) => return Err(eyre!("slicing takes a single sheet")),
+                (None, Some(input)) => unpack_image(input, &output, &format, &options)?,
+                (None, None) => report(unpack_batch(
+                    &input,
+                    &output,
+                    &format,
+                    &options,
+                    &batch.options(),
+                )?)?,
+            }
+        }
+        Args::Inspect {
+            input,
+            packed,
+            pixel,
+        } => {
+            let file = read_packed(&input, &packed.layout(), &packed.options(None))?;
+            match pixel {
+                Some(point) => print_pixel(&file, point)?,
+                None => print_inspection(&input, &file),
+            }
+        }
+        Args::Verify {
+            input,
+            quantize,
+            max_error,
+            max_mean_error,
+            min_psnr,
+            min_ssim,
+            max_alpha_changed,
+        } => {
+            let metrics = verify_image(&input, &quantize.layout(), &quantize.options())?;
+            print_metrics(&metrics);
+            let thresholds = Thresholds {
+                max_error,
+                max_mean_error,
+                min_psnr,
+                min_ssim,
+                max_alpha_changed,
+            };
+            let violations = thresholds.violations(&metrics);
+            if !violations.is_empty() {
+                return Err(eyre!("quality check failed: {}", violations.join("; ")));
+            }
+        }
+        Args::Diff {
+            original,
+            unpacked,
+            quantize,
+            heatmap,
+            heatmap_max,
+            alpha_mask,
+            strip,
+        } => {
+            let outputs = DiffOptions {
+                heatmap,
+                heatmap_max,
+                alpha_mask,
+                strip,
+            };
+            let layout = quantize.layout();
+            let metrics = diff_image(
+                &original,
+                unpacked.as_deref(),
+                &layout,
+                &quantize.options(),
+                &outputs,
+            )?;
+            print_metrics(&metrics);
+        }
+    };
+
+    Ok(())
+}
+
+fn print_inspection(input: &Path, file: &PackedFile) {
+    let layout = &file.layout;
+    let source = if file.carrier.describes_layout() {
+        "stored in file"
+    } else {
+        "declared"
     };
+    let info = inspect(layout, &file.values);
+    println!("file:    {} ({})", input.display(), file.carrier);
+    println!(
+        "size:    {}x{}, {} pixels",
+        file.width,
+        file.height,
+        file.values.len()
+    );
+    println!(
+        "layout:  {layout}, {} bits per pixel ({source})",
+        layout.bits
+    );
+    println!("colours: {} unique packed values", info.unique_colours);
+    if info.padding_set > 0 {
+        println!("padding: set in {} pixels", info.padding_set);
+    }
+    println!();
+    println!("channel  bits        raw range  8-bit range");
+    for range in &info.ranges {
+        let ch = range.channel;
+        println!(
+            "{:<7}  {:<10}  {:>9}  {:>11}",
+            range.name,
+            bit_span(ch.shift, ch.bits),
+            format!("{}..{}", range.min, range.max),
+            format!("{}..{}", ch.expand(range.min), ch.expand(range.max)),
+        );
+    }
+    match layout.a {
+        Some(a) if a.bits <= 4 => {
+            println!();
+            println!("alpha    pixels");
+            for (value, count) in &info.alpha_histogram {
+                let share = 100.0 * *count as f64 / file.values.len().max(1) as f64;
+                println!("{value:<7}  {count} ({share:.1}%)");
+            }
+        }
+        Some(_) => println!("\nalpha: {} distinct values", info.alpha_histogram.len()),
+        None => {}
+    }
+}
 
+fn print_pixel(file: &PackedFile, (x, y): (u32, u32)) -> Result<()> {
+    if x >= file.width || y >= file.height {
+        return Err(eyre!(
+            "pixel {x},{y} is outside the {}x{} image",
+            file.width,
+            file.height
+        ));
+    }
+    let layout = &file.layout;
+    let value = file.values[(y * file.width + x) as usize];
+    let digits = layout.bits as usize / 4;
+    let binary = format!("{value:0width$b}", width = layout.bits as usize);
+    let nibbles: Vec<String> = binary
+        .as_bytes()
+        .chunks(4)
+        .map(|c| String::from_utf8_lossy(c).into())
+        .collect();
+    println!(
+        "pixel {x},{y} of {layout}: 0x{value:0digits$X} = 0b{}",
+        nibbles.join("_")
+    );
+    for field in fields(layout, value) {
+        let ch = field.channel;
+        println!(
+            "  {}  {:<11}  raw {:>5}  -> {:>3}",
+            field.name,
+            bit_span(ch.shift, ch.bits),
+            field.raw,
+            field.expanded
+        );
+    }
+    let [r, g, b, a] = layout.unpack(value);
+    println!("unpacks to RGBA ({r}, {g}, {b}, {a})");
     Ok(())
 }
+
+fn bit_span(shift: u32, bits: u32) -> String {
+    match bits {
+        1 => format!("bit {shift}"),
+        _ => format!("bits {}-{}", shift, shift + bits - 1),
+    }
+}
+
+fn parse_point(s: &str) -> Result<(u32, u32), String> {
+    let (x, y) = s.split_once(',').ok_or("expected x,y")?;
+    let parse = |v: &str| {
+        v.trim()
+            .parse::<u32>()
+            .map_err(|err| format!("`{v}`: {err}"))
+    };
+    Ok((parse(x)?, parse(y)?))
+}
+
+fn parse_size(s: &str) -> Result<(u32, u32), String> {
+    let (w, h) = s.split_once(['x', 'X']).ok_or("expected WxH")?;
+    let parse = |v: &str| {
+        v.trim()
+            .parse::<u32>()
+            .map_err(|err| format!("`{v}`: {err}"))
+    };
+    Ok((parse(w)?, parse(h)?))
+}
+
+fn parse_data_align(s: &str) -> Result<u32, String> {
+    match s
+        .trim()
+        .parse::<u32>()
+        .map_err(|err| format!("`{s}`: {err}"))?
+    {
+        align if align.is_power_of_two() => Ok(align),
+        align => Err(format!("`{align}` is not a power of two")),
+    }
+}
+
+fn parse_pivot(s: &str) -> Result<(f64, f64), String> {
+    let (x, y) = s.split_once(',').ok_or("expected x,y")?;
+    let parse = |v: &str| {
+        v.trim()
+            .parse::<f64>()
+            .map_err(|err| format!("`{v}`: {err}"))
+    };
+    Ok((parse(x)?, parse(y)?))
+}
+
+fn print_metrics(metrics: &Metrics) {
+    println!("channel  max error  mean error      PSNR    SSIM");
+    let rows = CHANNEL_NAMES.iter().zip(&metrics.channels);
+    for (name, channel) in rows.chain([(&"RGB", &metrics.colour)]) {
+        let psnr = match channel.psnr {
+            psnr if psnr.is_finite() => format!("{psnr:.2} dB"),
+            _ => "inf".to_string(),
+        };
+        println!(
+            "{name:<7}  {:>9}  {:>10.3}  {psnr:>8}  {:.4}",
+            channel.max, channel.mean, channel.ssim
+        );
+    }
+    println!(
+        "alpha changed in {} of {} pixels",
+        metrics.alpha_changed, metrics.pixels
+    );
+}
+
+/// The input path when a single file is converted to a single output.
+fn single(inputs: &[PathBuf]) -> Option<&PathBuf> {
+    match inputs {
+        [input] if !input.is_dir() && !is_pattern(input) => Some(input),
+        _ => None,
+    }
+}
+
+/// Prints each failed file of a batch and fails if there were any.
+fn report(report: BatchReport) -> Result<()> {
+    for (path, err) in &report.failures {
+        eprintln!("{}: {err}", path.display());
+    }
+    match report.failures.len() {
+        0 => Ok(()),
+        failed => Err(eyre!(
+            "{failed} of {} files failed",
+            failed + report.converted
+        )),
+    }
+}
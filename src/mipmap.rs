@@ -0,0 +1,323 @@
+//! Mipmap chains: successively halved copies of an image, each resampled in
+//! linear light straight from the full-size source.
+
+use std::f32::consts::PI;
+use std::fmt;
+use std::str::FromStr;
+
+use image::{DynamicImage, Rgba, RgbaImage};
+
+use crate::{AlphaMode, Error, Result};
+
+/// Resampling filter used to make each level.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MipFilter {
+    /// Average of the source pixels each level pixel covers.
+    #[default]
+    Box,
+    /// Tent filter two level pixels wide: smoother than box, a little
+    /// blurrier.
+    Triangle,
+    /// Mitchell-Netravali cubic with B = C = 1/3: sharp with little ringing.
+    Mitchell,
+    /// Three-lobed Lanczos: the sharpest, with some ringing at hard edges.
+    Lanczos,
+}
+
+impl MipFilter {
+    /// Half width of the filter in level pixels.
+    fn support(self) -> f32 {
+        match self {
+            MipFilter::Box => 0.5,
+            MipFilter::Triangle => 1.0,
+            MipFilter::Mitchell => 2.0,
+            MipFilter::Lanczos => 3.0,
+        }
+    }
+
+    /// Weight of a sample `t` level pixels from the centre.
+    fn weight(self, t: f32) -> f32 {
+        let t = t.abs();
+        match self {
+            MipFilter::Box => (t < 0.5) as u8 as f32,
+            MipFilter::Triangle => (1.0 - t).max(0.0),
+            MipFilter::Mitchell => {
+                let (b, c) = (1.0 / 3.0, 1.0 / 3.0);
+                let w = if t < 1.0 {
+                    (12.0 - 9.0 * b - 6.0 * c) * t * t * t
+                        + (-18.0 + 12.0 * b + 6.0 * c) * t * t
+                        + (6.0 - 2.0 * b)
+                } else if t < 2.0 {
+                    (-b - 6.0 * c) * t * t * t
+                        + (6.0 * b + 30.0 * c) * t * t
+                        + (-12.0 * b - 48.0 * c) * t
+                        + (8.0 * b + 24.0 * c)
+                } else {
+                    0.0
+                };
+                w / 6.0
+            }
+            MipFilter::Lanczos => match t {
+                0.0 => 1.0,
+                t if t < 3.0 => {
+                    let x = PI * t;
+                    3.0 * x.sin() * (x / 3.0).sin() / (x * x)
+                }
+                _ => 0.0,
+            },
+        }
+    }
+}
+
+impl fmt::Display for MipFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            MipFilter::Box => "box",
+            MipFilter::Triangle => "triangle",
+            MipFilter::Mitchell => "mitchell",
+            MipFilter::Lanczos => "lanczos",
+        })
+    }
+}
+
+impl FromStr for MipFilter {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "box" => Ok(MipFilter::Box),
+            "triangle" | "tent" | "bilinear" => Ok(MipFilter::Triangle),
+            "mitchell" | "cubic" => Ok(MipFilter::Mitchell),
+            "lanczos" | "lanczos3" => Ok(MipFilter::Lanczos),
+            _ => Err(Error::UnknownMipFilter(s.to_string())),
+        }
+    }
+}
+
+/// Settings for mipmap generation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MipOptions {
+    pub filter: MipFilter,
+    /// Number of levels including the full-size one; `None` continues down
+    /// to 1x1.
+    pub levels: Option<u32>,
+    /// Write every level into one image, see [`chain_layout`], instead of a
+    /// file per level.
+    pub chain: bool,
+    /// For 1-bit alpha, scale each level's alpha so the share of pixels
+    /// passing the alpha test matches the full-size image. Without it,
+    /// alpha-tested foliage and fences thin out or vanish in small levels.
+    pub preserve_coverage: bool,
+}
+
+impl Default for MipOptions {
+    fn default() -> Self {
+        Self {
+            filter: MipFilter::default(),
+            levels: None,
+            chain: false,
+            preserve_coverage: true,
+        }
+    }
+}
+
+/// Sizes of the levels of a `width` by `height` image, each half the last
+/// rounded down, ending at 1x1 or after `levels`.
+pub fn mip_sizes(width: u32, height: u32, levels: Option<u32>) -> Vec<(u32, u32)> {
+    let full = 32 - width.max(height).max(1).leading_zeros();
+    let count = levels.map_or(full, |levels| levels.clamp(1, full));
+    (0..count)
+        .map(|level| ((width >> level).max(1), (height >> level).max(1)))
+        .collect()
+}
+
+/// Where each level goes in a single mip-chain image: the full-size level
+/// on the left and the smaller ones stacked top to bottom on its right.
+/// Returns the image size and each level's top-left corner.
+pub fn chain_layout(sizes: &[(u32, u32)]) -> (u32, u32, Vec<(u32, u32)>) {
+    let Some(&(width, height)) = sizes.first() else {
+        return (0, 0, Vec::new());
+    };
+    let mut offsets = vec![(0, 0)];
+    let mut y = 0;
+    for &(_, h) in &sizes[1..] {
+        offsets.push((width, y));
+        y += h;
+    }
+    let right = sizes.get(1).map_or(0, |&(w, _)| w);
+    (width + right, height.max(y), offsets)
+}
+
+/// Makes the mipmap levels of `img`, starting with the image itself.
+///
+/// Every level is resampled from the full-size source, at up to 32 bits
+/// per channel when the source has them, in linear light with
+/// premultiplied alpha so dark fringes do not bleed in from transparent
+/// pixels. `alpha_test` is the rule for 1-bit alpha whose coverage
+/// [`MipOptions::preserve_coverage`] keeps.
+pub fn mip_levels(
+    img: &DynamicImage,
+    alpha_test: Option<AlphaMode>,
+    options: &MipOptions,
+) -> Vec<RgbaImage> {
+    let source = img.to_rgba32f();
+    let (width, height) = source.dimensions();
+    let linear: Vec<[f32; 4]> = source
+        .pixels()
+        .map(|&Rgba([r, g, b, a])| {
+            [
+                srgb_to_linear(r) * a,
+                srgb_to_linear(g) * a,
+                srgb_to_linear(b) * a,
+                a,
+            ]
+        })
+        .collect();
+    let base = img.to_rgba8();
+    let alpha_test = alpha_test.filter(|_| options.preserve_coverage);
+    let target = alpha_test.map(|alpha| coverage(base.pixels().map(|px| px.0[3]), alpha));
+
+    let mut levels = vec![base];
+    for &(w, h) in &mip_sizes(width, height, options.levels)[1..] {
+        let rows = resample_rows(&linear, width, height, w, options.filter);
+        let pixels = resample_columns(&rows, w, height, h, options.filter);
+        let mut level = RgbaImage::new(w, h);
+        for (out, [r, g, b, a]) in level.pixels_mut().zip(pixels) {
+            let a = a.clamp(0.0, 1.0);
+            let colour = |c: f32| match a {
+                0.0 => 0,
+                a => to_u8(linear_to_srgb((c / a).clamp(0.0, 1.0))),
+            };
+            *out = Rgba([colour(r), colour(g), colour(b), to_u8(a)]);
+        }
+        if let (Some(alpha), Some(target)) = (alpha_test, target) {
+            keep_coverage(&mut level, alpha, target);
+        }
+        levels.push(level);
+    }
+    levels
+}
+
+/// Filters each row of a `width` by `height` image down to `to` pixels.
+fn resample_rows(
+    pixels: &[[f32; 4]],
+    width: u32,
+    height: u32,
+    to: u32,
+    filter: MipFilter,
+) -> Vec<[f32; 4]> {
+    let taps = taps(width, to, filter);
+    let mut out = Vec::with_capacity((to * height) as usize);
+    for row in pixels.chunks(width as usize) {
+        out.extend(taps.iter().map(|taps| apply(taps, |i| row[i])));
+    }
+    out
+}
+
+/// Filters each column of a `width` by `height` image down to `to` pixels.
+fn resample_columns(
+    pixels: &[[f32; 4]],
+    width: u32,
+    height: u32,
+    to: u32,
+    filter: MipFilter,
+) -> Vec<[f32; 4]> {
+    let taps = taps(height, to, filter);
+    let width = width as usize;
+    let mut out = Vec::with_capacity(width * to as usize);
+    for taps in &taps {
+        out.extend((0..width).map(|x| apply(taps, |i| pixels[i * width + x])));
+    }
+    out
+}
+
+/// For each of `to` output pixels, the source pixels it draws on and their
+/// normalised weights. Samples past the edges repeat the edge pixel.
+fn taps(from: u32, to: u32, filter: MipFilter) -> Vec<Vec<(usize, f32)>> {
+    let scale = from as f32 / to as f32;
+    let reach = filter.support() * scale;
+    (0..to)
+        .map(|i| {
+            let centre = (i as f32 + 0.5) * scale;
+            let first = (centre - reach).floor() as i64;
+            let last = (centre + reach).ceil() as i64;
+            let mut taps: Vec<(usize, f32)> = (first..=last)
+                .map(|j| {
+                    let weight = filter.weight((j as f32 + 0.5 - centre) / scale);
+                    (j.clamp(0, from as i64 - 1) as usize, weight)
+                })
+                .filter(|&(_, weight)| weight != 0.0)
+                .collect();
+            let total: f32 = taps.iter().map(|&(_, weight)| weight).sum();
+            for (_, weight) in &mut taps {
+                *weight /= total;
+            }
+            taps
+        })
+        .collect()
+}
+
+fn apply(taps: &[(usize, f32)], sample: impl Fn(usize) -> [f32; 4]) -> [f32; 4] {
+    let mut sum = [0.0; 4];
+    for &(i, weight) in taps {
+        for (s, v) in sum.iter_mut().zip(sample(i)) {
+            *s += v * weight;
+        }
+    }
+    sum
+}
+
+/// Share of alpha values that `alpha` counts as covered.
+fn coverage(alphas: impl ExactSizeIterator<Item = u8>, alpha: AlphaMode) -> f64 {
+    let total = alphas.len().max(1);
+    alphas.filter(|&a| alpha.covers(a)).count() as f64 / total as f64
+}
+
+/// Scales the alpha of `level` so its coverage comes as close to `target`
+/// as a single factor allows, searching the factor by bisection.
+fn keep_coverage(level: &mut RgbaImage, alpha: AlphaMode, target: f64) {
+    let original: Vec<u8> = level.pixels().map(|px| px.0[3]).collect();
+    let scaled = |factor: f32| {
+        original
+            .iter()
+            .map(move |&a| (a as f32 * factor).round().min(255.0) as u8)
+    };
+    let measure = |factor: f32| coverage(scaled(factor), alpha);
+    let (mut low, mut high) = (0.0_f32, 1.0_f32);
+    while measure(high) < target && high < 256.0 {
+        high *= 2.0;
+    }
+    for _ in 0..24 {
+        let middle = (low + high) / 2.0;
+        if measure(middle) < target {
+            low = middle;
+        } else {
+            high = middle;
+        }
+    }
+    let factor = match (measure(low) - target).abs() < (measure(high) - target).abs() {
+        true => low,
+        false => high,
+    };
+    for (px, a) in level.pixels_mut().zip(scaled(factor)) {
+        px.0[3] = a;
+    }
+}
+
+fn srgb_to_linear(c: f32) -> f32 {
+    match c {
+        c if c <= 0.04045 => c / 12.92,
+        c => ((c + 0.055) / 1.055).powf(2.4),
+    }
+}
+
+fn linear_to_srgb(c: f32) -> f32 {
+    match c {
+        c if c <= 0.003_130_8 => c * 12.92,
+        c => 1.055 * c.powf(1.0 / 2.4) - 0.055,
+    }
+}
+
+fn to_u8(c: f32) -> u8 {
+    (c * 255.0).round().clamp(0.0, 255.0) as u8
+}
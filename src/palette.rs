@@ -0,0 +1,401 @@
+//! Paletted output: colour quantization to a small palette of packed
+//! entries plus 4 or 8-bit indices.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use image::RgbaImage;
+
+use crate::{Dither, Error, Layout, PackOptions, Result};
+
+/// How the palette is chosen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Quantizer {
+    /// Repeatedly split the colour box with the widest channel at its median.
+    #[default]
+    MedianCut,
+    /// Refine the median-cut palette with Lloyd's k-means iterations.
+    KMeans,
+}
+
+impl fmt::Display for Quantizer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Quantizer::MedianCut => "median-cut",
+            Quantizer::KMeans => "k-means",
+        })
+    }
+}
+
+impl FromStr for Quantizer {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "median-cut" => Ok(Quantizer::MedianCut),
+            "k-means" | "kmeans" => Ok(Quantizer::KMeans),
+            _ => Err(Error::UnknownQuantizer(s.to_string())),
+        }
+    }
+}
+
+/// Settings for paletted output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PaletteOptions {
+    /// Bits per index: 4 for 16 colours or 8 for 256.
+    pub index_bits: u32,
+    pub quantizer: Quantizer,
+    /// Keep index 0 for pixels that pack as fully transparent.
+    pub transparent_index: bool,
+}
+
+impl Default for PaletteOptions {
+    fn default() -> Self {
+        Self {
+            index_bits: 8,
+            quantizer: Quantizer::default(),
+            transparent_index: false,
+        }
+    }
+}
+
+/// An image reduced to palette indices.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Indexed {
+    pub width: u32,
+    pub height: u32,
+    /// Bits per index, 4 or 8.
+    pub index_bits: u32,
+    /// Row-major indices, one per pixel.
+    pub indices: Vec<u8>,
+    /// Packed palette entries, padded with zeros to `2^index_bits` entries.
+    pub palette: Vec<u32>,
+}
+
+impl Indexed {
+    /// The packed value of every pixel, looked up in the palette.
+    pub fn resolve(&self) -> Result<Vec<u32>> {
+        resolve(&self.indices, &self.palette)
+    }
+
+    /// Indices as stored in raw and source output: a byte each for 8-bit
+    /// indices, or two to a byte with the left pixel in the low nibble and
+    /// each row padded to a whole byte for 4-bit indices.
+    pub fn index_bytes(&self) -> Vec<u8> {
+        match self.index_bits {
+            4 => pack_nibbles(&self.indices, self.width),
+            _ => self.indices.clone(),
+        }
+    }
+}
+
+/// Reads `width * height` indices stored as by [`Indexed::index_bytes`].
+pub fn read_indices(bytes: &[u8], index_bits: u32, width: u32, height: u32) -> Result<Vec<u8>> {
+    let pitch = match index_bits {
+        4 => (width as usize).div_ceil(2),
+        8 => width as usize,
+        bits => return Err(Error::InvalidIndexBits(bits)),
+    };
+    let expected = pitch * height as usize;
+    if bytes.len() < expected {
+        return Err(Error::Truncated {
+            expected,
+            actual: bytes.len(),
+        });
+    }
+    let bytes = &bytes[..expected];
+    Ok(match index_bits {
+        4 => unpack_nibbles(bytes, width),
+        _ => bytes.to_vec(),
+    })
+}
+
+/// Looks up each index in `palette`.
+pub fn resolve(indices: &[u8], palette: &[u32]) -> Result<Vec<u32>> {
+    indices
+        .iter()
+        .map(|&i| {
+            palette
+                .get(i as usize)
+                .copied()
+                .ok_or_else(|| Error::InvalidFile {
+                    format: "palette",
+                    reason: format!("index {i} is outside the {}-entry palette", palette.len()),
+                })
+        })
+        .collect()
+}
+
+/// Where the palette of a paletted `path` is stored when the carrier cannot
+/// hold both: `name.pal.ext` next to it.
+pub fn palette_path(path: &Path) -> PathBuf {
+    companion_path(path, "pal")
+}
+
+/// `name.tag.ext` next to `path`, for data written beside the main output.
+pub(crate) fn companion_path(path: &Path, tag: &str) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(ext) => format!("{stem}.{tag}.{}", ext.to_string_lossy()),
+        None => format!("{stem}.{tag}"),
+    };
+    path.with_file_name(name)
+}
+
+/// Whether `path` is named like a companion file with one of `tags`.
+pub(crate) fn is_companion_path(path: &Path, tags: &[&str]) -> bool {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    tags.iter().any(|tag| stem.ends_with(&format!(".{tag}")))
+}
+
+fn pack_nibbles(indices: &[u8], width: u32) -> Vec<u8> {
+    indices
+        .chunks(width.max(1) as usize)
+        .flat_map(|row| {
+            row.chunks(2)
+                .map(|pair| pair[0] | pair.get(1).map_or(0, |hi| hi << 4))
+        })
+        .collect()
+}
+
+fn unpack_nibbles(bytes: &[u8], width: u32) -> Vec<u8> {
+    let pitch = (width as usize).div_ceil(2);
+    bytes
+        .chunks(pitch.max(1))
+        .flat_map(|row| {
+            row.iter()
+                .flat_map(|&b| [b & 0x0F, b >> 4])
+                .take(width as usize)
+        })
+        .collect()
+}
+
+/// Reduces `img` to a palette of at most `2^index_bits` entries of `layout`
+/// and the index of each pixel. Colour dithering from `options` is applied
+/// against the palette; alpha dithering is not used.
+pub fn quantize_indexed(
+    layout: &Layout,
+    img: &RgbaImage,
+    options: &PackOptions,
+    palette_options: &PaletteOptions,
+) -> Result<Indexed> {
+    let index_bits = palette_options.index_bits;
+    if index_bits != 4 && index_bits != 8 {
+        return Err(Error::InvalidIndexBits(index_bits));
+    }
+    let size = 1usize << index_bits;
+    let reserved = palette_options.transparent_index as usize;
+
+    // Work with colours as the layout stores them, so palette entries are
+    // exactly representable and transparency follows the alpha rule.
+    let snap = |px: [u8; 4]| layout.unpack(layout.pack(px));
+    let transparent: Vec<bool> = img
+        .pixels()
+        .map(|px| palette_options.transparent_index && snap(px.0)[3] == 0)
+        .collect();
+    let mut counts: HashMap<[u8; 4], u32> = HashMap::new();
+    for (px, &skip) in img.pixels().zip(&transparent) {
+        if !skip {
+            *counts.entry(snap(px.0)).or_insert(0) += 1;
+        }
+    }
+    let mut colours: Vec<([f32; 4], u32)> = counts
+        .into_iter()
+        .map(|(c, n)| (c.map(|v| v as f32), n))
+        .collect();
+    colours.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+
+    let mut centroids = median_cut(&colours, size - reserved);
+    if palette_options.quantizer == Quantizer::KMeans {
+        k_means(&colours, &mut centroids);
+    }
+
+    let mut palette = vec![0u32; size];
+    let mut entries: Vec<[f32; 4]> = Vec::new();
+    if reserved == 1 {
+        palette[0] = layout.pack([0, 0, 0, 0]);
+        entries.push([f32::INFINITY; 4]);
+    }
+    for centroid in centroids {
+        let packed = layout.pack(centroid.map(|v| v.round().clamp(0.0, 255.0) as u8));
+        palette[entries.len()] = packed;
+        entries.push(layout.unpack(packed).map(|v| v as f32));
+    }
+
+    let indices = map_pixels(img, &entries, &transparent, options);
+    Ok(Indexed {
+        width: img.width(),
+        height: img.height(),
+        index_bits,
+        indices,
+        palette,
+    })
+}
+
+fn distance(a: &[f32; 4], b: &[f32; 4]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| (x - y) * (x - y)).sum()
+}
+
+fn nearest(colour: &[f32; 4], entries: &[[f32; 4]]) -> usize {
+    (0..entries.len())
+        .min_by(|&i, &j| distance(colour, &entries[i]).total_cmp(&distance(colour, &entries[j])))
+        .unwrap_or(0)
+}
+
+/// Splits the weighted colours into at most `count` boxes and returns the
+/// weighted mean of each.
+fn median_cut(colours: &[([f32; 4], u32)], count: usize) -> Vec<[f32; 4]> {
+    if colours.is_empty() || count == 0 {
+        return Vec::new();
+    }
+    let mut boxes: Vec<Vec<([f32; 4], u32)>> = vec![colours.to_vec()];
+    while boxes.len() < count {
+        let widest = boxes
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| b.len() > 1)
+            .map(|(i, b)| {
+                let (channel, range) = widest_channel(b);
+                (i, channel, range)
+            })
+            .max_by(|a, b| a.2.total_cmp(&b.2));
+        let Some((i, channel, _)) = widest else { break };
+
+        let mut colours = boxes.swap_remove(i);
+        colours.sort_by(|a, b| a.0[channel].total_cmp(&b.0[channel]));
+        let total: u64 = colours.iter().map(|c| c.1 as u64).sum();
+        let mut seen = 0;
+        let mut split = colours.len() - 1;
+        for (j, c) in colours.iter().enumerate() {
+            seen += c.1 as u64;
+            if seen * 2 >= total {
+                split = j + 1;
+                break;
+            }
+        }
+        let upper = colours.split_off(split.clamp(1, colours.len() - 1));
+        boxes.push(colours);
+        boxes.push(upper);
+    }
+    boxes
+        .iter()
+        .map(|b| mean(b.iter().map(|c| (&c.0, c.1))))
+        .collect()
+}
+
+fn widest_channel(colours: &[([f32; 4], u32)]) -> (usize, f32) {
+    (0..4)
+        .map(|c| {
+            let (lo, hi) = colours.iter().fold((f32::MAX, f32::MIN), |(lo, hi), col| {
+                (lo.min(col.0[c]), hi.max(col.0[c]))
+            });
+            (c, hi - lo)
+        })
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .unwrap()
+}
+
+fn mean<'a>(colours: impl Iterator<Item = (&'a [f32; 4], u32)>) -> [f32; 4] {
+    let mut sum = [0.0f64; 4];
+    let mut total = 0.0f64;
+    for (c, n) in colours {
+        for (s, v) in sum.iter_mut().zip(c) {
+            *s += *v as f64 * n as f64;
+        }
+        total += n as f64;
+    }
+    sum.map(|s| (s / total.max(1.0)) as f32)
+}
+
+/// Moves each centroid to the weighted mean of the colours nearest to it
+/// until the assignment settles.
+fn k_means(colours: &[([f32; 4], u32)], centroids: &mut [[f32; 4]]) {
+    let mut assignment = vec![usize::MAX; colours.len()];
+    for _ in 0..16 {
+        let mut changed = false;
+        for (slot, (colour, _)) in assignment.iter_mut().zip(colours) {
+            let best = nearest(colour, centroids);
+            changed |= *slot != best;
+            *slot = best;
+        }
+        if !changed {
+            break;
+        }
+        for (k, centroid) in centroids.iter_mut().enumerate() {
+            let members = colours
+                .iter()
+                .zip(&assignment)
+                .filter(|(_, a)| **a == k)
+                .map(|(c, _)| (&c.0, c.1));
+            let members: Vec<_> = members.collect();
+            if !members.is_empty() {
+                *centroid = mean(members.into_iter());
+            }
+        }
+    }
+}
+
+/// Picks the palette entry of every pixel, dithering against the palette.
+fn map_pixels(
+    img: &RgbaImage,
+    entries: &[[f32; 4]],
+    transparent: &[bool],
+    options: &PackOptions,
+) -> Vec<u8> {
+    let (width, height) = (img.width() as i32, img.height() as i32);
+    let mut work: Vec<[f32; 4]> = img.pixels().map(|px| px.0.map(|v| v as f32)).collect();
+    let mut indices = vec![0u8; work.len()];
+    match options.dither {
+        Dither::Diffusion(kernel) => {
+            let (taps, divisor) = kernel.taps();
+            for y in 0..height {
+                let reverse = options.serpentine && y % 2 == 1;
+                for i in 0..width {
+                    let x = if reverse { width - 1 - i } else { i };
+                    let idx = (y * width + x) as usize;
+                    if transparent[idx] {
+                        continue;
+                    }
+                    let colour = work[idx].map(|v| v.clamp(0.0, 255.0));
+                    let best = nearest(&colour, entries);
+                    indices[idx] = best as u8;
+                    let error: [f32; 4] = std::array::from_fn(|c| colour[c] - entries[best][c]);
+                    for &(dx, dy, weight) in taps {
+                        let (nx, ny) = (if reverse { x - dx } else { x + dx }, y + dy);
+                        if nx < 0 || nx >= width || ny >= height {
+                            continue;
+                        }
+                        let n = (ny * width + nx) as usize;
+                        for c in 0..4 {
+                            work[n][c] += error[c] * weight / divisor;
+                        }
+                    }
+                }
+            }
+        }
+        dither => {
+            // Spread ordered offsets over roughly one palette step per channel.
+            let spread = 255.0 / (entries.len().max(2) as f32).cbrt();
+            let mut cache: HashMap<[u8; 4], u8> = HashMap::new();
+            for (i, (colour, index)) in work.iter_mut().zip(&mut indices).enumerate() {
+                if transparent[i] {
+                    continue;
+                }
+                if let Dither::Ordered(map) = dither {
+                    let (x, y) = (i as u32 % width as u32, i as u32 / width as u32);
+                    let t = (map.threshold(x, y) - 0.5) * spread;
+                    colour[..3]
+                        .iter_mut()
+                        .for_each(|v| *v = (*v + t).clamp(0.0, 255.0));
+                }
+                let key = colour.map(|v| v as u8);
+                *index = *cache
+                    .entry(key)
+                    .or_insert_with(|| nearest(colour, entries) as u8);
+            }
+        }
+    }
+    indices
+}
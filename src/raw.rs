@@ -0,0 +1,160 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::{Error, Layout, Result};
+
+/// Byte order of multi-byte packed values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Endian {
+    #[default]
+    Little,
+    Big,
+}
+
+impl fmt::Display for Endian {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Endian::Little => "little",
+            Endian::Big => "big",
+        })
+    }
+}
+
+impl FromStr for Endian {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "little" | "le" => Ok(Endian::Little),
+            "big" | "be" => Ok(Endian::Big),
+            _ => Err(Error::UnknownEndian(s.to_string())),
+        }
+    }
+}
+
+/// Largest raw output [`RawFormat::encode`] writes, so that a stray stride
+/// or alignment fails instead of exhausting memory.
+const MAX_ENCODED_SIZE: u64 = 1 << 32;
+
+/// Byte layout of headerless packed pixel data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawFormat {
+    pub endian: Endian,
+    /// Bytes to skip before the first row when reading.
+    pub offset: usize,
+    /// Bytes from the start of one row to the next. Defaults to the packed
+    /// row size rounded up to `align`.
+    pub stride: Option<usize>,
+    /// Alignment of each row in bytes.
+    pub align: usize,
+}
+
+impl Default for RawFormat {
+    fn default() -> Self {
+        Self {
+            endian: Endian::default(),
+            offset: 0,
+            stride: None,
+            align: 1,
+        }
+    }
+}
+
+impl RawFormat {
+    /// Bytes from the start of one row to the next for rows of `width` pixels.
+    /// An alignment too large to reach saturates, so reading then reports
+    /// the input as truncated.
+    pub fn row_pitch(&self, layout: &Layout, width: u32) -> usize {
+        let row = width as usize * bytes_per_pixel(layout);
+        self.stride.unwrap_or_else(|| {
+            row.checked_next_multiple_of(self.align.max(1))
+                .unwrap_or(usize::MAX)
+        })
+    }
+
+    /// Serializes row-major packed values, zero-filling row padding. The
+    /// offset only applies to reading.
+    pub fn encode(&self, layout: &Layout, width: u32, packed: &[u32]) -> Result<Vec<u8>> {
+        let bpp = bytes_per_pixel(layout);
+        let pitch = self.row_pitch(layout, width);
+        let row = width as usize * bpp;
+        if pitch < row {
+            return Err(Error::StrideTooSmall { stride: pitch, row });
+        }
+        let rows = packed.len().div_ceil(width.max(1) as usize);
+        let size = pitch
+            .checked_mul(rows)
+            .filter(|&size| size as u64 <= MAX_ENCODED_SIZE)
+            .ok_or(Error::RawTooLarge {
+                stride: pitch,
+                rows,
+            })?;
+        let mut bytes = Vec::with_capacity(size);
+        for values in packed.chunks(width.max(1) as usize) {
+            for &v in values {
+                self.push_value(&mut bytes, v, bpp);
+            }
+            bytes.resize(bytes.len() + pitch - row, 0);
+        }
+        Ok(bytes)
+    }
+
+    /// Reads `width * height` row-major packed values from `bytes`.
+    pub fn decode(
+        &self,
+        layout: &Layout,
+        width: u32,
+        height: u32,
+        bytes: &[u8],
+    ) -> Result<Vec<u32>> {
+        let bpp = bytes_per_pixel(layout);
+        let pitch = self.row_pitch(layout, width);
+        let row = width as usize * bpp;
+        if pitch < row {
+            return Err(Error::StrideTooSmall { stride: pitch, row });
+        }
+        let truncated = |expected| Error::Truncated {
+            expected,
+            actual: bytes.len(),
+        };
+        let needed = match height {
+            0 => Some(self.offset),
+            h => pitch
+                .checked_mul(h as usize - 1)
+                .and_then(|rows| rows.checked_add(row))
+                .and_then(|rows| rows.checked_add(self.offset)),
+        }
+        .ok_or_else(|| truncated(usize::MAX))?;
+        if bytes.len() < needed {
+            return Err(truncated(needed));
+        }
+        let mut packed = Vec::with_capacity(width as usize * height as usize);
+        for y in 0..height as usize {
+            // Cannot overflow: the last row start was checked above.
+            let start = self.offset + y * pitch;
+            for px in bytes[start..start + row].chunks_exact(bpp) {
+                packed.push(self.read_value(px));
+            }
+        }
+        Ok(packed)
+    }
+
+    fn push_value(&self, out: &mut Vec<u8>, v: u32, bpp: usize) {
+        match self.endian {
+            Endian::Little => out.extend_from_slice(&v.to_le_bytes()[..bpp]),
+            Endian::Big => out.extend_from_slice(&v.to_be_bytes()[4 - bpp..]),
+        }
+    }
+
+    fn read_value(&self, px: &[u8]) -> u32 {
+        let fold = |v: u32, &b: &u8| (v << 8) | b as u32;
+        match self.endian {
+            Endian::Little => px.iter().rev().fold(0, fold),
+            Endian::Big => px.iter().fold(0, fold),
+        }
+    }
+}
+
+fn bytes_per_pixel(layout: &Layout) -> usize {
+    layout.bits as usize / 8
+}
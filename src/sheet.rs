@@ -0,0 +1,447 @@
+//! Sprite sheet metadata: frame coordinates of an atlas in the formats game
+//! engines load.
+
+use std::fmt::{self, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize, Serializer};
+
+use crate::{Atlas, Channel, Error, Layout, Placement, Result, Rotation, page_path};
+
+/// Metadata file format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SheetFormat {
+    /// TexturePacker JSON with frames keyed by name, one file per page.
+    /// PixiJS loads it and finds further pages through
+    /// `meta.related_multi_packs`.
+    #[default]
+    JsonHash,
+    /// TexturePacker JSON with frames in an array, one file per page.
+    JsonArray,
+    /// Phaser 3 multi-atlas JSON: every page in one file.
+    Phaser,
+    /// libGDX `.atlas` text, every page in one file. libGDX has no pivot
+    /// field, so pivots are left out, and turns rotated regions
+    /// counter-clockwise.
+    Libgdx,
+    /// One CSV row per sprite with a header row.
+    Csv,
+}
+
+impl SheetFormat {
+    /// Extension of metadata files in this format.
+    pub fn extension(self) -> &'static str {
+        match self {
+            SheetFormat::JsonHash | SheetFormat::JsonArray | SheetFormat::Phaser => "json",
+            SheetFormat::Libgdx => "atlas",
+            SheetFormat::Csv => "csv",
+        }
+    }
+
+    /// Which way readers of this format expect rotated sprites to be
+    /// turned; the atlas must be built with it.
+    pub fn rotation(self) -> Rotation {
+        match self {
+            SheetFormat::Libgdx => Rotation::CounterClockwise,
+            _ => Rotation::Clockwise,
+        }
+    }
+
+    /// Whether each page gets its own metadata file.
+    pub fn per_page(self) -> bool {
+        matches!(self, SheetFormat::JsonHash | SheetFormat::JsonArray)
+    }
+}
+
+impl fmt::Display for SheetFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SheetFormat::JsonHash => "json-hash",
+            SheetFormat::JsonArray => "json-array",
+            SheetFormat::Phaser => "phaser",
+            SheetFormat::Libgdx => "libgdx",
+            SheetFormat::Csv => "csv",
+        })
+    }
+}
+
+impl FromStr for SheetFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "json-hash" | "json" | "pixi" => Ok(SheetFormat::JsonHash),
+            "json-array" => Ok(SheetFormat::JsonArray),
+            "phaser" => Ok(SheetFormat::Phaser),
+            "libgdx" | "gdx" => Ok(SheetFormat::Libgdx),
+            "csv" => Ok(SheetFormat::Csv),
+            _ => Err(Error::UnknownSheetFormat(s.to_string())),
+        }
+    }
+}
+
+/// Settings for sprite sheet metadata.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SheetOptions {
+    pub format: SheetFormat,
+    /// Where the metadata is written; defaults to [`sheet_path`] of the
+    /// output, or of each page for per-page formats.
+    pub file: Option<PathBuf>,
+    /// Pivot of every sprite as a fraction of its source size, from the
+    /// top left.
+    pub pivot: (f64, f64),
+}
+
+impl Default for SheetOptions {
+    fn default() -> Self {
+        Self {
+            format: SheetFormat::default(),
+            file: None,
+            pivot: (0.5, 0.5),
+        }
+    }
+}
+
+/// Where the metadata of a sheet written to `path` goes by default: the
+/// same name with the format's extension.
+pub fn sheet_path(path: &Path, format: SheetFormat) -> PathBuf {
+    path.with_extension(format.extension())
+}
+
+/// Renders metadata for `atlas`, whose pages are stored in the files named
+/// by `images`. Per-page formats give one document per page, stored in the
+/// [`page_path`]s of `name`, the others a single document. `layout` names
+/// the pixel format in formats that record one.
+pub fn render(
+    atlas: &Atlas,
+    images: &[String],
+    name: &str,
+    layout: &Layout,
+    options: &SheetOptions,
+) -> Vec<String> {
+    let sheet = Sheet {
+        atlas,
+        images,
+        name,
+        format: layout.to_string(),
+        pixmap_format: pixmap_format(layout),
+        options,
+    };
+    match options.format {
+        SheetFormat::JsonHash | SheetFormat::JsonArray => (0..images.len())
+            .map(|page| json_document(&sheet.texture_packer(page)))
+            .collect(),
+        SheetFormat::Phaser => vec![json_document(&sheet.phaser())],
+        SheetFormat::Libgdx => vec![text_document(|out| sheet.libgdx(out))],
+        SheetFormat::Csv => vec![text_document(|out| sheet.csv(out))],
+    }
+}
+
+/// An atlas with the names of its page files, being rendered.
+struct Sheet<'a> {
+    atlas: &'a Atlas,
+    images: &'a [String],
+    /// File name of the metadata, numbered per page by [`page_path`].
+    name: &'a str,
+    /// Pixel format recorded in JSON metadata.
+    format: String,
+    /// Nearest libGDX `Pixmap.Format` to the pixel format.
+    pixmap_format: &'static str,
+    options: &'a SheetOptions,
+}
+
+impl Sheet<'_> {
+    fn on_page(&self, page: usize) -> impl Iterator<Item = &Placement> {
+        self.atlas.placements.iter().filter(move |p| p.page == page)
+    }
+
+    fn size(&self, page: usize) -> (u32, u32) {
+        self.atlas.pages[page].dimensions()
+    }
+
+    /// One TexturePacker JSON document for a single page.
+    fn texture_packer(&self, page: usize) -> TexturePackerJson<'_> {
+        let frames = self.frames(page);
+        let (w, h) = self.size(page);
+        // PixiJS follows these to load the other pages.
+        let related_multi_packs = (0..self.images.len())
+            .filter(|&i| i != page)
+            .map(|i| {
+                let path = page_path(Path::new(self.name), i, self.images.len());
+                path.to_string_lossy().into_owned()
+            })
+            .collect();
+        TexturePackerJson {
+            frames: match self.options.format {
+                SheetFormat::JsonHash => JsonFrames::Hash(frames),
+                _ => JsonFrames::Array(frames),
+            },
+            meta: TexturePackerMeta {
+                app: APP,
+                image: &self.images[page],
+                format: &self.format,
+                size: Size { w, h },
+                scale: "1",
+                related_multi_packs,
+            },
+        }
+    }
+
+    /// A Phaser 3 multi-atlas holding every page.
+    fn phaser(&self) -> PhaserJson<'_> {
+        let textures = self
+            .images
+            .iter()
+            .enumerate()
+            .map(|(page, image)| {
+                let (w, h) = self.size(page);
+                PhaserTexture {
+                    image,
+                    format: &self.format,
+                    size: Size { w, h },
+                    scale: 1,
+                    frames: self.frames(page),
+                }
+            })
+            .collect();
+        PhaserJson {
+            textures,
+            meta: APP,
+        }
+    }
+
+    /// The fields TexturePacker and Phaser give each frame on `page`.
+    /// Frame sizes are before rotation; the frame covers them swapped when
+    /// `rotated`.
+    fn frames(&self, page: usize) -> Vec<NamedFrame<'_>> {
+        let (pivot_x, pivot_y) = self.options.pivot;
+        self.on_page(page)
+            .map(|p| NamedFrame {
+                filename: &p.name,
+                frame: JsonFrame {
+                    frame: Rect {
+                        x: p.x,
+                        y: p.y,
+                        w: p.width,
+                        h: p.height,
+                    },
+                    rotated: p.rotated,
+                    trimmed: p.trimmed(),
+                    sprite_source_size: Rect {
+                        x: p.offset_x,
+                        y: p.offset_y,
+                        w: p.width,
+                        h: p.height,
+                    },
+                    source_size: Size {
+                        w: p.source_width,
+                        h: p.source_height,
+                    },
+                    pivot: Point {
+                        x: pivot_x,
+                        y: pivot_y,
+                    },
+                },
+            })
+            .collect()
+    }
+
+    /// A libGDX atlas holding every page.
+    fn libgdx(&self, out: &mut String) -> fmt::Result {
+        for (page, image) in self.images.iter().enumerate() {
+            let (width, height) = self.size(page);
+            writeln!(out)?;
+            writeln!(out, "{image}")?;
+            writeln!(out, "size: {width},{height}")?;
+            writeln!(out, "format: {}", self.pixmap_format)?;
+            writeln!(out, "filter: Nearest,Nearest")?;
+            writeln!(out, "repeat: none")?;
+            for p in self.on_page(page) {
+                // libGDX measures offsets from the bottom left.
+                let offset_y = p.source_height - p.offset_y - p.height;
+                // libGDX names regions without the file extension.
+                writeln!(out, "{}", Path::new(&p.name).with_extension("").display())?;
+                writeln!(out, "  rotate: {}", p.rotated)?;
+                writeln!(out, "  xy: {}, {}", p.x, p.y)?;
+                writeln!(out, "  size: {}, {}", p.width, p.height)?;
+                writeln!(out, "  orig: {}, {}", p.source_width, p.source_height)?;
+                writeln!(out, "  offset: {}, {offset_y}", p.offset_x)?;
+                writeln!(out, "  index: -1")?;
+            }
+        }
+        Ok(())
+    }
+
+    /// One CSV row per sprite, in input order.
+    fn csv(&self, out: &mut String) -> fmt::Result {
+        writeln!(
+            out,
+            "name,image,x,y,width,height,rotated,trimmed,offset_x,offset_y,\
+             source_width,source_height,pivot_x,pivot_y"
+        )?;
+        let (pivot_x, pivot_y) = self.options.pivot;
+        for p in &self.atlas.placements {
+            writeln!(
+                out,
+                "{},{},{},{},{},{},{},{},{},{},{},{},{pivot_x},{pivot_y}",
+                csv_field(&p.name),
+                csv_field(&self.images[p.page]),
+                p.x,
+                p.y,
+                p.width,
+                p.height,
+                p.rotated,
+                p.trimmed(),
+                p.offset_x,
+                p.offset_y,
+                p.source_width,
+                p.source_height,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// A TexturePacker JSON document for one page.
+#[derive(Serialize)]
+struct TexturePackerJson<'a> {
+    frames: JsonFrames<'a>,
+    meta: TexturePackerMeta<'a>,
+}
+
+/// Frames keyed by name, or in an array naming each one.
+#[derive(Serialize)]
+#[serde(untagged)]
+enum JsonFrames<'a> {
+    #[serde(serialize_with = "frame_hash")]
+    Hash(Vec<NamedFrame<'a>>),
+    Array(Vec<NamedFrame<'a>>),
+}
+
+/// Writes frames as an object keyed by name, in atlas order.
+fn frame_hash<S: Serializer>(frames: &[NamedFrame<'_>], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_map(frames.iter().map(|f| (f.filename, &f.frame)))
+}
+
+#[derive(Serialize)]
+struct NamedFrame<'a> {
+    filename: &'a str,
+    #[serde(flatten)]
+    frame: JsonFrame,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonFrame {
+    frame: Rect,
+    rotated: bool,
+    trimmed: bool,
+    sprite_source_size: Rect,
+    source_size: Size,
+    pivot: Point,
+}
+
+#[derive(Serialize)]
+struct TexturePackerMeta<'a> {
+    #[serde(flatten)]
+    app: App,
+    image: &'a str,
+    format: &'a str,
+    size: Size,
+    scale: &'static str,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    related_multi_packs: Vec<String>,
+}
+
+/// A Phaser 3 multi-atlas.
+#[derive(Serialize)]
+struct PhaserJson<'a> {
+    textures: Vec<PhaserTexture<'a>>,
+    meta: App,
+}
+
+#[derive(Serialize)]
+struct PhaserTexture<'a> {
+    image: &'a str,
+    format: &'a str,
+    size: Size,
+    scale: u32,
+    frames: Vec<NamedFrame<'a>>,
+}
+
+/// The `app` and `version` fields of the `meta` object.
+#[derive(Serialize)]
+struct App {
+    app: &'static str,
+    version: &'static str,
+}
+
+const APP: App = App {
+    app: "image-packer",
+    version: env!("CARGO_PKG_VERSION"),
+};
+
+/// A rectangle as JSON metadata gives it.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub(crate) struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub w: u32,
+    pub h: u32,
+}
+
+/// A size as JSON metadata gives it.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub(crate) struct Size {
+    pub w: u32,
+    pub h: u32,
+}
+
+#[derive(Serialize)]
+struct Point {
+    x: f64,
+    y: f64,
+}
+
+fn json_document(document: &impl Serialize) -> String {
+    let mut out = serde_json::to_string_pretty(document).expect("metadata always serializes");
+    out.push('\n');
+    out
+}
+
+/// The smallest libGDX `Pixmap.Format` holding every channel of `layout`
+/// at its full depth, which libGDX converts the page to when loading it.
+/// Luminance counts as all three colour channels.
+fn pixmap_format(layout: &Layout) -> &'static str {
+    let bits = |channel: Option<Channel>| channel.or(layout.l).map_or(0, |c| c.bits);
+    let (r, g, b) = (bits(layout.r), bits(layout.g), bits(layout.b));
+    let a = layout.a.map_or(0, |c| c.bits);
+    [
+        ("RGB565", [5, 6, 5, 0]),
+        ("RGBA4444", [4, 4, 4, 4]),
+        ("RGB888", [8, 8, 8, 0]),
+    ]
+    .into_iter()
+    .find(|(_, depth)| {
+        [r, g, b, a]
+            .iter()
+            .zip(depth)
+            .all(|(have, fits)| have <= fits)
+    })
+    .map_or("RGBA8888", |(name, _)| name)
+}
+
+fn text_document(write: impl FnOnce(&mut String) -> fmt::Result) -> String {
+    let mut out = String::new();
+    write(&mut out).expect("writing to a String cannot fail");
+    out
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
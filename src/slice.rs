@@ -0,0 +1,476 @@
+//! Slicing sprite sheets back into frames, by a regular grid or by the
+//! metadata written with an atlas.
+
+use std::path::{Component, Path, PathBuf};
+
+use image::{RgbaImage, imageops};
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use crate::sheet::{Rect, Size};
+use crate::{Error, Placement, Result, Rotation, SheetFormat};
+
+/// A regular grid of equally sized cells.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Grid {
+    pub cell_width: u32,
+    pub cell_height: u32,
+    /// Pixels between the image edge and the outer cells.
+    pub margin: u32,
+    /// Pixels between neighbouring cells.
+    pub spacing: u32,
+}
+
+/// Where the frames of a sheet are found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FrameSource {
+    /// Cells of a regular grid.
+    Grid(Grid),
+    /// Frames listed in a metadata file written by [`SheetFormat`] or a
+    /// tool using one of its formats.
+    Sheet(PathBuf),
+}
+
+/// Settings for slicing a sheet into frames.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SliceOptions {
+    pub frames: FrameSource,
+    /// Lay the frames out again in a grid this many columns wide, written
+    /// as one image, instead of writing a file per frame.
+    pub relayout: Option<u32>,
+}
+
+/// Frames listed by a metadata file, with the page images they name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SheetFrames {
+    /// Page file names, indexed by [`Placement::page`].
+    pub images: Vec<String>,
+    pub frames: Vec<Placement>,
+    /// Which way the format turns rotated frames.
+    pub rotation: Rotation,
+}
+
+/// The frames of a `width` by `height` image cut by `grid`, row by row,
+/// named by their index. Partial cells at the right and bottom are left out.
+pub fn grid_frames(width: u32, height: u32, grid: &Grid) -> Result<Vec<Placement>> {
+    // Worked out in u64 so that no margin, spacing or cell size overflows.
+    let (margin, spacing) = (u64::from(grid.margin), u64::from(grid.spacing));
+    let count = |size: u32, cell: u32| match u64::from(cell) {
+        0 => 0,
+        cell => (u64::from(size).saturating_sub(2 * margin) + spacing) / (cell + spacing),
+    };
+    let (columns, rows) = (
+        count(width, grid.cell_width),
+        count(height, grid.cell_height),
+    );
+    if columns == 0 || rows == 0 {
+        return Err(Error::EmptyGrid {
+            width: grid.cell_width,
+            height: grid.cell_height,
+        });
+    }
+    // Every counted cell lies inside the image, so its corner fits a u32.
+    let corner = |index: u64, cell: u32| (margin + index * (u64::from(cell) + spacing)) as u32;
+    Ok((0..rows * columns)
+        .map(|i| {
+            let (column, row) = (i % columns, i / columns);
+            Placement {
+                name: i.to_string(),
+                page: 0,
+                x: corner(column, grid.cell_width),
+                y: corner(row, grid.cell_height),
+                width: grid.cell_width,
+                height: grid.cell_height,
+                rotated: false,
+                offset_x: 0,
+                offset_y: 0,
+                source_width: grid.cell_width,
+                source_height: grid.cell_height,
+            }
+        })
+        .collect())
+}
+
+/// Which format a metadata file at `path` holding `text` is in: libGDX and
+/// CSV by extension, JSON flavours by their structure.
+pub fn detect_format(path: &Path, text: &str) -> SheetFormat {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("atlas") => SheetFormat::Libgdx,
+        Some("csv") => SheetFormat::Csv,
+        _ => match serde_json::from_str::<Value>(text) {
+            Ok(json) if json.get("textures").is_some() => SheetFormat::Phaser,
+            Ok(json) if json.get("frames").is_some_and(Value::is_array) => SheetFormat::JsonArray,
+            _ => SheetFormat::JsonHash,
+        },
+    }
+}
+
+/// Reads the frames of sprite sheet metadata in `format`.
+pub fn parse_sheet(text: &str, format: SheetFormat) -> Result<SheetFrames> {
+    match format {
+        SheetFormat::JsonHash | SheetFormat::JsonArray => {
+            let json: TexturePackerJson = parse_json(text)?;
+            Ok(SheetFrames {
+                images: vec![json.meta.image],
+                frames: json_frames(json.frames, 0)?,
+                rotation: format.rotation(),
+            })
+        }
+        SheetFormat::Phaser => {
+            let json: PhaserJson = parse_json(text)?;
+            let mut sheet = SheetFrames {
+                images: Vec::new(),
+                frames: Vec::new(),
+                rotation: format.rotation(),
+            };
+            for (page, texture) in json.textures.into_iter().enumerate() {
+                sheet.images.push(texture.image);
+                sheet.frames.extend(json_frames(texture.frames, page)?);
+            }
+            Ok(sheet)
+        }
+        SheetFormat::Libgdx => parse_libgdx(text),
+        SheetFormat::Csv => parse_csv(text),
+    }
+}
+
+/// File of a frame named `name` under `out_dir`, with `extension`. Only
+/// the plain components of the name are kept, so names from metadata
+/// cannot point outside the directory.
+pub fn frame_path(out_dir: &Path, name: &str, extension: &str) -> PathBuf {
+    let relative: PathBuf = Path::new(name)
+        .components()
+        .filter(|c| matches!(c, Component::Normal(_)))
+        .collect();
+    out_dir.join(relative).with_extension(extension)
+}
+
+/// How many times the page area a frame's source size may cover, counting
+/// pages smaller than [`MIN_SOURCE_LIMIT`] pixels as that size.
+const MAX_SOURCE_SCALE: u64 = 16;
+const MIN_SOURCE_LIMIT: u64 = 1024 * 1024;
+
+/// Cuts a frame out of its page, turns it back if it was rotated in
+/// `rotation` and puts it at its offset in a transparent image of its
+/// source size, undoing any trimming.
+pub fn extract_frame(page: &RgbaImage, frame: &Placement, rotation: Rotation) -> Result<RgbaImage> {
+    let (w, h) = match frame.rotated {
+        true => (frame.height, frame.width),
+        false => (frame.width, frame.height),
+    };
+    let fits = |at: u32, size: u32, page: u32| at.checked_add(size).is_some_and(|end| end <= page);
+    if !fits(frame.x, w, page.width()) || !fits(frame.y, h, page.height()) {
+        return Err(invalid(format!(
+            "frame `{}` at {},{} runs off the {}x{} page",
+            frame.name,
+            frame.x,
+            frame.y,
+            page.width(),
+            page.height()
+        )));
+    }
+    if !fits(frame.offset_x, frame.width, frame.source_width)
+        || !fits(frame.offset_y, frame.height, frame.source_height)
+    {
+        return Err(invalid(format!(
+            "frame `{}` at offset {},{} does not fit its {}x{} source size",
+            frame.name, frame.offset_x, frame.offset_y, frame.source_width, frame.source_height
+        )));
+    }
+    // A trimmed sprite's source is seldom much bigger than the page it was
+    // packed on; rather than trust the metadata with the allocation, refuse
+    // one that is.
+    let page_area = u64::from(page.width()) * u64::from(page.height());
+    let source_area = u64::from(frame.source_width) * u64::from(frame.source_height);
+    if source_area > MAX_SOURCE_SCALE * page_area.max(MIN_SOURCE_LIMIT) {
+        return Err(invalid(format!(
+            "frame `{}` has a {}x{} source size, far larger than its {}x{} page",
+            frame.name,
+            frame.source_width,
+            frame.source_height,
+            page.width(),
+            page.height()
+        )));
+    }
+    let cut = imageops::crop_imm(page, frame.x, frame.y, w, h).to_image();
+    let cut = match (frame.rotated, rotation) {
+        (true, Rotation::Clockwise) => imageops::rotate270(&cut),
+        (true, Rotation::CounterClockwise) => imageops::rotate90(&cut),
+        (false, _) => cut,
+    };
+    let mut out = RgbaImage::new(frame.source_width, frame.source_height);
+    imageops::replace(&mut out, &cut, frame.offset_x as i64, frame.offset_y as i64);
+    Ok(out)
+}
+
+/// Lays frames out left to right in rows of `columns`, each at the top
+/// left of a cell the size of the largest frame.
+pub fn relayout(frames: &[RgbaImage], columns: u32) -> RgbaImage {
+    let columns = columns.max(1);
+    let cell_width = frames.iter().map(RgbaImage::width).max().unwrap_or(0);
+    let cell_height = frames.iter().map(RgbaImage::height).max().unwrap_or(0);
+    let rows = (frames.len() as u32).div_ceil(columns);
+    let mut out = RgbaImage::new(
+        cell_width * columns.min(frames.len() as u32),
+        cell_height * rows,
+    );
+    for (i, frame) in frames.iter().enumerate() {
+        let (column, row) = (i as u32 % columns, i as u32 / columns);
+        let (x, y) = (column * cell_width, row * cell_height);
+        imageops::replace(&mut out, frame, x as i64, y as i64);
+    }
+    out
+}
+
+fn invalid(reason: impl Into<String>) -> Error {
+    Error::InvalidFile {
+        format: "sprite sheet",
+        reason: reason.into(),
+    }
+}
+
+/// A TexturePacker JSON document.
+#[derive(Deserialize)]
+struct TexturePackerJson {
+    frames: Value,
+    #[serde(default)]
+    meta: JsonMeta,
+}
+
+#[derive(Default, Deserialize)]
+struct JsonMeta {
+    #[serde(default)]
+    image: String,
+}
+
+/// A Phaser 3 multi-atlas.
+#[derive(Deserialize)]
+struct PhaserJson {
+    textures: Vec<PhaserTexture>,
+}
+
+#[derive(Deserialize)]
+struct PhaserTexture {
+    #[serde(default)]
+    image: String,
+    frames: Value,
+}
+
+/// A frame of a TexturePacker or Phaser `frames` hash or array; the hash
+/// names it by its key instead of `filename`.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonFrame {
+    #[serde(default)]
+    filename: String,
+    frame: Rect,
+    #[serde(default)]
+    rotated: bool,
+    sprite_source_size: Option<Rect>,
+    source_size: Option<Size>,
+}
+
+fn parse_json<T: DeserializeOwned>(text: &str) -> Result<T> {
+    serde_json::from_str(text).map_err(|e| invalid(e.to_string()))
+}
+
+/// Frames of a TexturePacker or Phaser `frames` hash or array. The frames
+/// are decoded one by one so errors can name the frame.
+fn json_frames(frames: Value, page: usize) -> Result<Vec<Placement>> {
+    let entries: Vec<(Option<String>, Value)> = match frames {
+        Value::Object(fields) => fields.into_iter().map(|(k, v)| (Some(k), v)).collect(),
+        Value::Array(items) => items.into_iter().map(|v| (None, v)).collect(),
+        _ => return Err(invalid("`frames` is not an object or array")),
+    };
+    entries
+        .into_iter()
+        .enumerate()
+        .map(|(i, (key, entry))| {
+            let frame: JsonFrame = serde_json::from_value(entry).map_err(|e| {
+                let name = key
+                    .as_deref()
+                    .map_or(format!("frame {i}"), |k| format!("frame `{k}`"));
+                invalid(format!("{name}: {e}"))
+            })?;
+            let Rect {
+                x,
+                y,
+                w: width,
+                h: height,
+            } = frame.frame;
+            let trim = frame.sprite_source_size;
+            let source = frame.source_size;
+            Ok(Placement {
+                name: key.unwrap_or(frame.filename),
+                page,
+                x,
+                y,
+                width,
+                height,
+                rotated: frame.rotated,
+                offset_x: trim.map_or(0, |t| t.x),
+                offset_y: trim.map_or(0, |t| t.y),
+                source_width: source.map_or(width, |s| s.w),
+                source_height: source.map_or(height, |s| s.h),
+            })
+        })
+        .collect()
+}
+
+/// Reads a libGDX atlas in the classic layout (`xy`, `size`, `orig`,
+/// `offset`) or the newer one (`bounds`, `offsets`).
+fn parse_libgdx(text: &str) -> Result<SheetFrames> {
+    let mut sheet = SheetFrames {
+        images: Vec::new(),
+        frames: Vec::new(),
+        rotation: SheetFormat::Libgdx.rotation(),
+    };
+    let mut in_page = false;
+    let mut region: Option<Placement> = None;
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() {
+            // A blank line ends the page; the next line names another.
+            finish_region(&mut sheet, region.take());
+            in_page = false;
+            continue;
+        }
+        if !in_page {
+            sheet.images.push(line.to_string());
+            in_page = true;
+            continue;
+        }
+        let Some((key, value)) = line.split_once(':') else {
+            finish_region(&mut sheet, region.take());
+            region = Some(Placement {
+                name: line.to_string(),
+                page: sheet.images.len() - 1,
+                x: 0,
+                y: 0,
+                width: 0,
+                height: 0,
+                rotated: false,
+                offset_x: 0,
+                offset_y: 0,
+                source_width: 0,
+                source_height: 0,
+            });
+            continue;
+        };
+        // Fields before the first region describe the page.
+        let Some(frame) = region.as_mut() else {
+            continue;
+        };
+        let value = value.trim();
+        let numbers: Vec<u32> = value
+            .split(',')
+            .filter_map(|v| v.trim().parse().ok())
+            .collect();
+        match (key.trim(), numbers.as_slice()) {
+            ("rotate", _) => frame.rotated = value == "true" || value == "90",
+            ("xy", &[x, y]) => (frame.x, frame.y) = (x, y),
+            ("size", &[w, h]) => (frame.width, frame.height) = (w, h),
+            ("orig", &[w, h]) => (frame.source_width, frame.source_height) = (w, h),
+            ("offset", &[x, y]) => (frame.offset_x, frame.offset_y) = (x, y),
+            ("bounds", &[x, y, w, h]) => {
+                (frame.x, frame.y, frame.width, frame.height) = (x, y, w, h)
+            }
+            ("offsets", &[x, y, w, h]) => {
+                (frame.offset_x, frame.offset_y) = (x, y);
+                (frame.source_width, frame.source_height) = (w, h);
+            }
+            // Frames of an animation share a name and differ by index; -1,
+            // for a lone region, does not parse.
+            ("index", &[index]) => frame.name = format!("{}_{index}", frame.name),
+            _ => {}
+        }
+    }
+    finish_region(&mut sheet, region);
+    Ok(sheet)
+}
+
+/// Adds a complete libGDX region, filling in the source size of untrimmed
+/// regions and flipping its offset, which libGDX counts from the bottom.
+fn finish_region(sheet: &mut SheetFrames, region: Option<Placement>) {
+    let Some(mut frame) = region else {
+        return;
+    };
+    if frame.source_width == 0 && frame.source_height == 0 {
+        (frame.source_width, frame.source_height) = (frame.width, frame.height);
+    }
+    frame.offset_y = frame
+        .source_height
+        .saturating_sub(frame.offset_y.saturating_add(frame.height));
+    sheet.frames.push(frame);
+}
+
+/// Reads CSV with a header row naming at least `name`, `x`, `y`, `width`
+/// and `height`, as written by [`SheetFormat::Csv`].
+fn parse_csv(text: &str) -> Result<SheetFrames> {
+    let mut lines = text.lines().filter(|line| !line.trim().is_empty());
+    let header = csv_row(lines.next().unwrap_or_default());
+    let column = |name: &str| header.iter().position(|h| h == name);
+    let required = |name: &str| column(name).ok_or_else(|| invalid(format!("no `{name}` column")));
+    let (name, x, y, width, height) = (
+        required("name")?,
+        required("x")?,
+        required("y")?,
+        required("width")?,
+        required("height")?,
+    );
+    let mut sheet = SheetFrames {
+        images: Vec::new(),
+        frames: Vec::new(),
+        rotation: SheetFormat::Csv.rotation(),
+    };
+    for line in lines {
+        let row = csv_row(line);
+        let text = |i: Option<usize>| i.and_then(|i| row.get(i)).map(String::as_str);
+        let number = |i: Option<usize>| text(i).and_then(|v| v.parse::<u32>().ok());
+        let need = |i: usize| {
+            number(Some(i)).ok_or_else(|| invalid(format!("bad number in row `{line}`")))
+        };
+        let image = text(column("image")).unwrap_or_default();
+        let page = match sheet.images.iter().position(|i| i == image) {
+            Some(page) => page,
+            None => {
+                sheet.images.push(image.to_string());
+                sheet.images.len() - 1
+            }
+        };
+        let (w, h) = (need(width)?, need(height)?);
+        sheet.frames.push(Placement {
+            name: text(Some(name)).unwrap_or_default().to_string(),
+            page,
+            x: need(x)?,
+            y: need(y)?,
+            width: w,
+            height: h,
+            rotated: text(column("rotated")) == Some("true"),
+            offset_x: number(column("offset_x")).unwrap_or(0),
+            offset_y: number(column("offset_y")).unwrap_or(0),
+            source_width: number(column("source_width")).unwrap_or(w),
+            source_height: number(column("source_height")).unwrap_or(h),
+        });
+    }
+    Ok(sheet)
+}
+
+/// Splits one CSV line, honouring double-quoted fields.
+fn csv_row(line: &str) -> Vec<String> {
+    let mut fields = vec![String::new()];
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, quoted) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                chars.next();
+                fields.last_mut().unwrap().push('"');
+            }
+            ('"', _) => quoted = !quoted,
+            (',', false) => fields.push(String::new()),
+            (c, _) => fields.last_mut().unwrap().push(c),
+        }
+    }
+    fields
+}
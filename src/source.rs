@@ -0,0 +1,408 @@
+use std::fmt::{self, Write};
+
+use crate::Layout;
+use crate::palette::Indexed;
+use crate::tiles::{TILE_SIZE, TileFormat, Tiled};
+
+/// Language of a generated source array.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Language {
+    /// A C header with `static const uint16_t name[]` and size defines.
+    C,
+    /// A C++ header with `inline constexpr` arrays.
+    Cpp,
+    /// A Rust module with a `pub static` array.
+    Rust,
+    /// A GNU assembler listing using `.byte`, `.hword` or `.word`.
+    Asm,
+}
+
+impl Language {
+    pub fn name(self) -> &'static str {
+        match self {
+            Language::C => "c",
+            Language::Cpp => "cpp",
+            Language::Rust => "rust",
+            Language::Asm => "asm",
+        }
+    }
+}
+
+/// Settings for source array output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceOptions {
+    /// Array symbol; defaults to the output file name.
+    pub symbol: Option<String>,
+    /// Alignment of the array in bytes; must be a power of two.
+    pub align: Option<u32>,
+    /// Number of values written per line.
+    pub per_line: usize,
+}
+
+impl Default for SourceOptions {
+    fn default() -> Self {
+        Self {
+            symbol: None,
+            align: None,
+            per_line: 16,
+        }
+    }
+}
+
+/// Turns an arbitrary name, such as a file stem, into a valid identifier.
+pub fn sanitize_symbol(name: &str) -> String {
+    let mut symbol: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if !symbol.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        symbol.insert(0, '_');
+    }
+    symbol
+}
+
+/// Turns a symbol into a Rust type name, `TITLE_SCREEN` into `TitleScreen`,
+/// so generated types do not trip `non_camel_case_types`.
+fn camel_case(symbol: &str) -> String {
+    let mut name: String = symbol
+        .split('_')
+        .flat_map(|part| {
+            let mut chars = part.chars();
+            let first = chars.next().map(|c| c.to_ascii_uppercase());
+            first
+                .into_iter()
+                .chain(chars.map(|c| c.to_ascii_lowercase()))
+        })
+        .collect();
+    if !name.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        name.insert(0, '_');
+    }
+    name
+}
+
+/// Renders row-major packed values as a source array named `symbol`.
+pub fn render(
+    language: Language,
+    symbol: &str,
+    layout: &Layout,
+    width: u32,
+    height: u32,
+    packed: &[u32],
+    options: &SourceOptions,
+) -> String {
+    let source = Source {
+        symbol,
+        width,
+        height,
+        description: format!("{width}x{height} {layout} pixels"),
+        arrays: vec![Array {
+            suffix: "",
+            bits: layout.bits,
+            len: None,
+            values: packed.to_vec(),
+        }],
+        options,
+    };
+    source.render(language)
+}
+
+/// Renders a paletted image as two arrays: the indices named `symbol`, two
+/// to a byte for 4-bit indices, and the packed palette `symbol_palette`.
+pub fn render_indexed(
+    language: Language,
+    symbol: &str,
+    layout: &Layout,
+    indexed: &Indexed,
+    options: &SourceOptions,
+) -> String {
+    let indices: Vec<u32> = indexed.index_bytes().into_iter().map(u32::from).collect();
+    let source = Source {
+        symbol,
+        width: indexed.width,
+        height: indexed.height,
+        description: format!(
+            "{}x{} {}-bit indices into a {}-entry {layout} palette",
+            indexed.width,
+            indexed.height,
+            indexed.index_bits,
+            indexed.palette.len()
+        ),
+        arrays: vec![
+            Array {
+                suffix: "",
+                bits: 8,
+                len: (indexed.index_bits == 4).then_some(indices.len()),
+                values: indices,
+            },
+            Array {
+                suffix: "_palette",
+                bits: layout.bits,
+                len: Some(indexed.palette.len()),
+                values: indexed.palette.clone(),
+            },
+        ],
+        options,
+    };
+    source.render(language)
+}
+
+/// Renders 8x8 tiles as a tileset `symbol_tiles`, a tilemap `symbol_map`
+/// and, for indexed tiles, the packed palette `symbol_palette`. Indexed
+/// tiles are stored as `format` lays them out in memory.
+pub fn render_tiled(
+    language: Language,
+    symbol: &str,
+    layout: &Layout,
+    tiled: &Tiled,
+    indexed: Option<&Indexed>,
+    format: TileFormat,
+    options: &SourceOptions,
+) -> String {
+    let pixels = (TILE_SIZE * TILE_SIZE) as usize;
+    let (width, height) = (tiled.width, tiled.height);
+    let count = tiled.tile_count();
+    let mut arrays = Vec::new();
+    let description = match indexed {
+        Some(indexed) => {
+            let tiles: Vec<u32> = tiled
+                .tiles
+                .chunks(pixels)
+                .flat_map(|tile| format.encode_indices(tile, indexed.index_bits))
+                .map(u32::from)
+                .collect();
+            arrays.push(Array {
+                suffix: "_tiles",
+                bits: 8,
+                len: Some(tiles.len()),
+                values: tiles,
+            });
+            format!(
+                "{width}x{height} {format} tiles, {count} unique, of {}-bit indices into a {}-entry {layout} palette",
+                indexed.index_bits,
+                indexed.palette.len()
+            )
+        }
+        None => {
+            arrays.push(Array {
+                suffix: "_tiles",
+                bits: layout.bits,
+                len: Some(tiled.tiles.len()),
+                values: tiled.tiles.clone(),
+            });
+            format!("{width}x{height} {format} tiles, {count} unique, of {layout} pixels")
+        }
+    };
+    arrays.push(Array {
+        suffix: "_map",
+        bits: 16,
+        len: Some(tiled.map.len()),
+        values: tiled.map.iter().map(|&entry| entry as u32).collect(),
+    });
+    if let Some(indexed) = indexed {
+        arrays.push(Array {
+            suffix: "_palette",
+            bits: layout.bits,
+            len: Some(indexed.palette.len()),
+            values: indexed.palette.clone(),
+        });
+    }
+    let source = Source {
+        symbol,
+        width,
+        height,
+        description,
+        arrays,
+        options,
+    };
+    source.render(language)
+}
+
+struct Source<'a> {
+    symbol: &'a str,
+    width: u32,
+    height: u32,
+    description: String,
+    arrays: Vec<Array>,
+    options: &'a SourceOptions,
+}
+
+/// One array of a [`Source`], named by appending `suffix` to the symbol.
+struct Array {
+    suffix: &'static str,
+    bits: u32,
+    /// Number of values, when it is not one per pixel.
+    len: Option<usize>,
+    values: Vec<u32>,
+}
+
+impl Source<'_> {
+    fn render(&self, language: Language) -> String {
+        let mut out = String::new();
+        match language {
+            Language::C => self.c(&mut out),
+            Language::Cpp => self.cpp(&mut out),
+            Language::Rust => self.rust(&mut out),
+            Language::Asm => self.asm(&mut out),
+        }
+        .expect("writing to a String cannot fail");
+        out
+    }
+
+    fn c(&self, out: &mut String) -> fmt::Result {
+        let upper = self.symbol.to_ascii_uppercase();
+        writeln!(out, "/* {} */", self.description())?;
+        writeln!(out, "#ifndef {upper}_H")?;
+        writeln!(out, "#define {upper}_H")?;
+        writeln!(out)?;
+        writeln!(out, "#include <stdint.h>")?;
+        writeln!(out)?;
+        writeln!(out, "#define {upper}_WIDTH {}", self.width)?;
+        writeln!(out, "#define {upper}_HEIGHT {}", self.height)?;
+        let align = match self.options.align {
+            Some(n) => format!(" __attribute__((aligned({n})))"),
+            None => String::new(),
+        };
+        for array in &self.arrays {
+            let len = match array.len {
+                Some(len) => len.to_string(),
+                None => format!("{upper}_WIDTH * {upper}_HEIGHT"),
+            };
+            writeln!(out)?;
+            writeln!(
+                out,
+                "static const uint{}_t {}{}[{len}]{align} = {{",
+                array.bits, self.symbol, array.suffix
+            )?;
+            self.values(out, array, "    ", ",")?;
+            writeln!(out, "}};")?;
+        }
+        writeln!(out)?;
+        writeln!(out, "#endif /* {upper}_H */")
+    }
+
+    fn cpp(&self, out: &mut String) -> fmt::Result {
+        let symbol = self.symbol;
+        writeln!(out, "// {}", self.description())?;
+        writeln!(out, "#pragma once")?;
+        writeln!(out)?;
+        writeln!(out, "#include <cstdint>")?;
+        writeln!(out)?;
+        writeln!(
+            out,
+            "inline constexpr std::uint32_t {symbol}_width = {};",
+            self.width
+        )?;
+        writeln!(
+            out,
+            "inline constexpr std::uint32_t {symbol}_height = {};",
+            self.height
+        )?;
+        let align = match self.options.align {
+            Some(n) => format!("alignas({n}) "),
+            None => String::new(),
+        };
+        for array in &self.arrays {
+            let len = match array.len {
+                Some(len) => len.to_string(),
+                None => format!("{symbol}_width * {symbol}_height"),
+            };
+            writeln!(out)?;
+            writeln!(
+                out,
+                "{align}inline constexpr std::uint{}_t {symbol}{}[{len}] = {{",
+                array.bits, array.suffix
+            )?;
+            self.values(out, array, "    ", ",")?;
+            writeln!(out, "}};")?;
+        }
+        Ok(())
+    }
+
+    fn rust(&self, out: &mut String) -> fmt::Result {
+        let upper = self.symbol.to_ascii_uppercase();
+        writeln!(out, "// {}", self.description())?;
+        writeln!(out)?;
+        writeln!(out, "pub const {upper}_WIDTH: usize = {};", self.width)?;
+        writeln!(out, "pub const {upper}_HEIGHT: usize = {};", self.height)?;
+        for array in &self.arrays {
+            let name = format!("{upper}{}", array.suffix.to_ascii_uppercase());
+            let ty = format!("[u{}; {}]", array.bits, array.values.len());
+            writeln!(out)?;
+            match self.options.align {
+                Some(n) => {
+                    writeln!(out, "#[repr(C, align({n}))]")?;
+                    let wrapper = format!("{}Aligned", camel_case(&name));
+                    writeln!(out, "pub struct {wrapper}(pub {ty});")?;
+                    writeln!(out)?;
+                    writeln!(out, "pub static {name}: {wrapper} = {wrapper}([")?;
+                    self.values(out, array, "    ", ",")?;
+                    writeln!(out, "]);")?;
+                }
+                None => {
+                    writeln!(out, "pub static {name}: {ty} = [")?;
+                    self.values(out, array, "    ", ",")?;
+                    writeln!(out, "];")?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn asm(&self, out: &mut String) -> fmt::Result {
+        let symbol = self.symbol;
+        writeln!(out, "/* {} */", self.description())?;
+        writeln!(out, "    .section .rodata")?;
+        writeln!(out, "    .global {symbol}_width")?;
+        writeln!(out, "    .equ {symbol}_width, {}", self.width)?;
+        writeln!(out, "    .global {symbol}_height")?;
+        writeln!(out, "    .equ {symbol}_height, {}", self.height)?;
+        for array in &self.arrays {
+            let name = format!("{symbol}{}", array.suffix);
+            let directive = match array.bits {
+                8 => ".byte",
+                16 => ".hword",
+                _ => ".word",
+            };
+            writeln!(out, "    .global {name}")?;
+            writeln!(
+                out,
+                "    .balign {}",
+                self.options.align.unwrap_or(array.bits / 8)
+            )?;
+            writeln!(out, "{name}:")?;
+            self.values(out, array, &format!("    {directive} "), "")?;
+            writeln!(out, "    .size {name}, . - {name}")?;
+        }
+        Ok(())
+    }
+
+    fn description(&self) -> String {
+        format!("{}, generated by image-packer", self.description)
+    }
+
+    /// Writes the values of `array` as hex, `per_line` to a line. Lines
+    /// start with `prefix`; values are followed by `separator`, or joined by
+    /// commas when it is empty.
+    fn values(
+        &self,
+        out: &mut String,
+        array: &Array,
+        prefix: &str,
+        separator: &str,
+    ) -> fmt::Result {
+        let digits = array.bits as usize / 4;
+        for line in array.values.chunks(self.options.per_line.max(1)) {
+            out.push_str(prefix);
+            for (i, v) in line.iter().enumerate() {
+                match (i, separator) {
+                    (0, _) => {}
+                    (_, "") => out.push_str(", "),
+                    _ => out.push(' '),
+                }
+                write!(out, "0x{v:0digits$X}{separator}")?;
+            }
+            out.push('\n');
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,271 @@
+//! GPU texture swizzles: reordering packed pixels into the memory layouts
+//! consoles and mobile GPUs sample from.
+//!
+//! Each swizzle pads the image to its block or page size and places every
+//! pixel at an address inside the padded buffer; padding is left zero.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::{Error, Result};
+
+/// A texture memory layout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Swizzle {
+    /// Morton or Z-order: the bits of x and y interleaved, x in the even
+    /// bits. Both sides are padded to powers of two; the longer side's
+    /// extra bits sit above the interleaved ones.
+    Morton,
+    /// PSP: blocks 16 bytes wide and 8 rows tall, stored one after another
+    /// row by row, each block's rows stored in order.
+    Psp,
+    /// PS2 GS memory for PSMCT32 and PSMCT16 textures: pages of blocks of
+    /// columns, as the GS reads them. 16 and 32-bit pixels only.
+    Ps2,
+    /// 3DS: 8x8 tiles stored row by row from the top, each tile in Morton
+    /// order.
+    Ctr,
+    /// Switch block-linear: 64-byte by 8-row GOBs stacked into blocks of up
+    /// to 16 GOBs, with the block height picked from the image height.
+    Switch,
+}
+
+impl Swizzle {
+    /// Width and height of the buffer holding a swizzled `width` by
+    /// `height` image of `bits`-per-pixel values.
+    pub fn padded_size(self, bits: u32, width: u32, height: u32) -> Result<(u32, u32)> {
+        let bpp = self.bytes_per_pixel(bits)?;
+        Ok(match self {
+            Swizzle::Morton => (width.next_power_of_two(), height.next_power_of_two()),
+            Swizzle::Psp => (width.next_multiple_of(16 / bpp), height.next_multiple_of(8)),
+            Swizzle::Ps2 => {
+                let (page_width, page_height) = ps2_page(bpp);
+                (
+                    width.next_multiple_of(page_width),
+                    height.next_multiple_of(page_height),
+                )
+            }
+            Swizzle::Ctr => (width.next_multiple_of(8), height.next_multiple_of(8)),
+            Swizzle::Switch => (
+                width.next_multiple_of(64 / bpp),
+                height.next_multiple_of(8 * switch_block_height(height)),
+            ),
+        })
+    }
+
+    /// Reorders row-major `values` of a `width` by `height` image into the
+    /// swizzled buffer, returning the buffer with its width and height.
+    pub fn swizzle(
+        self,
+        bits: u32,
+        width: u32,
+        height: u32,
+        values: &[u32],
+    ) -> Result<(u32, u32, Vec<u32>)> {
+        check_len(width as usize * height as usize, values.len())?;
+        let (padded_width, padded_height) = self.padded_size(bits, width, height)?;
+        let mut out = vec![0; padded_width as usize * padded_height as usize];
+        for (address, &value) in self.addresses(bits, width, height)?.zip(values) {
+            out[address] = value;
+        }
+        Ok((padded_width, padded_height, out))
+    }
+
+    /// Reverses [`Swizzle::swizzle`], reading the row-major values of a
+    /// `width` by `height` image out of a swizzled buffer.
+    pub fn unswizzle(self, bits: u32, width: u32, height: u32, values: &[u32]) -> Result<Vec<u32>> {
+        let (padded_width, padded_height) = self.padded_size(bits, width, height)?;
+        check_len(padded_width as usize * padded_height as usize, values.len())?;
+        Ok(self
+            .addresses(bits, width, height)?
+            .map(|address| values[address])
+            .collect())
+    }
+
+    /// Buffer index of every pixel in row-major order.
+    fn addresses(self, bits: u32, width: u32, height: u32) -> Result<impl Iterator<Item = usize>> {
+        let bpp = self.bytes_per_pixel(bits)?;
+        let (padded_width, _) = self.padded_size(bits, width, height)?;
+        let block_height = switch_block_height(height);
+        let pixels = (0..height).flat_map(move |y| (0..width).map(move |x| (x, y)));
+        Ok(pixels.map(move |(x, y)| {
+            let (x, y, padded_width) = (x as usize, y as usize, padded_width as usize);
+            match self {
+                Swizzle::Morton => morton_rect(x, y, width, height),
+                Swizzle::Psp => {
+                    let block_width = 16 / bpp as usize;
+                    let blocks_per_row = padded_width / block_width;
+                    let block = (y / 8) * blocks_per_row + x / block_width;
+                    (block * 8 + y % 8) * block_width + x % block_width
+                }
+                Swizzle::Ps2 => ps2_address(x, y, padded_width, bpp),
+                Swizzle::Ctr => {
+                    let tile = (y / 8) * (padded_width / 8) + x / 8;
+                    tile * 64 + morton(x % 8, y % 8)
+                }
+                Swizzle::Switch => {
+                    let bpp = bpp as usize;
+                    let x_bytes = x * bpp;
+                    let gobs_per_row = padded_width * bpp / 64;
+                    let block_rows = 8 * block_height as usize;
+                    let block_size = 512 * block_height as usize;
+                    let gob = ((x_bytes % 64) / 32) * 256
+                        + ((y % 8) / 2) * 64
+                        + ((x_bytes % 32) / 16) * 32
+                        + (y % 2) * 16
+                        + x_bytes % 16;
+                    let address = (y / block_rows) * block_size * gobs_per_row
+                        + (x_bytes / 64) * block_size
+                        + ((y % block_rows) / 8) * 512
+                        + gob;
+                    address / bpp
+                }
+            }
+        }))
+    }
+
+    fn bytes_per_pixel(self, bits: u32) -> Result<u32> {
+        match (self, bits) {
+            (Swizzle::Ps2, 16 | 32) => Ok(bits / 8),
+            (Swizzle::Ps2, _) => Err(Error::UnsupportedSwizzle {
+                swizzle: self,
+                bits,
+            }),
+            (_, 8 | 16 | 32) => Ok(bits / 8),
+            _ => Err(Error::UnsupportedSwizzle {
+                swizzle: self,
+                bits,
+            }),
+        }
+    }
+}
+
+impl fmt::Display for Swizzle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Swizzle::Morton => "morton",
+            Swizzle::Psp => "psp",
+            Swizzle::Ps2 => "ps2",
+            Swizzle::Ctr => "3ds",
+            Swizzle::Switch => "switch",
+        })
+    }
+}
+
+impl FromStr for Swizzle {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "morton" | "z-order" | "twiddle" => Ok(Swizzle::Morton),
+            "psp" => Ok(Swizzle::Psp),
+            "ps2" => Ok(Swizzle::Ps2),
+            "3ds" | "ctr" => Ok(Swizzle::Ctr),
+            "switch" | "block-linear" => Ok(Swizzle::Switch),
+            _ => Err(Error::UnknownSwizzle(s.to_string())),
+        }
+    }
+}
+
+/// Interleaves the bits of `x` and `y`, x in the even bits.
+fn morton(x: usize, y: usize) -> usize {
+    let spread = |mut v: usize| {
+        let mut out = 0;
+        let mut bit = 0;
+        while v != 0 {
+            out |= (v & 1) << (2 * bit);
+            v >>= 1;
+            bit += 1;
+        }
+        out
+    };
+    spread(x) | spread(y) << 1
+}
+
+/// Morton order for a rectangle padded to powers of two: the low bits of
+/// both coordinates interleave up to the shorter side, and the longer
+/// side's remaining bits follow.
+fn morton_rect(x: usize, y: usize, width: u32, height: u32) -> usize {
+    let (width, height) = (width.next_power_of_two(), height.next_power_of_two());
+    let shared = width.min(height) as usize;
+    let bits = shared.trailing_zeros() * 2;
+    let low = morton(x % shared, y % shared);
+    let high = if width >= height {
+        x / shared
+    } else {
+        y / shared
+    };
+    high << bits | low
+}
+
+/// Width and height of a GS memory page in pixels.
+fn ps2_page(bpp: u32) -> (u32, u32) {
+    match bpp {
+        4 => (64, 32),
+        _ => (64, 64),
+    }
+}
+
+/// Block numbers within a PSMCT32 page of 8 by 4 blocks of 8x8 pixels.
+const PS2_BLOCKS_32: [[usize; 8]; 4] = [
+    [0, 1, 4, 5, 16, 17, 20, 21],
+    [2, 3, 6, 7, 18, 19, 22, 23],
+    [8, 9, 12, 13, 24, 25, 28, 29],
+    [10, 11, 14, 15, 26, 27, 30, 31],
+];
+
+/// Block numbers within a PSMCT16 page of 4 by 8 blocks of 16x8 pixels.
+const PS2_BLOCKS_16: [[usize; 4]; 8] = [
+    [0, 2, 8, 10],
+    [1, 3, 9, 11],
+    [4, 6, 12, 14],
+    [5, 7, 13, 15],
+    [16, 18, 24, 26],
+    [17, 19, 25, 27],
+    [20, 22, 28, 30],
+    [21, 23, 29, 31],
+];
+
+/// Word within a PSMCT32 column of 8x2 pixels.
+const PS2_COLUMN_32: [[usize; 8]; 2] = [[0, 1, 4, 5, 8, 9, 12, 13], [2, 3, 6, 7, 10, 11, 14, 15]];
+
+/// Halfword within a PSMCT16 column of 16x2 pixels.
+const PS2_COLUMN_16: [[usize; 16]; 2] = [
+    [0, 2, 8, 10, 16, 18, 24, 26, 1, 3, 9, 11, 17, 19, 25, 27],
+    [4, 6, 12, 14, 20, 22, 28, 30, 5, 7, 13, 15, 21, 23, 29, 31],
+];
+
+/// Index of a pixel in GS memory, in pixels, for a buffer `padded_width`
+/// pixels wide.
+fn ps2_address(x: usize, y: usize, padded_width: usize, bpp: u32) -> usize {
+    let (page_width, page_height) = ps2_page(bpp);
+    let (page_width, page_height) = (page_width as usize, page_height as usize);
+    let page = (y / page_height) * (padded_width / page_width) + x / page_width;
+    let (px, py) = (x % page_width, y % page_height);
+    // Each page holds 32 blocks of 4 columns, 2 pixel rows per column.
+    let column = (py % 8) / 2;
+    match bpp {
+        4 => {
+            let block = PS2_BLOCKS_32[py / 8][px / 8];
+            page * 2048 + block * 64 + column * 16 + PS2_COLUMN_32[py % 2][px % 8]
+        }
+        _ => {
+            let block = PS2_BLOCKS_16[py / 8][px / 16];
+            page * 4096 + block * 128 + column * 32 + PS2_COLUMN_16[py % 2][px % 16]
+        }
+    }
+}
+
+/// GOBs per Switch block: the GOB rows the image spans rounded up to a
+/// power of two, at most 16.
+fn switch_block_height(height: u32) -> u32 {
+    height.div_ceil(8).max(1).next_power_of_two().min(16)
+}
+
+fn check_len(expected: usize, actual: usize) -> Result<()> {
+    if expected == actual {
+        Ok(())
+    } else {
+        Err(Error::BufferSize { expected, actual })
+    }
+}
@@ -0,0 +1,324 @@
+//! Truevision TGA files with 16-bit attribute-alpha, 32-bit, greyscale and
+//! colour-mapped pixels, optionally run-length encoded.
+
+use crate::palette::{Indexed, resolve};
+use crate::{Error, Layout, RawFormat, Result};
+
+const HEADER_SIZE: usize = 18;
+const FOOTER_SIGNATURE: &[u8; 18] = b"TRUEVISION-XFILE.\0";
+
+const TYPE_COLOR_MAPPED: u8 = 1;
+const TYPE_TRUE_COLOR: u8 = 2;
+const TYPE_GREY: u8 = 3;
+const TYPE_RLE: u8 = 8;
+
+const DESCRIPTOR_RIGHT_TO_LEFT: u8 = 0x10;
+const DESCRIPTOR_TOP_DOWN: u8 = 0x20;
+
+/// Writes a bottom-up TGA file for A1R5G5B5, X1R5G5B5, A8R8G8B8, X8R8G8B8,
+/// L8 or A8L8 pixels. The alpha channel is recorded as attribute bits in
+/// the image descriptor, so 16-bit files keep their 1-bit alpha.
+pub fn encode(
+    layout: &Layout,
+    width: u32,
+    height: u32,
+    packed: &[u32],
+    rle: bool,
+) -> Result<Vec<u8>> {
+    let (image_type, attribute_bits) = match (layout.bits, layout.l.is_some(), layout.masks()) {
+        (8, true, [0xFF, 0, 0, 0]) => (TYPE_GREY, 0),
+        (16, true, [0xFF, 0, 0, 0xFF00]) => (TYPE_GREY, 8),
+        _ => (TYPE_TRUE_COLOR, true_colour_attribute_bits(layout)?),
+    };
+    let mut out = Header {
+        image_type,
+        colour_map: None,
+        width,
+        height,
+        depth: layout.bits,
+        attribute_bits,
+        rle,
+    }
+    .write()?;
+    write_pixels(&mut out, layout, width, packed, rle)?;
+    Ok(out)
+}
+
+/// Writes a bottom-up colour-mapped TGA file with 8-bit indices into a
+/// palette of A1R5G5B5, X1R5G5B5, A8R8G8B8 or X8R8G8B8 entries.
+pub fn encode_indexed(layout: &Layout, indexed: &Indexed, rle: bool) -> Result<Vec<u8>> {
+    let (width, height) = (indexed.width, indexed.height);
+    let mut out = Header {
+        image_type: TYPE_COLOR_MAPPED,
+        colour_map: Some((indexed.palette.len() as u16, layout.bits)),
+        width,
+        height,
+        depth: 8,
+        attribute_bits: true_colour_attribute_bits(layout)?,
+        rle,
+    }
+    .write()?;
+    let entries = indexed.palette.len() as u32;
+    out.extend(RawFormat::default().encode(layout, entries, &indexed.palette)?);
+    let indices: Vec<u32> = indexed.indices.iter().map(|&i| i as u32).collect();
+    let index_layout = Layout::from_masks(8, [0xFF, 0, 0, 0], true)?;
+    write_pixels(&mut out, &index_layout, width, &indices, rle)?;
+    Ok(out)
+}
+
+/// Attribute bits of the true-colour layouts TGA can store, or an error
+/// for any other layout.
+fn true_colour_attribute_bits(layout: &Layout) -> Result<u32> {
+    match (layout.bits, layout.l.is_some(), layout.masks()) {
+        (16, false, [0x7C00, 0x03E0, 0x001F, a]) if a == 0 || a == 0x8000 => Ok(a.count_ones()),
+        (32, false, [0xFF_0000, 0xFF00, 0xFF, a]) if a == 0 || a == 0xFF00_0000 => {
+            Ok(a.count_ones())
+        }
+        _ => Err(Error::InvalidFile {
+            format: "TGA",
+            reason: format!("no TGA pixel format stores {layout}"),
+        }),
+    }
+}
+
+/// Layout of true-colour pixels or colour map entries of `depth` bits,
+/// with alpha when the descriptor declares attribute bits.
+fn true_colour_layout(depth: u8, attribute_bits: u8) -> Result<Layout> {
+    let (bits, masks) = match depth {
+        15 => (16, [0x7C00, 0x03E0, 0x001F, 0]),
+        16 => {
+            let a = if attribute_bits > 0 { 0x8000 } else { 0 };
+            (16, [0x7C00, 0x03E0, 0x001F, a])
+        }
+        32 => {
+            let a = if attribute_bits > 0 { 0xFF00_0000 } else { 0 };
+            (32, [0xFF_0000, 0xFF00, 0xFF, a])
+        }
+        24 => {
+            return Err(Error::InvalidFile {
+                format: "TGA",
+                reason: "24-bit pixels have no packed layout".to_string(),
+            });
+        }
+        _ => {
+            return Err(Error::InvalidFile {
+                format: "TGA",
+                reason: "unsupported pixel depth".to_string(),
+            });
+        }
+    };
+    Layout::from_masks(bits, masks, false)
+}
+
+/// Fields of the 18-byte file header.
+struct Header {
+    image_type: u8,
+    /// Number of palette entries and bits per entry.
+    colour_map: Option<(u16, u32)>,
+    width: u32,
+    height: u32,
+    depth: u32,
+    attribute_bits: u32,
+    rle: bool,
+}
+
+impl Header {
+    fn write(&self) -> Result<Vec<u8>> {
+        let (width, height) = (self.width, self.height);
+        let (Ok(w), Ok(h)) = (u16::try_from(width), u16::try_from(height)) else {
+            return Err(Error::InvalidFile {
+                format: "TGA",
+                reason: format!("{width}x{height} exceeds 65535 pixels per side"),
+            });
+        };
+        let mut out = Vec::with_capacity(HEADER_SIZE + width as usize * height as usize * 4 + 26);
+        out.push(0); // ID length
+        out.push(self.colour_map.is_some() as u8);
+        out.push(if self.rle {
+            self.image_type | TYPE_RLE
+        } else {
+            self.image_type
+        });
+        let (entries, entry_bits) = self.colour_map.unwrap_or((0, 0));
+        out.extend_from_slice(&[0; 2]); // first entry index
+        out.extend_from_slice(&entries.to_le_bytes());
+        out.push(entry_bits as u8);
+        out.extend_from_slice(&[0; 4]); // x and y origin
+        out.extend_from_slice(&w.to_le_bytes());
+        out.extend_from_slice(&h.to_le_bytes());
+        out.push(self.depth as u8);
+        out.push(self.attribute_bits as u8);
+        Ok(out)
+    }
+}
+
+/// Appends the rows bottom-up, then the footer.
+fn write_pixels(
+    out: &mut Vec<u8>,
+    layout: &Layout,
+    width: u32,
+    packed: &[u32],
+    rle: bool,
+) -> Result<()> {
+    let bpp = layout.bits as usize / 8;
+    let raw = RawFormat::default();
+    for row in packed.chunks(width.max(1) as usize).rev() {
+        let bytes = raw.encode(layout, width, row)?;
+        if rle {
+            encode_rle(&bytes, bpp, out);
+        } else {
+            out.extend(bytes);
+        }
+    }
+
+    out.extend_from_slice(&[0; 8]); // extension and developer area offsets
+    out.extend_from_slice(FOOTER_SIGNATURE);
+    Ok(())
+}
+
+/// Reads an uncompressed or run-length encoded true-colour, greyscale or
+/// colour-mapped TGA file, returning its layout, width, height and top-down
+/// row-major values. Colour-mapped pixels are looked up in the colour map,
+/// giving the layout of its entries.
+pub fn decode(bytes: &[u8]) -> Result<(Layout, u32, u32, Vec<u32>)> {
+    let invalid = |reason: &str| Error::InvalidFile {
+        format: "TGA",
+        reason: reason.to_string(),
+    };
+    if bytes.len() < HEADER_SIZE {
+        return Err(invalid("missing TGA header"));
+    }
+    let short = |i: usize| u16::from_le_bytes([bytes[i], bytes[i + 1]]) as u32;
+    let (id_length, colour_map_type, image_type) = (bytes[0], bytes[1], bytes[2]);
+    let (map_first, map_length) = (short(3) as usize, short(5) as usize);
+    let map_entry_bits = bytes[7];
+    let (width, height, depth, descriptor) = (short(12), short(14), bytes[16], bytes[17]);
+    let attribute_bits = descriptor & 0x0F;
+
+    let (layout, palette_layout) = match (image_type & !TYPE_RLE, depth) {
+        (TYPE_COLOR_MAPPED, 8) if colour_map_type == 1 => (
+            Layout::from_masks(8, [0xFF, 0, 0, 0], true)?,
+            Some(true_colour_layout(map_entry_bits, attribute_bits)?),
+        ),
+        (TYPE_COLOR_MAPPED, _) => {
+            return Err(invalid("only 8-bit colour map indices are supported"));
+        }
+        (TYPE_TRUE_COLOR, _) => (true_colour_layout(depth, attribute_bits)?, None),
+        (TYPE_GREY, 8) => (Layout::from_masks(8, [0xFF, 0, 0, 0], true)?, None),
+        (TYPE_GREY, 16) => (Layout::from_masks(16, [0xFF, 0, 0, 0xFF00], true)?, None),
+        (TYPE_GREY, _) => return Err(invalid("unsupported pixel depth")),
+        _ => return Err(invalid("unsupported image type")),
+    };
+    let bits = layout.bits;
+
+    let mut offset = HEADER_SIZE + id_length as usize;
+    let mut palette = Vec::new();
+    if colour_map_type != 0 {
+        let map_size = map_length * (map_entry_bits as usize).div_ceil(8);
+        if let Some(entry_layout) = &palette_layout {
+            let map = bytes
+                .get(offset..offset + map_size)
+                .ok_or(Error::Truncated {
+                    expected: offset + map_size,
+                    actual: bytes.len(),
+                })?;
+            // Indices count from the first entry, so pad the palette in front.
+            palette = vec![0; map_first];
+            palette.extend(RawFormat::default().decode(entry_layout, map_length as u32, 1, map)?);
+        }
+        offset += map_size;
+    }
+    let bpp = bits as usize / 8;
+    let size = (width as usize)
+        .checked_mul(height as usize)
+        .and_then(|pixels| pixels.checked_mul(bpp))
+        .ok_or_else(|| invalid("image too large"))?;
+    let pixels = if image_type & TYPE_RLE != 0 {
+        decode_rle(bytes.get(offset..).unwrap_or_default(), bpp, size)
+            .ok_or_else(|| invalid("truncated run-length data"))?
+    } else {
+        let end = offset + size;
+        bytes
+            .get(offset..end)
+            .ok_or(Error::Truncated {
+                expected: end,
+                actual: bytes.len(),
+            })?
+            .to_vec()
+    };
+
+    let mut packed = RawFormat::default().decode(&layout, width, height, &pixels)?;
+    let row = width.max(1) as usize;
+    if descriptor & DESCRIPTOR_RIGHT_TO_LEFT != 0 {
+        packed.chunks_mut(row).for_each(<[u32]>::reverse);
+    }
+    if descriptor & DESCRIPTOR_TOP_DOWN == 0 {
+        packed = packed.chunks(row).rev().flatten().copied().collect();
+    }
+    match palette_layout {
+        Some(entry_layout) => {
+            let indices: Vec<u8> = packed.iter().map(|&i| i as u8).collect();
+            Ok((entry_layout, width, height, resolve(&indices, &palette)?))
+        }
+        None => Ok((layout, width, height, packed)),
+    }
+}
+
+/// Appends one row of pixels as TGA packets: runs of two or more equal
+/// pixels become run-length packets, everything else raw packets.
+fn encode_rle(row: &[u8], bpp: usize, out: &mut Vec<u8>) {
+    let pixels: Vec<&[u8]> = row.chunks_exact(bpp).collect();
+    let run_at = |i: usize| {
+        pixels[i..]
+            .iter()
+            .take(128)
+            .take_while(|&&px| px == pixels[i])
+            .count()
+    };
+    let mut i = 0;
+    while i < pixels.len() {
+        let run = run_at(i);
+        if run > 1 {
+            out.push(0x80 | (run - 1) as u8);
+            out.extend_from_slice(pixels[i]);
+            i += run;
+            continue;
+        }
+        let start = i;
+        while i < pixels.len() && i - start < 128 && (i == start || run_at(i) == 1) {
+            i += 1;
+        }
+        out.push((i - start - 1) as u8);
+        pixels[start..i]
+            .iter()
+            .for_each(|px| out.extend_from_slice(px));
+    }
+}
+
+/// Expands run-length encoded packets into `size` bytes of pixels, or
+/// `None` if the packets end early.
+fn decode_rle(data: &[u8], bpp: usize, size: usize) -> Option<Vec<u8>> {
+    // A packet of n bytes expands to at most 128 * n, so reserving more than
+    // that for a forged header would only waste memory.
+    let mut pixels = Vec::with_capacity(size.min(data.len().saturating_mul(128)));
+    let mut data = data.iter().copied();
+    while pixels.len() < size {
+        let header = data.next()?;
+        let count = (header & 0x7F) as usize + 1;
+        if header & 0x80 != 0 {
+            let px: Vec<u8> = data.by_ref().take(bpp).collect();
+            if px.len() < bpp {
+                return None;
+            }
+            (0..count).for_each(|_| pixels.extend_from_slice(&px));
+        } else {
+            let before = pixels.len();
+            pixels.extend(data.by_ref().take(count * bpp));
+            if pixels.len() - before < count * bpp {
+                return None;
+            }
+        }
+    }
+    pixels.truncate(size);
+    Some(pixels)
+}
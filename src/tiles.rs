@@ -0,0 +1,346 @@
+//! 8x8 tile output for consoles such as the GBA, NDS and SNES: a tileset of
+//! unique tiles plus a tilemap of tile numbers with flip flags.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::palette::companion_path;
+use crate::{Endian, Error, Result};
+
+/// Width and height of a tile in pixels.
+pub const TILE_SIZE: u32 = 8;
+
+/// Pixels in one tile.
+const TILE_PIXELS: usize = (TILE_SIZE * TILE_SIZE) as usize;
+
+/// Largest tile number a tilemap entry can hold.
+const MAX_TILES: usize = 1024;
+
+/// Console whose tilemap entries and indexed tile data are written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TileFormat {
+    /// GBA and NDS text backgrounds: tile number in bits 0-9, horizontal
+    /// flip in bit 10, vertical flip in bit 11 and palette bank in bits
+    /// 12-15. Indexed tiles hold one byte per 8-bit index, or two 4-bit
+    /// indices per byte with the left pixel in the low nibble.
+    #[default]
+    Gba,
+    /// SNES backgrounds: tile number in bits 0-9, palette in bits 10-12,
+    /// priority in bit 13, horizontal flip in bit 14 and vertical flip in
+    /// bit 15. Indexed tiles are stored as interleaved pairs of bitplanes.
+    Snes,
+}
+
+impl TileFormat {
+    /// Bits of the horizontal and vertical flip flags.
+    fn flip_bits(self) -> (u16, u16) {
+        match self {
+            TileFormat::Gba => (1 << 10, 1 << 11),
+            TileFormat::Snes => (1 << 14, 1 << 15),
+        }
+    }
+
+    /// Makes a tilemap entry with palette bank and priority 0.
+    pub fn entry(self, tile: usize, flip: Flip) -> u16 {
+        let (h, v) = self.flip_bits();
+        let mut entry = tile as u16 & 0x3FF;
+        if flip.horizontal {
+            entry |= h;
+        }
+        if flip.vertical {
+            entry |= v;
+        }
+        entry
+    }
+
+    /// Splits a tilemap entry into its tile number and flips, ignoring
+    /// palette bank and priority.
+    pub fn parse(self, entry: u16) -> (usize, Flip) {
+        let (h, v) = self.flip_bits();
+        let flip = Flip {
+            horizontal: entry & h != 0,
+            vertical: entry & v != 0,
+        };
+        ((entry & 0x3FF) as usize, flip)
+    }
+
+    /// Serializes one tile of palette indices.
+    pub fn encode_indices(self, tile: &[u32], index_bits: u32) -> Vec<u8> {
+        match (self, index_bits) {
+            (TileFormat::Gba, 4) => tile
+                .chunks(2)
+                .map(|pair| (pair[0] & 0xF) as u8 | ((pair[1] & 0xF) as u8) << 4)
+                .collect(),
+            (TileFormat::Gba, _) => tile.iter().map(|&i| i as u8).collect(),
+            (TileFormat::Snes, _) => {
+                // Bitplanes come in pairs; each pair interleaves its two
+                // planes row by row, with the leftmost pixel in bit 7.
+                let mut out = Vec::with_capacity(TILE_PIXELS * index_bits as usize / 8);
+                for pair in (0..index_bits).step_by(2) {
+                    for row in tile.chunks(TILE_SIZE as usize) {
+                        for plane in [pair, pair + 1] {
+                            out.push(
+                                row.iter()
+                                    .fold(0, |byte, &i| byte << 1 | (i >> plane) as u8 & 1),
+                            );
+                        }
+                    }
+                }
+                out
+            }
+        }
+    }
+
+    /// Reverses [`TileFormat::encode_indices`] for one tile.
+    pub fn decode_indices(self, bytes: &[u8], index_bits: u32) -> Vec<u32> {
+        match (self, index_bits) {
+            (TileFormat::Gba, 4) => bytes
+                .iter()
+                .flat_map(|&b| [(b & 0xF) as u32, (b >> 4) as u32])
+                .collect(),
+            (TileFormat::Gba, _) => bytes.iter().map(|&b| b as u32).collect(),
+            (TileFormat::Snes, _) => {
+                let mut tile = vec![0; TILE_PIXELS];
+                for (pair, planes) in bytes.chunks(2 * TILE_SIZE as usize).enumerate() {
+                    for (y, row) in planes.chunks(2).enumerate() {
+                        for (p, &byte) in row.iter().enumerate() {
+                            let plane = 2 * pair + p;
+                            for x in 0..TILE_SIZE as usize {
+                                let bit = (byte >> (7 - x)) & 1;
+                                tile[y * TILE_SIZE as usize + x] |= (bit as u32) << plane;
+                            }
+                        }
+                    }
+                }
+                tile
+            }
+        }
+    }
+}
+
+impl fmt::Display for TileFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            TileFormat::Gba => "gba",
+            TileFormat::Snes => "snes",
+        })
+    }
+}
+
+impl FromStr for TileFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "gba" | "nds" => Ok(TileFormat::Gba),
+            "snes" => Ok(TileFormat::Snes),
+            _ => Err(Error::UnknownTileFormat(s.to_string())),
+        }
+    }
+}
+
+/// Mirroring of a tile.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Flip {
+    pub horizontal: bool,
+    pub vertical: bool,
+}
+
+impl Flip {
+    /// Every combination, starting with no flip.
+    const ALL: [Flip; 4] = [
+        Flip {
+            horizontal: false,
+            vertical: false,
+        },
+        Flip {
+            horizontal: true,
+            vertical: false,
+        },
+        Flip {
+            horizontal: false,
+            vertical: true,
+        },
+        Flip {
+            horizontal: true,
+            vertical: true,
+        },
+    ];
+
+    /// Mirrors a row-major tile; flipping twice restores it.
+    fn apply(self, tile: &[u32]) -> Vec<u32> {
+        let size = TILE_SIZE as usize;
+        (0..TILE_PIXELS)
+            .map(|i| {
+                let (x, y) = (i % size, i / size);
+                let x = if self.horizontal { size - 1 - x } else { x };
+                let y = if self.vertical { size - 1 - y } else { y };
+                tile[y * size + x]
+            })
+            .collect()
+    }
+}
+
+/// Settings for tile output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TileOptions {
+    pub format: TileFormat,
+    /// Match tiles against mirrored copies of earlier ones, for hardware
+    /// that can flip tiles.
+    pub flips: bool,
+}
+
+impl Default for TileOptions {
+    fn default() -> Self {
+        Self {
+            format: TileFormat::default(),
+            flips: true,
+        }
+    }
+}
+
+/// An image cut into unique tiles.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tiled {
+    pub width: u32,
+    pub height: u32,
+    /// Values of each unique tile, 64 per tile in row-major order.
+    pub tiles: Vec<u32>,
+    /// One entry per tile of the image, row by row.
+    pub map: Vec<u16>,
+}
+
+impl Tiled {
+    /// Number of unique tiles.
+    pub fn tile_count(&self) -> usize {
+        self.tiles.len() / TILE_PIXELS
+    }
+}
+
+/// Where the tilemap of a tiled `path` is stored when the carrier cannot
+/// hold both: `name.map.ext` next to it.
+pub fn tilemap_path(path: &Path) -> PathBuf {
+    companion_path(path, "map")
+}
+
+/// Cuts row-major values, packed pixels or palette indices, into 8x8
+/// tiles, storing each distinct tile once. Both dimensions must be
+/// multiples of 8.
+pub fn make_tiles(values: &[u32], width: u32, height: u32, options: &TileOptions) -> Result<Tiled> {
+    check_tile_dimensions(width, height)?;
+    let (columns, rows) = (width / TILE_SIZE, height / TILE_SIZE);
+    let flips: &[Flip] = if options.flips {
+        &Flip::ALL
+    } else {
+        &Flip::ALL[..1]
+    };
+    let mut seen: HashMap<Vec<u32>, usize> = HashMap::new();
+    let mut tiles = Vec::new();
+    let mut map = Vec::with_capacity((columns * rows) as usize);
+    for row in 0..rows {
+        for column in 0..columns {
+            let tile = read_tile(values, width, column, row);
+            // A stored tile equal to this one mirrored is this one mirrored
+            // back, with the same flip.
+            let found = flips
+                .iter()
+                .find_map(|&flip| seen.get(&flip.apply(&tile)).map(|&n| (n, flip)));
+            let (number, flip) = match found {
+                Some(found) => found,
+                None => {
+                    let n = seen.len();
+                    if n == MAX_TILES {
+                        return Err(Error::TooManyTiles(MAX_TILES));
+                    }
+                    tiles.extend_from_slice(&tile);
+                    seen.insert(tile, n);
+                    (n, Flip::default())
+                }
+            };
+            map.push(options.format.entry(number, flip));
+        }
+    }
+    Ok(Tiled {
+        width,
+        height,
+        tiles,
+        map,
+    })
+}
+
+/// Reassembles row-major values of a `width` by `height` image from a
+/// tileset and its tilemap.
+pub fn untile(
+    tiles: &[u32],
+    map: &[u16],
+    width: u32,
+    height: u32,
+    format: TileFormat,
+) -> Result<Vec<u32>> {
+    check_tile_dimensions(width, height)?;
+    let columns = width / TILE_SIZE;
+    let expected = (columns * (height / TILE_SIZE)) as usize;
+    if map.len() < expected {
+        return Err(Error::Truncated {
+            expected,
+            actual: map.len(),
+        });
+    }
+    let count = tiles.len() / TILE_PIXELS;
+    let mut values = vec![0; width as usize * height as usize];
+    for (i, &entry) in map[..expected].iter().enumerate() {
+        let (number, flip) = format.parse(entry);
+        let tile = tiles
+            .get(number * TILE_PIXELS..(number + 1) * TILE_PIXELS)
+            .ok_or_else(|| Error::InvalidFile {
+                format: "tilemap",
+                reason: format!("tile {number} is outside the {count}-tile set"),
+            })?;
+        let (column, row) = (i as u32 % columns, i as u32 / columns);
+        for (j, value) in flip.apply(tile).into_iter().enumerate() {
+            let x = column * TILE_SIZE + j as u32 % TILE_SIZE;
+            let y = row * TILE_SIZE + j as u32 / TILE_SIZE;
+            values[(y * width + x) as usize] = value;
+        }
+    }
+    Ok(values)
+}
+
+/// Serializes tilemap entries as 16-bit values.
+pub fn encode_map(map: &[u16], endian: Endian) -> Vec<u8> {
+    map.iter()
+        .flat_map(|&entry| match endian {
+            Endian::Little => entry.to_le_bytes(),
+            Endian::Big => entry.to_be_bytes(),
+        })
+        .collect()
+}
+
+/// Reverses [`encode_map`].
+pub fn decode_map(bytes: &[u8], endian: Endian) -> Vec<u16> {
+    bytes
+        .chunks_exact(2)
+        .map(|b| match endian {
+            Endian::Little => u16::from_le_bytes([b[0], b[1]]),
+            Endian::Big => u16::from_be_bytes([b[0], b[1]]),
+        })
+        .collect()
+}
+
+fn read_tile(values: &[u32], width: u32, column: u32, row: u32) -> Vec<u32> {
+    (0..TILE_SIZE)
+        .flat_map(|y| {
+            let start = ((row * TILE_SIZE + y) * width + column * TILE_SIZE) as usize;
+            values[start..start + TILE_SIZE as usize].iter().copied()
+        })
+        .collect()
+}
+
+fn check_tile_dimensions(width: u32, height: u32) -> Result<()> {
+    if !width.is_multiple_of(TILE_SIZE) || !height.is_multiple_of(TILE_SIZE) {
+        return Err(Error::TileDimensions { width, height });
+    }
+    Ok(())
+}
@@ -0,0 +1,201 @@
+//! Quality metrics comparing an image with its packed and unpacked round trip.
+
+use image::RgbaImage;
+
+use crate::Result;
+use crate::diff::check_dimensions;
+
+/// Names of the channels in [`Metrics::channels`].
+pub const CHANNEL_NAMES: [&str; 4] = ["R", "G", "B", "A"];
+
+/// Error of one channel, or of the colour channels together.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChannelError {
+    /// Largest absolute difference of any pixel.
+    pub max: u8,
+    /// Mean absolute difference.
+    pub mean: f64,
+    /// Peak signal-to-noise ratio in dB; infinite when nothing changed.
+    pub psnr: f64,
+    /// Mean structural similarity over 11x11 Gaussian windows, 1 when identical.
+    pub ssim: f64,
+}
+
+/// Differences between an image and its round trip.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Metrics {
+    /// Error of the red, green, blue and alpha channels.
+    pub channels: [ChannelError; 4],
+    /// Error of red, green and blue taken together.
+    pub colour: ChannelError,
+    /// Number of pixels whose alpha value changed.
+    pub alpha_changed: usize,
+    /// Number of pixels compared.
+    pub pixels: usize,
+}
+
+/// Limits on [`Metrics`]; `None` leaves a metric unchecked.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Thresholds {
+    /// Largest allowed error of any channel.
+    pub max_error: Option<u8>,
+    /// Largest allowed mean error of any channel.
+    pub max_mean_error: Option<f64>,
+    /// Smallest allowed colour PSNR in dB.
+    pub min_psnr: Option<f64>,
+    /// Smallest allowed colour SSIM.
+    pub min_ssim: Option<f64>,
+    /// Largest allowed number of pixels whose alpha changed.
+    pub max_alpha_changed: Option<usize>,
+}
+
+impl Thresholds {
+    /// Describes every limit `metrics` exceeds, or nothing if all hold.
+    pub fn violations(&self, metrics: &Metrics) -> Vec<String> {
+        let mut violations = Vec::new();
+        let channels = CHANNEL_NAMES.iter().zip(&metrics.channels);
+        for (name, channel) in channels {
+            if let Some(limit) = self.max_error.filter(|&limit| channel.max > limit) {
+                violations.push(format!("{name} max error {} exceeds {limit}", channel.max));
+            }
+            if let Some(limit) = self.max_mean_error.filter(|&limit| channel.mean > limit) {
+                violations.push(format!(
+                    "{name} mean error {:.3} exceeds {limit}",
+                    channel.mean
+                ));
+            }
+        }
+        let colour = &metrics.colour;
+        if let Some(limit) = self.min_psnr.filter(|&limit| colour.psnr < limit) {
+            violations.push(format!("PSNR {:.2} dB is below {limit} dB", colour.psnr));
+        }
+        if let Some(limit) = self.min_ssim.filter(|&limit| colour.ssim < limit) {
+            violations.push(format!("SSIM {:.4} is below {limit}", colour.ssim));
+        }
+        if let Some(limit) = self
+            .max_alpha_changed
+            .filter(|&limit| metrics.alpha_changed > limit)
+        {
+            violations.push(format!(
+                "{} pixels changed alpha, more than {limit}",
+                metrics.alpha_changed
+            ));
+        }
+        violations
+    }
+}
+
+/// Measures how far `actual` differs from `expected`.
+pub fn compare(expected: &RgbaImage, actual: &RgbaImage) -> Result<Metrics> {
+    check_dimensions(expected, actual)?;
+    let (width, height) = expected.dimensions();
+    let plane =
+        |img: &RgbaImage, c: usize| -> Vec<f64> { img.pixels().map(|px| px[c] as f64).collect() };
+
+    let mut channels = [ChannelError {
+        max: 0,
+        mean: 0.0,
+        psnr: f64::INFINITY,
+        ssim: 1.0,
+    }; 4];
+    let mut squared = [0.0; 4];
+    for (c, channel) in channels.iter_mut().enumerate() {
+        let (a, b) = (plane(expected, c), plane(actual, c));
+        let mut sum = 0.0;
+        for (&x, &y) in a.iter().zip(&b) {
+            let diff = (x - y).abs();
+            channel.max = channel.max.max(diff as u8);
+            sum += diff;
+            squared[c] += diff * diff;
+        }
+        let n = a.len().max(1) as f64;
+        channel.mean = sum / n;
+        channel.psnr = psnr(squared[c] / n);
+        channel.ssim = ssim(&a, &b, width as usize, height as usize);
+    }
+
+    let [r, g, b, _] = channels;
+    let n = (expected.len() / 4).max(1) as f64;
+    let colour = ChannelError {
+        max: r.max.max(g.max).max(b.max),
+        mean: (r.mean + g.mean + b.mean) / 3.0,
+        psnr: psnr(squared[..3].iter().sum::<f64>() / (3.0 * n)),
+        ssim: (r.ssim + g.ssim + b.ssim) / 3.0,
+    };
+    let alpha_changed = expected
+        .pixels()
+        .zip(actual.pixels())
+        .filter(|(x, y)| x[3] != y[3])
+        .count();
+    Ok(Metrics {
+        channels,
+        colour,
+        alpha_changed,
+        pixels: expected.len() / 4,
+    })
+}
+
+fn psnr(mse: f64) -> f64 {
+    if mse == 0.0 {
+        f64::INFINITY
+    } else {
+        10.0 * (255.0 * 255.0 / mse).log10()
+    }
+}
+
+/// Mean SSIM of two planes, using the usual 11x11 Gaussian window with
+/// sigma 1.5 and clamping at the edges.
+fn ssim(a: &[f64], b: &[f64], width: usize, height: usize) -> f64 {
+    if a.is_empty() {
+        return 1.0;
+    }
+    const C1: f64 = (0.01 * 255.0) * (0.01 * 255.0);
+    const C2: f64 = (0.03 * 255.0) * (0.03 * 255.0);
+    let product =
+        |x: &[f64], y: &[f64]| -> Vec<f64> { x.iter().zip(y).map(|(x, y)| x * y).collect() };
+    let blur = |plane: &[f64]| gaussian(plane, width, height);
+    let (mu_a, mu_b) = (blur(a), blur(b));
+    let (aa, bb, ab) = (
+        blur(&product(a, a)),
+        blur(&product(b, b)),
+        blur(&product(a, b)),
+    );
+
+    let total: f64 = (0..a.len())
+        .map(|i| {
+            let (ma, mb) = (mu_a[i], mu_b[i]);
+            let var_a = aa[i] - ma * ma;
+            let var_b = bb[i] - mb * mb;
+            let cov = ab[i] - ma * mb;
+            ((2.0 * ma * mb + C1) * (2.0 * cov + C2))
+                / ((ma * ma + mb * mb + C1) * (var_a + var_b + C2))
+        })
+        .sum();
+    total / a.len() as f64
+}
+
+fn gaussian(plane: &[f64], width: usize, height: usize) -> Vec<f64> {
+    const RADIUS: isize = 5;
+    let kernel: Vec<f64> = (-RADIUS..=RADIUS)
+        .map(|i| (-(i * i) as f64 / (2.0 * 1.5 * 1.5)).exp())
+        .collect();
+    let norm: f64 = kernel.iter().sum();
+    let pass = |src: &[f64], horizontal: bool| -> Vec<f64> {
+        let mut out = vec![0.0; src.len()];
+        for y in 0..height {
+            for x in 0..width {
+                let mut sum = 0.0;
+                for (k, weight) in (-RADIUS..=RADIUS).zip(&kernel) {
+                    let (sx, sy) = match horizontal {
+                        true => ((x as isize + k).clamp(0, width as isize - 1) as usize, y),
+                        false => (x, (y as isize + k).clamp(0, height as isize - 1) as usize),
+                    };
+                    sum += weight * src[sy * width + sx];
+                }
+                out[y * width + x] = sum / norm;
+            }
+        }
+        out
+    };
+    pass(&pass(plane, true), false)
+}
@@ -0,0 +1,313 @@
+use std::path::Path;
+
+use image::imageops::FilterType;
+use image::{Rgba, RgbaImage, imageops};
+use image_packer::{
+    AlphaPolarity, Atlas, AtlasOptions, Error, Layout, Packer, PixelFormat, Placement, Rotation,
+    Sprite, build_atlas, page_path, trim_bounds,
+};
+
+const PACKERS: [Packer; 2] = [Packer::MaxRects, Packer::Skyline];
+
+/// Sprites of assorted sizes, each pixel naming its sprite and position so
+/// a misplaced or wrongly turned copy shows.
+fn sprites(count: u32) -> Vec<Sprite> {
+    (0..count)
+        .map(|i| {
+            let (w, h) = (3 + i * 7 % 19, 2 + i * 11 % 23);
+            Sprite {
+                name: format!("sprite{i}"),
+                image: RgbaImage::from_fn(w, h, |x, y| Rgba([i as u8, x as u8, y as u8, 255])),
+            }
+        })
+        .collect()
+}
+
+/// The pixels a placement covers on its page, turned back upright.
+fn region(atlas: &Atlas, placement: &Placement, rotation: Rotation) -> RgbaImage {
+    let page = &atlas.pages[placement.page];
+    let (w, h) = match placement.rotated {
+        true => (placement.height, placement.width),
+        false => (placement.width, placement.height),
+    };
+    let view = imageops::crop_imm(page, placement.x, placement.y, w, h).to_image();
+    match (placement.rotated, rotation) {
+        (true, Rotation::Clockwise) => imageops::rotate270(&view),
+        (true, Rotation::CounterClockwise) => imageops::rotate90(&view),
+        (false, _) => view,
+    }
+}
+
+/// The cell a placement reserves: its extrusion on every side and the
+/// padding to the right and below.
+fn cell(placement: &Placement, options: &AtlasOptions) -> (u32, u32, u32, u32) {
+    let e = options.extrude;
+    let (w, h) = match placement.rotated {
+        true => (placement.height, placement.width),
+        false => (placement.width, placement.height),
+    };
+    (
+        placement.x - e,
+        placement.y - e,
+        w + 2 * e + options.padding,
+        h + 2 * e + options.padding,
+    )
+}
+
+/// Checks that every sprite sits whole on a page no larger than allowed,
+/// that no two cells overlap and that each keeps its pixels.
+fn check(sprites: &[Sprite], atlas: &Atlas, options: &AtlasOptions) {
+    assert_eq!(atlas.placements.len(), sprites.len());
+    for page in &atlas.pages {
+        assert!(page.width() <= options.max_width && page.height() <= options.max_height);
+    }
+    for (sprite, placement) in sprites.iter().zip(&atlas.placements) {
+        assert_eq!(placement.name, sprite.name);
+        assert!(placement.page < atlas.pages.len());
+        let page = &atlas.pages[placement.page];
+        let (x, y, w, h) = cell(placement, options);
+        // The padding of the last row and column may hang off the page.
+        assert!(x + w - options.padding <= page.width(), "{}", sprite.name);
+        assert!(y + h - options.padding <= page.height(), "{}", sprite.name);
+        assert_eq!(region(atlas, placement, options.rotation), sprite.image);
+    }
+    for (i, a) in atlas.placements.iter().enumerate() {
+        for b in &atlas.placements[i + 1..] {
+            if a.page != b.page {
+                continue;
+            }
+            let (ax, ay, aw, ah) = cell(a, options);
+            let (bx, by, bw, bh) = cell(b, options);
+            let apart = ax + aw <= bx || bx + bw <= ax || ay + ah <= by || by + bh <= ay;
+            assert!(apart, "{} overlaps {}", a.name, b.name);
+        }
+    }
+}
+
+#[test]
+fn placements_keep_their_padding_apart() {
+    let sprites = sprites(40);
+    for packer in PACKERS {
+        for (padding, extrude) in [(0, 0), (2, 0), (3, 1), (0, 2)] {
+            for rotate in [false, true] {
+                let options = AtlasOptions {
+                    packer,
+                    max_width: 256,
+                    max_height: 256,
+                    padding,
+                    extrude,
+                    rotate,
+                    ..AtlasOptions::default()
+                };
+                let atlas = build_atlas(&sprites, &options).unwrap();
+                assert_eq!(atlas.pages.len(), 1, "{packer}");
+                check(&sprites, &atlas, &options);
+            }
+        }
+    }
+}
+
+#[test]
+fn extrusion_repeats_the_edges() {
+    let sprites = sprites(3);
+    let options = AtlasOptions {
+        extrude: 2,
+        padding: 1,
+        ..AtlasOptions::default()
+    };
+    let atlas = build_atlas(&sprites, &options).unwrap();
+    for (sprite, placement) in sprites.iter().zip(&atlas.placements) {
+        let page = &atlas.pages[placement.page];
+        let corner = *sprite.image.get_pixel(0, 0);
+        assert_eq!(*page.get_pixel(placement.x - 2, placement.y - 2), corner);
+        assert_eq!(*page.get_pixel(placement.x - 1, placement.y), corner);
+    }
+}
+
+#[test]
+fn rotation_turns_sprites_either_way() {
+    // Tall sprites on a short page only fit on their side.
+    let sprites: Vec<Sprite> = sprites(12)
+        .into_iter()
+        .enumerate()
+        .map(|(i, s)| Sprite {
+            image: imageops::resize(&s.image, 3 + i as u32, 20 + i as u32, FilterType::Nearest),
+            ..s
+        })
+        .collect();
+    for rotation in [Rotation::Clockwise, Rotation::CounterClockwise] {
+        let options = AtlasOptions {
+            max_width: 512,
+            max_height: 16,
+            padding: 1,
+            rotate: true,
+            rotation,
+            ..AtlasOptions::default()
+        };
+        let atlas = build_atlas(&sprites, &options).unwrap();
+        assert!(atlas.placements.iter().all(|p| p.rotated));
+        check(&sprites, &atlas, &options);
+    }
+}
+
+#[test]
+fn power_of_two_pages() {
+    let sprites = sprites(25);
+    for packer in PACKERS {
+        // The limit is rounded down to 64 before packing.
+        let options = AtlasOptions {
+            packer,
+            max_width: 100,
+            max_height: 90,
+            power_of_two: true,
+            ..AtlasOptions::default()
+        };
+        let atlas = build_atlas(&sprites, &options).unwrap();
+        for page in &atlas.pages {
+            assert!(page.width().is_power_of_two() && page.height().is_power_of_two());
+            assert!(page.width() <= 64 && page.height() <= 64, "{packer}");
+        }
+        check(&sprites, &atlas, &options);
+    }
+}
+
+#[test]
+fn overflow_goes_onto_more_pages() {
+    let sprites = sprites(40);
+    for packer in PACKERS {
+        let options = AtlasOptions {
+            packer,
+            max_width: 48,
+            max_height: 48,
+            padding: 2,
+            ..AtlasOptions::default()
+        };
+        let atlas = build_atlas(&sprites, &options).unwrap();
+        assert!(atlas.pages.len() > 2, "{packer}");
+        for page in 0..atlas.pages.len() {
+            assert!(atlas.placements.iter().any(|p| p.page == page));
+        }
+        check(&sprites, &atlas, &options);
+    }
+}
+
+#[test]
+fn rejects_sprites_larger_than_a_page() {
+    let sprite = Sprite {
+        name: "wide".into(),
+        image: RgbaImage::new(40, 8),
+    };
+    let options = AtlasOptions {
+        max_width: 32,
+        max_height: 64,
+        padding: 0,
+        ..AtlasOptions::default()
+    };
+    assert!(matches!(
+        build_atlas(std::slice::from_ref(&sprite), &options),
+        Err(Error::SpriteTooLarge {
+            width: 40,
+            height: 8,
+            ..
+        })
+    ));
+    let rotate = AtlasOptions {
+        rotate: true,
+        ..options
+    };
+    let atlas = build_atlas(&[sprite], &rotate).unwrap();
+    assert!(atlas.placements[0].rotated);
+}
+
+#[test]
+fn pages_are_numbered_when_there_are_several() {
+    let path = Path::new("out/atlas.png");
+    assert_eq!(page_path(path, 0, 1), path);
+    assert_eq!(page_path(path, 0, 3), Path::new("out/atlas-0.png"));
+    assert_eq!(page_path(path, 2, 3), Path::new("out/atlas-2.png"));
+    assert_eq!(page_path(Path::new("atlas"), 1, 2), Path::new("atlas-1"));
+}
+
+/// A 10x8 image, fully transparent but for an opaque 3x2 block at (4, 3)
+/// and a faint pixel of alpha `faint` at (1, 6).
+fn faint_sprite(faint: u8) -> RgbaImage {
+    let mut image = RgbaImage::new(10, 8);
+    for (x, y) in [(4, 3), (5, 3), (6, 3), (4, 4), (5, 4), (6, 4)] {
+        image.put_pixel(x, y, Rgba([200, 100, 50, 255]));
+    }
+    image.put_pixel(1, 6, Rgba([200, 100, 50, faint]));
+    image
+}
+
+#[test]
+fn trim_follows_the_one_bit_alpha_rule() {
+    let mut layout = PixelFormat::Argb1555.layout();
+    let image = faint_sprite(60);
+    assert_eq!(trim_bounds(&image, &layout), (1, 3, 6, 4));
+    for polarity in [AlphaPolarity::Transparent, AlphaPolarity::SemiTransparent] {
+        layout.alpha_mode.polarity = polarity;
+        assert_eq!(trim_bounds(&image, &layout), (1, 3, 6, 4), "{polarity}");
+    }
+    // Alpha 60 is not above the threshold, so the faint pixel packs as
+    // transparent and is trimmed off.
+    layout.alpha_mode.threshold = 60;
+    assert_eq!(trim_bounds(&image, &layout), (4, 3, 3, 2));
+    layout.alpha_mode.polarity = AlphaPolarity::Opaque;
+    assert_eq!(trim_bounds(&image, &layout), (4, 3, 3, 2));
+}
+
+#[test]
+fn trim_follows_quantized_alpha() {
+    let layout = PixelFormat::Argb4444.layout();
+    // Alpha 8 rounds to 0 in four bits, alpha 9 to 1.
+    assert_eq!(trim_bounds(&faint_sprite(8), &layout), (4, 3, 3, 2));
+    assert_eq!(trim_bounds(&faint_sprite(9), &layout), (1, 3, 6, 4));
+    let layout = Layout::parse_spec("A8R8G8B8").unwrap();
+    assert_eq!(trim_bounds(&faint_sprite(1), &layout), (1, 3, 6, 4));
+}
+
+#[test]
+fn layouts_without_alpha_are_never_trimmed() {
+    let layout = PixelFormat::Rgb565.layout();
+    assert_eq!(trim_bounds(&faint_sprite(0), &layout), (0, 0, 10, 8));
+    assert_eq!(trim_bounds(&RgbaImage::new(5, 3), &layout), (0, 0, 5, 3));
+}
+
+#[test]
+fn invisible_sprites_keep_one_pixel() {
+    let layout = PixelFormat::Argb1555.layout();
+    assert_eq!(trim_bounds(&RgbaImage::new(5, 3), &layout), (0, 0, 1, 1));
+    assert_eq!(trim_bounds(&RgbaImage::new(0, 0), &layout), (0, 0, 0, 0));
+}
+
+#[test]
+fn trimmed_placements_record_their_source() {
+    let sprites = [
+        Sprite {
+            name: "faint".into(),
+            image: faint_sprite(60),
+        },
+        Sprite {
+            name: "full".into(),
+            image: RgbaImage::from_pixel(4, 4, Rgba([1, 2, 3, 255])),
+        },
+    ];
+    let mut layout = PixelFormat::Argb1555.layout();
+    layout.alpha_mode.threshold = 60;
+    let options = AtlasOptions {
+        trim: Some(layout),
+        ..AtlasOptions::default()
+    };
+    let atlas = build_atlas(&sprites, &options).unwrap();
+    let faint = &atlas.placements[0];
+    assert!(faint.trimmed());
+    assert_eq!((faint.width, faint.height), (3, 2));
+    assert_eq!((faint.offset_x, faint.offset_y), (4, 3));
+    assert_eq!((faint.source_width, faint.source_height), (10, 8));
+    let cropped = imageops::crop_imm(&sprites[0].image, 4, 3, 3, 2).to_image();
+    assert_eq!(region(&atlas, faint, options.rotation), cropped);
+
+    let full = &atlas.placements[1];
+    assert!(!full.trimmed());
+    assert_eq!((full.offset_x, full.offset_y), (0, 0));
+}
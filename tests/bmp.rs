@@ -0,0 +1,192 @@
+use image_packer::{Error, Layout, PixelFormat, decode_bmp, encode_bmp};
+
+/// 32-bit layouts beyond the named 16-bit formats.
+const SPECS: [&str; 4] = ["A8R8G8B8", "X8R8G8B8", "A8B8G8R8", "R8G8B8A8"];
+
+/// Odd widths leave padding at the end of 16-bit rows.
+const SIZES: [(u32, u32); 4] = [(1, 1), (3, 5), (16, 9), (7, 1)];
+
+fn layout(spec: &str) -> Layout {
+    Layout::parse_spec(spec).unwrap()
+}
+
+fn layouts() -> Vec<Layout> {
+    let named = PixelFormat::ALL.into_iter().map(PixelFormat::layout);
+    named.chain(SPECS.map(layout)).collect()
+}
+
+/// Scrambled values using every bit the layout stores.
+fn values(layout: &Layout, width: u32, height: u32) -> Vec<u32> {
+    let mask = layout.masks().into_iter().fold(0, |all, m| all | m);
+    (0..width * height)
+        .map(|i| i.wrapping_mul(0x9E37_79B9).rotate_left(i % 32) & mask)
+        .collect()
+}
+
+fn word(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+}
+
+fn put(bytes: &mut [u8], at: usize, value: u32) {
+    bytes[at..at + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+fn invalid_reason(result: Result<(Layout, u32, u32, Vec<u32>), Error>) -> String {
+    match result {
+        Err(Error::InvalidFile {
+            format: "BMP",
+            reason,
+        }) => reason,
+        other => panic!("expected an invalid BMP file, got {other:?}"),
+    }
+}
+
+#[test]
+fn every_layout_round_trips() {
+    for layout in layouts() {
+        for (width, height) in SIZES {
+            let packed = values(&layout, width, height);
+            let bytes = encode_bmp(&layout, width, height, &packed).unwrap();
+            let (decoded, w, h, values) = decode_bmp(&bytes).unwrap();
+            assert_eq!(decoded, layout);
+            assert_eq!((w, h), (width, height), "{layout}");
+            assert_eq!(values, packed, "{layout} {width}x{height}");
+        }
+    }
+}
+
+#[test]
+fn header_holds_bitfields_and_bottom_up_rows() {
+    let layout = PixelFormat::Argb1555.layout();
+    let packed = values(&layout, 3, 2);
+    let bytes = encode_bmp(&layout, 3, 2, &packed).unwrap();
+    assert_eq!(&bytes[..2], b"BM");
+    assert_eq!(word(&bytes, 2) as usize, bytes.len());
+    assert_eq!(word(&bytes, 10), 14 + 108, "data offset");
+    assert_eq!(word(&bytes, 14), 108, "BITMAPV4HEADER");
+    assert_eq!((word(&bytes, 18), word(&bytes, 22)), (3, 2));
+    assert_eq!(word(&bytes, 28) & 0xFFFF, 16);
+    assert_eq!(word(&bytes, 30), 3, "BI_BITFIELDS");
+    let masks: Vec<u32> = (0..4).map(|i| word(&bytes, 54 + 4 * i)).collect();
+    assert_eq!(masks, [0x7C00, 0x03E0, 0x001F, 0x8000]);
+    // Rows of 6 bytes padded to 8, the bottom one first.
+    assert_eq!(bytes.len(), 122 + 2 * 8);
+    let first = u16::from_le_bytes([bytes[122], bytes[123]]);
+    assert_eq!(u32::from(first), packed[3]);
+}
+
+#[test]
+fn negative_height_means_top_down_rows() {
+    let layout = layout("A8R8G8B8");
+    let (width, height) = (5, 4);
+    let packed = values(&layout, width, height);
+    let mut bytes = encode_bmp(&layout, width, height, &packed).unwrap();
+    put(&mut bytes, 22, (-(height as i32)) as u32);
+    let (_, w, h, values) = decode_bmp(&bytes).unwrap();
+    assert_eq!((w, h), (width, height));
+    let flipped: Vec<u32> = packed
+        .chunks(width as usize)
+        .rev()
+        .flatten()
+        .copied()
+        .collect();
+    assert_eq!(values, flipped);
+}
+
+#[test]
+fn bi_rgb_uses_the_default_masks() {
+    for spec in ["X1R5G5B5", "X8R8G8B8"] {
+        let layout = layout(spec);
+        let packed = values(&layout, 6, 3);
+        let mut bytes = encode_bmp(&layout, 6, 3, &packed).unwrap();
+        put(&mut bytes, 30, 0);
+        // The masks left in the header must be ignored.
+        put(&mut bytes, 54, 0x1F);
+        let (decoded, _, _, values) = decode_bmp(&bytes).unwrap();
+        assert_eq!(decoded, layout, "{spec}");
+        assert_eq!(values, packed, "{spec}");
+    }
+}
+
+#[test]
+fn short_headers_only_carry_alpha_with_alphabitfields() {
+    let layout = PixelFormat::Argb1555.layout();
+    let packed = values(&layout, 4, 2);
+    let mut bytes = encode_bmp(&layout, 4, 2, &packed).unwrap();
+    put(&mut bytes, 14, 40); // BITMAPINFOHEADER
+    let (decoded, _, _, values) = decode_bmp(&bytes).unwrap();
+    assert_eq!(decoded, PixelFormat::Xrgb1555.layout());
+    // The alpha bit is left as padding.
+    let colour = |values: &[u32]| values.iter().map(|v| v & 0x7FFF).collect::<Vec<_>>();
+    assert_eq!(colour(&values), colour(&packed));
+
+    put(&mut bytes, 30, 6); // BI_ALPHABITFIELDS
+    let (decoded, _, _, values) = decode_bmp(&bytes).unwrap();
+    assert_eq!(decoded, layout);
+    assert_eq!(values, packed);
+}
+
+#[test]
+fn refuses_layouts_without_bitfields() {
+    assert!(encode_bmp(&layout("L8"), 1, 1, &[0]).is_err());
+    assert!(encode_bmp(&layout("A8L8"), 1, 1, &[0]).is_err());
+    assert!(encode_bmp(&layout("R3G3B2"), 1, 1, &[0]).is_err());
+}
+
+#[test]
+fn rejects_malformed_headers() {
+    let layout = PixelFormat::Rgb565.layout();
+    let good = encode_bmp(&layout, 4, 4, &values(&layout, 4, 4)).unwrap();
+
+    assert_eq!(
+        invalid_reason(decode_bmp(&good[..53])),
+        "missing BMP header"
+    );
+    let mut magic = good.clone();
+    magic[..2].copy_from_slice(b"MB");
+    assert_eq!(invalid_reason(decode_bmp(&magic)), "missing BMP header");
+
+    let mut bit_count = good.clone();
+    put(&mut bit_count, 26, 1 | 24 << 16);
+    assert_eq!(
+        invalid_reason(decode_bmp(&bit_count)),
+        "only 16 and 32-bit bitmaps hold packed pixels"
+    );
+    let mut width = good.clone();
+    put(&mut width, 18, (-4i32) as u32);
+    assert_eq!(
+        invalid_reason(decode_bmp(&width)),
+        "only 16 and 32-bit bitmaps hold packed pixels"
+    );
+    let mut compression = good.clone();
+    put(&mut compression, 30, 1); // BI_RLE8
+    assert_eq!(
+        invalid_reason(decode_bmp(&compression)),
+        "compressed bitmaps are not supported"
+    );
+
+    let mut overlapping = good.clone();
+    put(&mut overlapping, 58, 0xFFE0);
+    assert!(matches!(
+        decode_bmp(&overlapping),
+        Err(Error::OverlappingMasks(..))
+    ));
+}
+
+#[test]
+fn rejects_truncated_pixels() {
+    let layout = PixelFormat::Argb4444.layout();
+    let bytes = encode_bmp(&layout, 4, 4, &values(&layout, 4, 4)).unwrap();
+    assert!(matches!(
+        decode_bmp(&bytes[..bytes.len() - 1]),
+        Err(Error::Truncated {
+            expected: 154,
+            actual: 153
+        })
+    ));
+
+    // A forged data offset must not make the decoder read past the input.
+    let mut offset = bytes.clone();
+    put(&mut offset, 10, u32::MAX);
+    assert!(matches!(decode_bmp(&offset), Err(Error::Truncated { .. })));
+}
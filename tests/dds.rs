@@ -0,0 +1,156 @@
+use image_packer::{Error, Layout, PixelFormat, decode_dds, encode_dds};
+
+/// Mask-described layouts beyond the named 16-bit formats: 8 and 32-bit
+/// containers, luminance and alpha-only surfaces.
+const SPECS: [&str; 7] = ["R3G3B2", "L8", "A8", "A4L4", "A8L8", "A8R8G8B8", "X8B8G8R8"];
+
+const SIZES: [(u32, u32); 4] = [(1, 1), (3, 5), (16, 9), (7, 1)];
+
+fn layouts() -> Vec<Layout> {
+    let named = PixelFormat::ALL.into_iter().map(PixelFormat::layout);
+    let specs = SPECS.iter().map(|spec| Layout::parse_spec(spec).unwrap());
+    named.chain(specs).collect()
+}
+
+/// Scrambled values using every bit the layout stores.
+fn values(layout: &Layout, width: u32, height: u32) -> Vec<u32> {
+    let mask = layout.masks().into_iter().fold(0, |all, m| all | m);
+    (0..width * height)
+        .map(|i| i.wrapping_mul(0x9E37_79B9).rotate_left(i % 32) & mask)
+        .collect()
+}
+
+fn word(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+}
+
+fn invalid_reason(result: Result<(Layout, u32, u32, Vec<u32>), Error>) -> String {
+    match result {
+        Err(Error::InvalidFile {
+            format: "DDS",
+            reason,
+        }) => reason,
+        other => panic!("expected an invalid DDS file, got {other:?}"),
+    }
+}
+
+#[test]
+fn every_layout_round_trips() {
+    for layout in layouts() {
+        for (width, height) in SIZES {
+            let packed = values(&layout, width, height);
+            let bytes = encode_dds(&layout, width, height, &packed).unwrap();
+            let (decoded, w, h, values) = decode_dds(&bytes).unwrap();
+            assert_eq!(decoded, layout);
+            assert_eq!((w, h), (width, height), "{layout}");
+            assert_eq!(values, packed, "{layout} {width}x{height}");
+        }
+    }
+}
+
+#[test]
+fn header_describes_the_masks() {
+    let layout = PixelFormat::Argb1555.layout();
+    let bytes = encode_dds(&layout, 3, 2, &values(&layout, 3, 2)).unwrap();
+    assert_eq!(&bytes[..4], b"DDS ");
+    assert_eq!(word(&bytes, 4), 124);
+    assert_eq!((word(&bytes, 12), word(&bytes, 16)), (2, 3));
+    assert_eq!(word(&bytes, 20), 6, "pitch");
+    // DDPF_RGB | DDPF_ALPHAPIXELS, 16 bits, then the R, G, B and A masks.
+    assert_eq!(word(&bytes, 80), 0x41);
+    assert_eq!(word(&bytes, 88), 16);
+    let masks: Vec<u32> = (0..4).map(|i| word(&bytes, 92 + 4 * i)).collect();
+    assert_eq!(masks, [0x7C00, 0x03E0, 0x001F, 0x8000]);
+    assert_eq!(bytes.len(), 128 + 12);
+}
+
+#[test]
+fn reads_dx10_headers() {
+    let cases = [
+        (85u32, PixelFormat::Rgb565),
+        (86, PixelFormat::Argb1555),
+        (115, PixelFormat::Argb4444),
+    ];
+    for (dxgi, format) in cases {
+        let layout = format.layout();
+        let packed = values(&layout, 4, 3);
+        let legacy = encode_dds(&layout, 4, 3, &packed).unwrap();
+        let mut bytes = legacy[..128].to_vec();
+        bytes[80..84].copy_from_slice(&4u32.to_le_bytes()); // DDPF_FOURCC
+        bytes[84..88].copy_from_slice(b"DX10");
+        bytes.extend_from_slice(&dxgi.to_le_bytes());
+        bytes.extend_from_slice(&[0; 16]);
+        bytes.extend_from_slice(&legacy[128..]);
+        let (decoded, _, _, values) = decode_dds(&bytes).unwrap();
+        assert_eq!(decoded, layout, "{format}");
+        assert_eq!(values, packed, "{format}");
+    }
+}
+
+#[test]
+fn rejects_malformed_headers() {
+    let layout = PixelFormat::Rgb565.layout();
+    let good = encode_dds(&layout, 4, 4, &values(&layout, 4, 4)).unwrap();
+
+    assert_eq!(
+        invalid_reason(decode_dds(&good[..100])),
+        "missing DDS header"
+    );
+    let mut magic = good.clone();
+    magic[..4].copy_from_slice(b"DDX ");
+    assert_eq!(invalid_reason(decode_dds(&magic)), "missing DDS header");
+
+    let mut compressed = good.clone();
+    compressed[80..84].copy_from_slice(&4u32.to_le_bytes());
+    compressed[84..88].copy_from_slice(b"DXT1");
+    assert_eq!(
+        invalid_reason(decode_dds(&compressed)),
+        "compressed surfaces are not supported"
+    );
+
+    let mut dx10 = compressed.clone();
+    dx10[84..88].copy_from_slice(b"DX10");
+    assert_eq!(
+        invalid_reason(decode_dds(&dx10[..140])),
+        "truncated DX10 header"
+    );
+
+    let mut bit_count = good.clone();
+    bit_count[88..92].copy_from_slice(&24u32.to_le_bytes());
+    assert_eq!(
+        invalid_reason(decode_dds(&bit_count)),
+        "unsupported bit count"
+    );
+
+    let mut overlapping = good.clone();
+    overlapping[96..100].copy_from_slice(&0xFFE0u32.to_le_bytes());
+    assert!(matches!(
+        decode_dds(&overlapping),
+        Err(Error::OverlappingMasks(..))
+    ));
+
+    let mut out_of_range = good.clone();
+    out_of_range[92..96].copy_from_slice(&0x1F_0000u32.to_le_bytes());
+    assert!(matches!(
+        decode_dds(&out_of_range),
+        Err(Error::MaskOutOfRange { .. })
+    ));
+}
+
+#[test]
+fn rejects_truncated_pixels() {
+    let layout = PixelFormat::Argb4444.layout();
+    let bytes = encode_dds(&layout, 4, 4, &values(&layout, 4, 4)).unwrap();
+    assert!(matches!(
+        decode_dds(&bytes[..bytes.len() - 1]),
+        Err(Error::Truncated {
+            expected: 160,
+            actual: 159
+        })
+    ));
+
+    // A forged pitch must not make the decoder read past the input.
+    let mut pitch = bytes.clone();
+    pitch[20..24].copy_from_slice(&u32::MAX.to_le_bytes());
+    assert!(matches!(decode_dds(&pitch), Err(Error::Truncated { .. })));
+}
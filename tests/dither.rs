@@ -0,0 +1,217 @@
+use image::{Rgba, RgbaImage};
+use image_packer::{
+    AlphaMode, Dither, Error, Layout, PackOptions, PixelFormat, ThresholdMap, round_trip,
+};
+
+const ORDERED: [&str; 5] = ["bayer2", "bayer4", "bayer8", "bayer16", "blue-noise"];
+
+fn dither(name: &str) -> Dither {
+    name.parse().unwrap()
+}
+
+fn options(dither: Dither) -> PackOptions {
+    PackOptions {
+        dither,
+        ..PackOptions::default()
+    }
+}
+
+/// Mean of one channel over an image.
+fn mean(img: &RgbaImage, channel: usize) -> f64 {
+    img.pixels().map(|px| f64::from(px.0[channel])).sum::<f64>() / img.pixels().len() as f64
+}
+
+#[test]
+fn names_parse_and_display() {
+    for name in ORDERED {
+        assert_eq!(dither(name).to_string(), name);
+    }
+    assert_eq!(dither("Bayer4"), Dither::Ordered(ThresholdMap::Bayer(4)));
+    assert!(matches!(
+        "bayer3".parse::<Dither>(),
+        Err(Error::UnknownDither(_))
+    ));
+}
+
+#[test]
+fn bayer_matrices_rank_every_cell_once() {
+    let bayer2 = ThresholdMap::Bayer(2);
+    let cells = [(0, 0), (1, 0), (0, 1), (1, 1)].map(|(x, y)| bayer2.threshold(x, y));
+    assert_eq!(cells, [0.125, 0.625, 0.875, 0.375]);
+
+    for n in [2, 4, 8, 16] {
+        let map = ThresholdMap::Bayer(n);
+        let mut ranks: Vec<u32> = (0..n * n)
+            .map(|i| (map.threshold(i % n, i / n) * (n * n) as f32 - 0.5).round() as u32)
+            .collect();
+        ranks.sort();
+        assert_eq!(ranks, (0..n * n).collect::<Vec<_>>(), "bayer{n}");
+        // The matrix tiles the plane.
+        assert_eq!(map.threshold(3, 5), map.threshold(3 + n, 5 + 2 * n));
+    }
+}
+
+#[test]
+fn blue_noise_ranks_every_cell_once_and_spreads_them() {
+    let map = ThresholdMap::BlueNoise;
+    let mut ranks: Vec<u32> = (0..1024)
+        .map(|i| (map.threshold(i % 32, i / 32) * 1024.0 - 0.5).round() as u32)
+        .collect();
+    assert_eq!(map.threshold(7, 9), map.threshold(39, 73));
+    // Low ranks are spread out: no 4x4 block holds more than a couple of the
+    // lowest 64, where clumping would show as blotches.
+    for by in 0..8 {
+        for bx in 0..8 {
+            let low = (0..16)
+                .filter(|i| ranks[(by * 4 + i / 4) * 32 + bx * 4 + i % 4] < 64)
+                .count();
+            assert!(low <= 3, "block {bx},{by} holds {low}");
+        }
+    }
+    ranks.sort();
+    assert_eq!(ranks, (0..1024).collect::<Vec<_>>());
+}
+
+#[test]
+fn flat_colours_mix_the_two_nearest_levels() {
+    // 136 lies halfway between the 5-bit levels 132 and 140; 8-bit rounding
+    // of the offsets leaves the mix within half a unit of it.
+    let img = RgbaImage::from_pixel(32, 32, Rgba([136, 136, 136, 255]));
+    let layout = PixelFormat::Argb1555.layout();
+    let plain = round_trip(&layout, &img, &PackOptions::default()).unwrap();
+    assert!(
+        plain
+            .pixels()
+            .all(|px| px.0[0] == plain.get_pixel(0, 0).0[0])
+    );
+    for name in ORDERED {
+        let out = round_trip(&layout, &img, &options(dither(name))).unwrap();
+        assert!(
+            out.pixels().all(|px| [132, 140].contains(&px.0[0])),
+            "{name}"
+        );
+        assert!(
+            (mean(&out, 0) - 136.0).abs() <= 0.5,
+            "{name}: {}",
+            mean(&out, 0)
+        );
+        // Alpha is left alone.
+        assert!(out.pixels().all(|px| px.0[3] == 255), "{name}");
+    }
+}
+
+#[test]
+fn flat_blocks_keep_their_average_level() {
+    // 16x16 blocks of flat values a few steps apart, so each block's mean
+    // shows how well the dither preserves its value; plain rounding is off
+    // by up to half a level.
+    let value = |x: u32, y: u32| (60 + (y / 16 * 8 + x / 16) * 3) as u8;
+    let img = RgbaImage::from_fn(128, 64, |x, y| Rgba([value(x, y), 90, 30, 255]));
+    let layout = PixelFormat::Rgb565.layout();
+    let block_error = |out: &RgbaImage| {
+        (0..32)
+            .map(|block| {
+                let (bx, by) = (block % 8 * 16, block / 8 * 16);
+                let sum: f64 = (0..256)
+                    .map(|i| f64::from(out.get_pixel(bx + i % 16, by + i / 16).0[0]))
+                    .sum();
+                (sum / 256.0 - f64::from(value(bx, by))).abs()
+            })
+            .fold(0.0, f64::max)
+    };
+    let plain = round_trip(&layout, &img, &PackOptions::default()).unwrap();
+    assert!(block_error(&plain) > 3.0, "{}", block_error(&plain));
+    for name in ORDERED {
+        let out = round_trip(&layout, &img, &options(dither(name))).unwrap();
+        assert!(block_error(&out) <= 1.0, "{name}: {}", block_error(&out));
+    }
+}
+
+fn alpha_options(alpha_dither: Dither) -> PackOptions {
+    PackOptions {
+        alpha_dither,
+        ..PackOptions::default()
+    }
+}
+
+/// Share of pixels left with any alpha.
+fn coverage(img: &RgbaImage) -> f64 {
+    img.pixels().filter(|px| px.0[3] != 0).count() as f64 / img.pixels().len() as f64
+}
+
+#[test]
+fn one_bit_alpha_becomes_a_stipple() {
+    let layout = PixelFormat::Argb1555.layout();
+    for alpha in [32, 128, 200] {
+        let img = RgbaImage::from_pixel(64, 64, Rgba([200, 100, 50, alpha]));
+        // Without alpha dithering any alpha above the threshold is opaque.
+        let plain = round_trip(&layout, &img, &PackOptions::default()).unwrap();
+        assert_eq!(coverage(&plain), 1.0);
+        for name in ["bayer4", "blue-noise", "floyd-steinberg", "sierra"] {
+            let out = round_trip(&layout, &img, &alpha_options(dither(name))).unwrap();
+            assert!(out.pixels().all(|px| [0, 255].contains(&px.0[3])));
+            let share = f64::from(alpha) / 255.0;
+            assert!(
+                (coverage(&out) - share).abs() < 0.03,
+                "{name} alpha {alpha}: {}",
+                coverage(&out)
+            );
+        }
+    }
+    // Half alpha covers exactly half of every Bayer tile.
+    let img = RgbaImage::from_pixel(8, 8, Rgba([0, 0, 0, 128]));
+    let out = round_trip(&layout, &img, &alpha_options(dither("bayer4"))).unwrap();
+    assert_eq!(coverage(&out), 0.5);
+}
+
+#[test]
+fn alpha_below_the_threshold_stays_transparent() {
+    let layout = Layout {
+        alpha_mode: AlphaMode {
+            threshold: 100,
+            ..AlphaMode::default()
+        },
+        ..PixelFormat::Argb1555.layout()
+    };
+    let faint = RgbaImage::from_pixel(32, 32, Rgba([200, 100, 50, 100]));
+    for name in ["bayer8", "floyd-steinberg"] {
+        let out = round_trip(&layout, &faint, &alpha_options(dither(name))).unwrap();
+        assert_eq!(coverage(&out), 0.0, "{name}");
+        // Just above it, the stipple follows the alpha value.
+        let above = RgbaImage::from_pixel(32, 32, Rgba([200, 100, 50, 101]));
+        let out = round_trip(&layout, &above, &alpha_options(dither(name))).unwrap();
+        assert!((coverage(&out) - 101.0 / 255.0).abs() < 0.03, "{name}");
+    }
+}
+
+#[test]
+fn alpha_dither_leaves_colour_alone() {
+    let layout = PixelFormat::Argb1555.layout();
+    let img = RgbaImage::from_fn(32, 16, |x, y| Rgba([x as u8 * 8, y as u8 * 16, 77, 150]));
+    let plain = round_trip(&layout, &img, &PackOptions::default()).unwrap();
+    let out = round_trip(&layout, &img, &alpha_options(dither("blue-noise"))).unwrap();
+    for (a, b) in plain.pixels().zip(out.pixels()) {
+        if b.0[3] != 0 {
+            assert_eq!(a.0[..3], b.0[..3]);
+        }
+    }
+}
+
+#[test]
+fn wider_alpha_mixes_the_two_nearest_levels() {
+    // 128 lies between the 4-bit levels 119 and 136.
+    let layout = PixelFormat::Argb4444.layout();
+    let img = RgbaImage::from_pixel(16, 16, Rgba([0, 0, 0, 128]));
+    for name in ORDERED {
+        let out = round_trip(&layout, &img, &alpha_options(dither(name))).unwrap();
+        assert!(
+            out.pixels().all(|px| [119, 136].contains(&px.0[3])),
+            "{name}"
+        );
+        assert!(
+            (mean(&out, 3) - 128.0).abs() <= 1.0,
+            "{name}: {}",
+            mean(&out, 3)
+        );
+    }
+}
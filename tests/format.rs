@@ -0,0 +1,355 @@
+use image::{Rgba, RgbaImage};
+use image_packer::{
+    AlphaMode, AlphaPolarity, Error, Layout, PackOptions, PixelFormat, pack, pack_slice,
+    round_trip, unpack, unpack_slice,
+};
+
+/// The R, G, B and A masks of every named format.
+const MASKS: [(PixelFormat, [u32; 4]); 12] = [
+    (PixelFormat::Argb1555, [0x7C00, 0x03E0, 0x001F, 0x8000]),
+    (PixelFormat::Xrgb1555, [0x7C00, 0x03E0, 0x001F, 0]),
+    (PixelFormat::Rgba5551, [0xF800, 0x07C0, 0x003E, 0x0001]),
+    (PixelFormat::Abgr1555, [0x001F, 0x03E0, 0x7C00, 0x8000]),
+    (PixelFormat::Xbgr1555, [0x001F, 0x03E0, 0x7C00, 0]),
+    (PixelFormat::Bgra5551, [0x003E, 0x07C0, 0xF800, 0x0001]),
+    (PixelFormat::Rgb565, [0xF800, 0x07E0, 0x001F, 0]),
+    (PixelFormat::Bgr565, [0x001F, 0x07E0, 0xF800, 0]),
+    (PixelFormat::Argb4444, [0x0F00, 0x00F0, 0x000F, 0xF000]),
+    (PixelFormat::Rgba4444, [0xF000, 0x0F00, 0x00F0, 0x000F]),
+    (PixelFormat::Abgr4444, [0x000F, 0x00F0, 0x0F00, 0xF000]),
+    (PixelFormat::Bgra4444, [0x00F0, 0x0F00, 0xF000, 0x000F]),
+];
+
+#[test]
+fn names_parse_and_display() {
+    for format in PixelFormat::ALL {
+        assert_eq!(format.name().parse::<PixelFormat>().unwrap(), format);
+        let upper = format.name().to_ascii_uppercase();
+        assert_eq!(upper.parse::<PixelFormat>().unwrap(), format);
+        assert_eq!(format.to_string(), format.name());
+        assert_eq!(format.name().parse::<Layout>().unwrap(), format.layout());
+    }
+    assert_eq!(
+        "rgb555".parse::<PixelFormat>().unwrap(),
+        PixelFormat::Xrgb1555
+    );
+    assert_eq!(
+        "BGR555".parse::<PixelFormat>().unwrap(),
+        PixelFormat::Xbgr1555
+    );
+    assert!(matches!(
+        "rgb888".parse::<PixelFormat>(),
+        Err(Error::UnknownFormat(name)) if name == "rgb888"
+    ));
+}
+
+#[test]
+fn layouts_place_each_channel() {
+    for (format, masks) in MASKS {
+        let layout = format.layout();
+        assert_eq!(layout.bits, 16, "{format}");
+        assert_eq!(layout.masks(), masks, "{format}");
+    }
+}
+
+#[test]
+fn argb1555_matches_the_original_packing() {
+    let layout = PixelFormat::Argb1555.layout();
+    for v in (0..=255u8).step_by(3) {
+        let (r, g, b) = (v, v.wrapping_mul(7), 255 - v);
+        for a in [0, 1, 128, 255] {
+            let packed = layout.pack([r, g, b, a]);
+            assert_eq!(packed, u32::from(pack(r, g, b, a > 0)));
+        }
+    }
+    for value in 0..=u16::MAX {
+        let (r, g, b, a) = unpack(value);
+        assert_eq!(layout.unpack(value.into()), [r, g, b, a]);
+    }
+}
+
+#[test]
+fn every_value_round_trips() {
+    for format in PixelFormat::ALL {
+        let layout = format.layout();
+        let used = layout.masks().into_iter().fold(0, |all, m| all | m);
+        for value in 0..=0xFFFF {
+            let value = value & used;
+            assert_eq!(layout.pack(layout.unpack(value)), value, "{format}");
+        }
+    }
+}
+
+#[test]
+fn channels_round_to_the_nearest_level() {
+    for (format, _) in MASKS {
+        let layout = format.layout();
+        for (name, ch) in layout.channels() {
+            let step = 255.0 / ch.max() as f32;
+            assert_eq!((ch.expand(0), ch.expand(ch.max())), (0, 255));
+            for v in 0..=255u8 {
+                let back = ch.expand(ch.quantize(v));
+                let error = (f32::from(back) - f32::from(v)).abs();
+                assert!(error <= step / 2.0 + 0.5, "{format} {name} {v}");
+            }
+        }
+    }
+}
+
+#[test]
+fn missing_channels_unpack_as_black_and_opaque() {
+    let rgb565 = PixelFormat::Rgb565.layout();
+    assert_eq!(rgb565.unpack(0), [0, 0, 0, 255]);
+    assert_eq!(rgb565.pack([255, 255, 255, 0]), 0xFFFF);
+    let xrgb = PixelFormat::Xrgb1555.layout();
+    // The padding bit stays clear.
+    assert_eq!(xrgb.pack([255, 255, 255, 255]), 0x7FFF);
+}
+
+#[test]
+fn slices_need_a_wide_enough_container() {
+    let layout = PixelFormat::Argb4444.layout();
+    let rgba = [255, 0, 0, 255, 0, 0, 255, 0];
+    let mut packed = [0u16; 2];
+    pack_slice(&layout, &rgba, &mut packed).unwrap();
+    assert_eq!(packed, [0xFF00, 0x000F]);
+    let mut back = [0u8; 8];
+    unpack_slice(&layout, &packed, &mut back).unwrap();
+    assert_eq!(back, rgba);
+
+    let mut wide = [0u32; 2];
+    pack_slice(&layout, &rgba, &mut wide).unwrap();
+    assert_eq!(wide, [0xFF00, 0x000F]);
+    let mut narrow = [0u8; 2];
+    assert!(matches!(
+        pack_slice(&layout, &rgba, &mut narrow),
+        Err(Error::ContainerTooSmall {
+            layout: 16,
+            container: 8
+        })
+    ));
+}
+
+fn spec_error(spec: &str) -> String {
+    match Layout::parse_spec(spec) {
+        Err(Error::InvalidLayout { spec: s, reason }) if s == spec => reason,
+        other => panic!("expected {spec} to be rejected, got {other:?}"),
+    }
+}
+
+#[test]
+fn specs_describe_the_named_formats() {
+    for format in PixelFormat::ALL {
+        let spec = format.layout().to_string();
+        assert_eq!(
+            Layout::parse_spec(&spec).unwrap(),
+            format.layout(),
+            "{spec}"
+        );
+    }
+    assert_eq!(
+        Layout::parse_spec("a1r5g5b5").unwrap(),
+        PixelFormat::Argb1555.layout()
+    );
+    assert_eq!(
+        Layout::parse_spec("X1R5G5B5").unwrap(),
+        PixelFormat::Xrgb1555.layout()
+    );
+}
+
+#[test]
+fn specs_set_widths_containers_and_padding() {
+    let cases = [
+        ("R3G3B2", 8, [0xE0, 0x1C, 0x03, 0]),
+        ("L6A2", 8, [0xFC, 0, 0, 0x03]),
+        ("R5G5B5X1", 16, [0xF800, 0x07C0, 0x003E, 0]),
+        ("A8L8", 16, [0x00FF, 0, 0, 0xFF00]),
+        (
+            "R10G10B10A2",
+            32,
+            [0xFFC0_0000, 0x003F_F000, 0x0000_0FFC, 0x3],
+        ),
+        ("X8B8G8R8", 32, [0xFF, 0xFF00, 0xFF_0000, 0]),
+    ];
+    for (spec, bits, masks) in cases {
+        let layout = Layout::parse_spec(spec).unwrap();
+        assert_eq!((layout.bits, layout.masks()), (bits, masks), "{spec}");
+        assert_eq!(layout.to_string(), spec);
+        // Channels wider than 8 bits cannot survive a trip through RGBA8.
+        if layout.channels().any(|(_, ch)| ch.bits > 8) {
+            continue;
+        }
+        let used = masks.into_iter().fold(0, |all, m| all | m);
+        for i in 0..4096u32 {
+            let value = i.wrapping_mul(0x9E37_79B9) & used;
+            assert_eq!(layout.pack(layout.unpack(value)), value, "{spec}");
+        }
+    }
+}
+
+#[test]
+fn luminance_is_the_luma_of_the_colour() {
+    let layout = Layout::parse_spec("L8").unwrap();
+    assert_eq!(layout.pack([255, 255, 255, 255]), 255);
+    assert_eq!(layout.pack([0, 255, 0, 255]), 149);
+    assert_eq!(layout.unpack(149), [149, 149, 149, 255]);
+}
+
+#[test]
+fn rejects_malformed_specs() {
+    let cases = [
+        ("R5G5B5Q1", "unexpected character `Q`"),
+        ("R5G6B", "channel `B` has no width"),
+        ("R0G8B8", "channel `R` width must be between 1 and 16"),
+        ("R17X15", "channel `R` width must be between 1 and 16"),
+        ("X33", "channel `X` width must be between 1 and 32"),
+        ("R5G5B5", "widths add up to 15 bits, expected 8, 16 or 32"),
+        (
+            "R16G16B16X16",
+            "widths add up to 64 bits, expected 8, 16 or 32",
+        ),
+        ("R5R5G6", "channel `R` appears more than once"),
+        ("L8R8", "`L` cannot be combined with `R`, `G` or `B`"),
+        ("X16", "layout has no channels"),
+        ("", "widths add up to 0 bits, expected 8, 16 or 32"),
+    ];
+    for (spec, reason) in cases {
+        assert_eq!(spec_error(spec), reason, "{spec}");
+    }
+    assert!(spec_error("R99999999999G1").starts_with("channel `R` width `99999999999`"));
+}
+
+#[test]
+fn masks_must_fit_and_not_overlap() {
+    let layout = Layout::from_masks(16, [0xF800, 0x07E0, 0x001F, 0], false).unwrap();
+    assert_eq!(layout, PixelFormat::Rgb565.layout());
+    let luminance = Layout::from_masks(16, [0xFF, 0, 0, 0xFF00], true).unwrap();
+    assert_eq!(luminance, Layout::parse_spec("A8L8").unwrap());
+
+    assert!(matches!(
+        Layout::from_masks(16, [0x1F_0000, 0x07E0, 0x001F, 0], false),
+        Err(Error::MaskOutOfRange {
+            mask: 0x1F_0000,
+            bits: 16
+        })
+    ));
+    assert!(matches!(
+        Layout::from_masks(16, [0xFC00, 0x07E0, 0x001F, 0], false),
+        Err(Error::OverlappingMasks(0xFC00, 0x07E0))
+    ));
+    // Channels must be one contiguous run of at most 16 bits.
+    assert!(matches!(
+        Layout::from_masks(16, [0xF0F0, 0, 0, 0], false),
+        Err(Error::InvalidMask(0xF0F0))
+    ));
+    assert!(matches!(
+        Layout::from_masks(32, [0x1_FFFF, 0, 0, 0], false),
+        Err(Error::InvalidMask(0x1_FFFF))
+    ));
+}
+
+const POLARITIES: [AlphaPolarity; 3] = [
+    AlphaPolarity::Opaque,
+    AlphaPolarity::Transparent,
+    AlphaPolarity::SemiTransparent,
+];
+
+fn with_alpha(format: PixelFormat, threshold: u8, polarity: AlphaPolarity) -> Layout {
+    Layout {
+        alpha_mode: AlphaMode {
+            threshold,
+            polarity,
+        },
+        ..format.layout()
+    }
+}
+
+#[test]
+fn polarities_parse_and_display() {
+    for polarity in POLARITIES {
+        assert_eq!(
+            polarity.to_string().parse::<AlphaPolarity>().unwrap(),
+            polarity
+        );
+    }
+    assert_eq!(
+        "Semi-Transparent".parse::<AlphaPolarity>().unwrap(),
+        AlphaPolarity::SemiTransparent
+    );
+    assert!(matches!(
+        "inverted".parse::<AlphaPolarity>(),
+        Err(Error::UnknownAlphaPolarity(_))
+    ));
+}
+
+#[test]
+fn threshold_decides_coverage() {
+    for threshold in [0, 1, 127, 254] {
+        let layout = with_alpha(PixelFormat::Argb1555, threshold, AlphaPolarity::Opaque);
+        for a in 0..=255u8 {
+            let set = layout.pack([200, 100, 50, a]) & 0x8000 != 0;
+            assert_eq!(set, a > threshold, "threshold {threshold} alpha {a}");
+        }
+    }
+}
+
+#[test]
+fn polarity_sets_the_alpha_bit() {
+    let (opaque, faint, clear) = ([200, 100, 50, 255], [200, 100, 50, 100], [200, 100, 50, 0]);
+    let expected = [
+        (AlphaPolarity::Opaque, [0xE186, 0xE186, 0x6186]),
+        (AlphaPolarity::Transparent, [0x6186, 0x6186, 0xE186]),
+        // Opaque colours clear the bit, partial alpha sets it and
+        // transparency is all zero.
+        (AlphaPolarity::SemiTransparent, [0x6186, 0xE186, 0]),
+    ];
+    for (polarity, packed) in expected {
+        let layout = with_alpha(PixelFormat::Argb1555, 0, polarity);
+        let got = [opaque, faint, clear].map(|px| layout.pack(px));
+        assert_eq!(got, packed, "{polarity}");
+    }
+}
+
+#[test]
+fn semi_transparent_keeps_opaque_black() {
+    let layout = with_alpha(PixelFormat::Argb1555, 0, AlphaPolarity::SemiTransparent);
+    // Black cannot clear the bit without reading back as transparent.
+    assert_eq!(layout.pack([0, 0, 0, 255]), 0x8000);
+    assert_eq!(layout.unpack(0x8000), [0, 0, 0, 255]);
+    assert_eq!(layout.unpack(0), [0, 0, 0, 0]);
+    assert_eq!(layout.unpack(0xE186)[3], 128);
+    assert_eq!(layout.unpack(0x6186)[3], 255);
+}
+
+#[test]
+fn every_value_round_trips_in_each_polarity() {
+    for format in [PixelFormat::Argb1555, PixelFormat::Rgba5551] {
+        for polarity in POLARITIES {
+            let layout = with_alpha(format, 0, polarity);
+            for value in 0..=0xFFFF {
+                let back = layout.pack(layout.unpack(value));
+                assert_eq!(back, value, "{format} {polarity} {value:#06x}");
+            }
+        }
+    }
+}
+
+#[test]
+fn images_keep_their_coverage_in_each_polarity() {
+    let img = RgbaImage::from_fn(16, 4, |x, y| {
+        Rgba([
+            x as u8 * 16,
+            y as u8 * 60,
+            90,
+            [0, 40, 200, 255][y as usize],
+        ])
+    });
+    for polarity in POLARITIES {
+        let layout = with_alpha(PixelFormat::Bgra5551, 100, polarity);
+        let back = round_trip(&layout, &img, &PackOptions::default()).unwrap();
+        for (before, after) in img.pixels().zip(back.pixels()) {
+            let covered = before.0[3] > 100;
+            assert_eq!(after.0[3] != 0, covered, "{polarity} {before:?}");
+        }
+    }
+}
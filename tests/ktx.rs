@@ -0,0 +1,213 @@
+use image_packer::{Error, Layout, decode_ktx, decode_ktx2, encode_ktx, encode_ktx2};
+
+/// Layouts with a packed OpenGL type, and their `(type, format, internal
+/// format)`.
+const GL: [(&str, [u32; 3]); 11] = [
+    ("A1R5G5B5", [0x8366, 0x80E1, 0x8057]),
+    ("A1B5G5R5", [0x8366, 0x1908, 0x8057]),
+    ("R5G5B5A1", [0x8034, 0x1908, 0x8057]),
+    ("B5G5R5A1", [0x8034, 0x80E1, 0x8057]),
+    ("R5G6B5", [0x8363, 0x1907, 0x8D62]),
+    ("B5G6R5", [0x8364, 0x1907, 0x8D62]),
+    ("R4G4B4A4", [0x8033, 0x1908, 0x8056]),
+    ("B4G4R4A4", [0x8033, 0x80E1, 0x8056]),
+    ("A4R4G4B4", [0x8365, 0x80E1, 0x8056]),
+    ("A4B4G4R4", [0x8365, 0x1908, 0x8056]),
+    ("R3G3B2", [0x8032, 0x1907, 0x2A10]),
+];
+
+/// Layouts with a packed `VkFormat`, and its value.
+const VK: [(&str, u32); 11] = [
+    ("R4G4", 1),
+    ("R4G4B4A4", 2),
+    ("B4G4R4A4", 3),
+    ("R5G6B5", 4),
+    ("B5G6R5", 5),
+    ("R5G5B5A1", 6),
+    ("B5G5R5A1", 7),
+    ("A1R5G5B5", 8),
+    ("A4R4G4B4", 1000340000),
+    ("A4B4G4R4", 1000340001),
+    ("A1B5G5R5", 1000470000),
+];
+
+const SIZES: [(u32, u32); 4] = [(1, 1), (3, 5), (16, 9), (7, 1)];
+
+fn layout(spec: &str) -> Layout {
+    Layout::parse_spec(spec).unwrap()
+}
+
+/// Scrambled values using every bit the layout stores.
+fn values(layout: &Layout, width: u32, height: u32) -> Vec<u32> {
+    let mask = layout.masks().into_iter().fold(0, |all, m| all | m);
+    (0..width * height)
+        .map(|i| i.wrapping_mul(0x9E37_79B9).rotate_left(i % 32) & mask)
+        .collect()
+}
+
+fn word(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+}
+
+fn invalid_reason(result: Result<(Layout, u32, u32, Vec<u32>), Error>) -> String {
+    match result {
+        Err(Error::InvalidFile { reason, .. }) => reason,
+        other => panic!("expected an invalid file, got {other:?}"),
+    }
+}
+
+#[test]
+fn ktx1_round_trips_every_gl_format() {
+    for (spec, gl) in GL {
+        let layout = layout(spec);
+        for (width, height) in SIZES {
+            let packed = values(&layout, width, height);
+            let bytes = encode_ktx(&layout, width, height, &packed).unwrap();
+            let header: Vec<u32> = [16, 24, 28].map(|at| word(&bytes, at)).to_vec();
+            assert_eq!(header, gl, "{spec}");
+            assert_eq!(word(&bytes, 20), layout.bits / 8, "{spec} glTypeSize");
+            // Rows are padded to 4 bytes.
+            let pitch = (width * layout.bits / 8).next_multiple_of(4);
+            assert_eq!(word(&bytes, 64), pitch * height, "{spec} imageSize");
+            assert_eq!(bytes.len(), 68 + (pitch * height) as usize, "{spec}");
+
+            let (decoded, w, h, values) = decode_ktx(&bytes).unwrap();
+            assert_eq!(decoded, layout, "{spec}");
+            assert_eq!((w, h), (width, height), "{spec}");
+            assert_eq!(values, packed, "{spec} {width}x{height}");
+        }
+    }
+}
+
+#[test]
+fn ktx1_reads_big_endian_files() {
+    let layout = layout("R5G6B5");
+    let packed = values(&layout, 3, 2);
+    let mut bytes = encode_ktx(&layout, 3, 2, &packed).unwrap();
+    // Swap every header word and every 16-bit value, leaving row padding.
+    bytes[12..68].chunks_mut(4).for_each(<[u8]>::reverse);
+    for row in bytes[68..].chunks_mut(8) {
+        row[..6].chunks_mut(2).for_each(<[u8]>::reverse);
+    }
+    let (decoded, w, h, values) = decode_ktx(&bytes).unwrap();
+    assert_eq!((decoded, w, h), (layout, 3, 2));
+    assert_eq!(values, packed);
+}
+
+#[test]
+fn ktx2_round_trips_every_vk_format() {
+    for (spec, vk_format) in VK {
+        let layout = layout(spec);
+        for (width, height) in SIZES {
+            let packed = values(&layout, width, height);
+            let bytes = encode_ktx2(&layout, width, height, &packed).unwrap();
+            assert_eq!(word(&bytes, 12), vk_format, "{spec}");
+            assert_eq!(word(&bytes, 16), layout.bits / 8, "{spec} typeSize");
+            assert_eq!((word(&bytes, 20), word(&bytes, 24)), (width, height));
+
+            let (decoded, w, h, values) = decode_ktx2(&bytes).unwrap();
+            assert_eq!(decoded, layout, "{spec}");
+            assert_eq!((w, h), (width, height), "{spec}");
+            assert_eq!(values, packed, "{spec} {width}x{height}");
+        }
+    }
+}
+
+#[test]
+fn ktx2_describes_each_channel() {
+    let layout = layout("A1R5G5B5");
+    let bytes = encode_ktx2(&layout, 2, 2, &values(&layout, 2, 2)).unwrap();
+    let (dfd_offset, dfd_length) = (word(&bytes, 48) as usize, word(&bytes, 52) as usize);
+    let dfd: Vec<u32> = bytes[dfd_offset..dfd_offset + dfd_length]
+        .chunks_exact(4)
+        .map(|w| u32::from_le_bytes(w.try_into().unwrap()))
+        .collect();
+    // Total size, then a basic descriptor block of 24 bytes plus 16 per
+    // sample: RGBSDA colour model, BT.709 primaries, linear transfer and
+    // two bytes per texel.
+    assert_eq!(dfd.len(), 1 + 6 + 4 * 4);
+    assert_eq!(dfd[0] as usize, dfd_length);
+    assert_eq!(dfd[2], 2 | 88 << 16);
+    assert_eq!(dfd[3], 1 | 1 << 8 | 1 << 16);
+    assert_eq!(dfd[5], 2);
+    // Samples from the lowest bit up: blue, green, red and alpha, each
+    // with its offset, width minus one, channel id and upper value.
+    let samples: Vec<(u32, u32)> = dfd[7..].chunks(4).map(|s| (s[0], s[3])).collect();
+    assert_eq!(
+        samples,
+        [
+            (4 << 16 | 2 << 24, 31),
+            (5 | 4 << 16 | 1 << 24, 31),
+            (10 | 4 << 16, 31),
+            (15 | 15 << 24, 1),
+        ]
+    );
+    // The level data follows the descriptor on a 4-byte boundary.
+    let data_offset = u64::from_le_bytes(bytes[80..88].try_into().unwrap()) as usize;
+    assert_eq!(data_offset, (dfd_offset + dfd_length).next_multiple_of(4));
+    assert_eq!(bytes.len(), data_offset + 8);
+}
+
+#[test]
+fn formats_without_a_packed_type_are_refused() {
+    let luminance = layout("L8");
+    assert!(encode_ktx(&luminance, 1, 1, &[0]).is_err());
+    assert!(encode_ktx2(&luminance, 1, 1, &[0]).is_err());
+    let r3g3b2 = layout("R3G3B2");
+    assert!(encode_ktx2(&r3g3b2, 1, 1, &[0]).is_err());
+}
+
+#[test]
+fn rejects_malformed_headers() {
+    let layout = layout("R5G6B5");
+    let packed = values(&layout, 4, 4);
+    let ktx = encode_ktx(&layout, 4, 4, &packed).unwrap();
+    let ktx2 = encode_ktx2(&layout, 4, 4, &packed).unwrap();
+
+    assert_eq!(
+        invalid_reason(decode_ktx(&ktx[..60])),
+        "missing KTX 1 header"
+    );
+    assert_eq!(invalid_reason(decode_ktx(&ktx2)), "missing KTX 1 header");
+    let mut endianness = ktx.clone();
+    endianness[12..16].copy_from_slice(&[1, 1, 1, 1]);
+    assert_eq!(
+        invalid_reason(decode_ktx(&endianness)),
+        "bad endianness marker"
+    );
+    let mut gl_type = ktx.clone();
+    gl_type[16..20].copy_from_slice(&0x1401u32.to_le_bytes()); // GL_UNSIGNED_BYTE
+    assert_eq!(
+        invalid_reason(decode_ktx(&gl_type)),
+        "unsupported OpenGL format"
+    );
+    assert!(matches!(
+        decode_ktx(&ktx[..ktx.len() - 1]),
+        Err(Error::Truncated { .. })
+    ));
+
+    assert_eq!(
+        invalid_reason(decode_ktx2(&ktx2[..90])),
+        "missing KTX 2 header"
+    );
+    assert_eq!(invalid_reason(decode_ktx2(&ktx)), "missing KTX 2 header");
+    let mut vk_format = ktx2.clone();
+    vk_format[12..16].copy_from_slice(&37u32.to_le_bytes()); // R8G8B8A8_UNORM
+    assert_eq!(
+        invalid_reason(decode_ktx2(&vk_format)),
+        "unsupported VkFormat"
+    );
+    let mut scheme = ktx2.clone();
+    scheme[44..48].copy_from_slice(&1u32.to_le_bytes());
+    assert_eq!(
+        invalid_reason(decode_ktx2(&scheme)),
+        "supercompressed files are not supported"
+    );
+    let mut offset = ktx2.clone();
+    offset[80..88].copy_from_slice(&u64::MAX.to_le_bytes());
+    assert!(matches!(decode_ktx2(&offset), Err(Error::Truncated { .. })));
+    assert!(matches!(
+        decode_ktx2(&ktx2[..ktx2.len() - 1]),
+        Err(Error::Truncated { .. })
+    ));
+}
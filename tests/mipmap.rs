@@ -0,0 +1,165 @@
+use image::{DynamicImage, Rgba, RgbaImage};
+use image_packer::{
+    AlphaMode, AlphaPolarity, MipFilter, MipOptions, chain_layout, mip_levels, mip_sizes,
+};
+
+const FILTERS: [MipFilter; 4] = [
+    MipFilter::Box,
+    MipFilter::Triangle,
+    MipFilter::Mitchell,
+    MipFilter::Lanczos,
+];
+
+/// Alpha test passing values above 200, as for alpha-tested foliage.
+const ALPHA_TEST: AlphaMode = AlphaMode {
+    threshold: 200,
+    polarity: AlphaPolarity::Opaque,
+};
+
+/// A 64x64 image whose alpha is scattered over the whole range, so small
+/// levels average it towards the middle.
+fn foliage() -> DynamicImage {
+    DynamicImage::ImageRgba8(RgbaImage::from_fn(64, 64, |x, y| {
+        let a = (x * 37 + y * 91 + x * y * 13) % 256;
+        Rgba([40, 160, 30, a as u8])
+    }))
+}
+
+fn coverage(level: &RgbaImage) -> f64 {
+    let covered = level.pixels().filter(|px| ALPHA_TEST.covers(px.0[3]));
+    covered.count() as f64 / level.pixels().len() as f64
+}
+
+#[test]
+fn sizes_halve_down_to_one_pixel() {
+    assert_eq!(
+        mip_sizes(256, 64, None),
+        [
+            (256, 64),
+            (128, 32),
+            (64, 16),
+            (32, 8),
+            (16, 4),
+            (8, 2),
+            (4, 1),
+            (2, 1),
+            (1, 1)
+        ]
+    );
+    // Odd sizes round down.
+    assert_eq!(mip_sizes(5, 3, None), [(5, 3), (2, 1), (1, 1)]);
+    assert_eq!(mip_sizes(1, 1, None), [(1, 1)]);
+}
+
+#[test]
+fn level_counts_are_clamped() {
+    assert_eq!(mip_sizes(16, 16, Some(3)), [(16, 16), (8, 8), (4, 4)]);
+    assert_eq!(mip_sizes(16, 16, Some(0)), [(16, 16)]);
+    assert_eq!(mip_sizes(16, 16, Some(100)).len(), 5);
+}
+
+#[test]
+fn chain_puts_smaller_levels_beside_the_first() {
+    let (width, height, offsets) = chain_layout(&mip_sizes(16, 8, None));
+    assert_eq!((width, height), (24, 8));
+    assert_eq!(offsets, [(0, 0), (16, 0), (16, 4), (16, 6), (16, 7)]);
+
+    // A tall image's stack of smaller levels stays within its height.
+    let (width, height, offsets) = chain_layout(&mip_sizes(4, 16, None));
+    assert_eq!((width, height), (6, 16));
+    assert_eq!(offsets, [(0, 0), (4, 0), (4, 8), (4, 12), (4, 14)]);
+
+    assert_eq!(chain_layout(&[(7, 3)]), (7, 3, vec![(0, 0)]));
+    assert_eq!(chain_layout(&[]), (0, 0, Vec::new()));
+}
+
+#[test]
+fn chain_levels_fit_without_overlapping() {
+    for (w, h) in [(64, 64), (100, 30), (30, 100), (1, 9)] {
+        let sizes = mip_sizes(w, h, None);
+        let (width, height, offsets) = chain_layout(&sizes);
+        let rects: Vec<_> = sizes.into_iter().zip(offsets).collect();
+        for (i, &((lw, lh), (x, y))) in rects.iter().enumerate() {
+            assert!(x + lw <= width && y + lh <= height, "{w}x{h} level {i}");
+            for &((ow, oh), (ox, oy)) in &rects[i + 1..] {
+                let apart = x + lw <= ox || ox + ow <= x || y + lh <= oy || oy + oh <= y;
+                assert!(apart, "{w}x{h} level {i}");
+            }
+        }
+    }
+}
+
+#[test]
+fn levels_match_their_sizes() {
+    let img = foliage();
+    for filter in FILTERS {
+        let options = MipOptions {
+            filter,
+            ..MipOptions::default()
+        };
+        let levels = mip_levels(&img, None, &options);
+        let sizes: Vec<_> = levels.iter().map(RgbaImage::dimensions).collect();
+        assert_eq!(sizes, mip_sizes(64, 64, None), "{filter}");
+        assert_eq!(levels[0], img.to_rgba8(), "{filter}");
+    }
+}
+
+#[test]
+fn flat_colours_stay_flat() {
+    let img = DynamicImage::ImageRgba8(RgbaImage::from_pixel(24, 10, Rgba([90, 180, 30, 255])));
+    for filter in FILTERS {
+        let options = MipOptions {
+            filter,
+            ..MipOptions::default()
+        };
+        for level in mip_levels(&img, None, &options) {
+            for px in level.pixels() {
+                assert_eq!(*px, Rgba([90, 180, 30, 255]), "{filter}");
+            }
+        }
+    }
+}
+
+#[test]
+fn averages_in_linear_light_with_premultiplied_alpha() {
+    // Black and white average to linear 0.5, which is 188 in sRGB.
+    let checker = RgbaImage::from_fn(2, 2, |x, y| match (x + y) % 2 {
+        0 => Rgba([0, 0, 0, 255]),
+        _ => Rgba([255, 255, 255, 255]),
+    });
+    let levels = mip_levels(&checker.into(), None, &MipOptions::default());
+    assert_eq!(*levels[1].get_pixel(0, 0), Rgba([188, 188, 188, 255]));
+
+    // A transparent pixel's colour does not bleed into its neighbour.
+    let edge = RgbaImage::from_fn(2, 1, |x, _| match x {
+        0 => Rgba([255, 0, 0, 255]),
+        _ => Rgba([0, 255, 0, 0]),
+    });
+    let levels = mip_levels(&edge.into(), None, &MipOptions::default());
+    assert_eq!(*levels[1].get_pixel(0, 0), Rgba([255, 0, 0, 128]));
+}
+
+#[test]
+fn one_bit_alpha_keeps_its_coverage() {
+    let img = foliage();
+    let target = coverage(&img.to_rgba8());
+    let options = MipOptions::default();
+    let kept = mip_levels(&img, Some(ALPHA_TEST), &options);
+    let plain = mip_levels(&img, None, &options);
+    // Levels down to 8x8 have pixels enough to match it closely, while
+    // plain averaging loses nearly all of it.
+    for level in 1..=3 {
+        let (kept, plain) = (coverage(&kept[level]), coverage(&plain[level]));
+        assert!(
+            (kept - target).abs() < 0.03,
+            "level {level}: {kept} for {target}"
+        );
+        assert!(plain < target / 2.0, "level {level}: {plain} for {target}");
+    }
+
+    let off = MipOptions {
+        preserve_coverage: false,
+        ..options
+    };
+    assert_eq!(mip_levels(&img, Some(ALPHA_TEST), &off), plain);
+}
@@ -0,0 +1,153 @@
+use image::{Rgba, RgbaImage};
+use image_packer::{
+    Dither, Error, Indexed, Layout, PackOptions, PaletteOptions, PixelFormat, Quantizer,
+    quantize_indexed, read_indices,
+};
+
+const QUANTIZERS: [Quantizer; 2] = [Quantizer::MedianCut, Quantizer::KMeans];
+
+/// A gradient with far more colours than any palette holds, and a
+/// transparent hole in the middle.
+fn gradient() -> RgbaImage {
+    RgbaImage::from_fn(32, 24, |x, y| {
+        let a = if (10..22).contains(&x) && (8..16).contains(&y) {
+            0
+        } else {
+            255
+        };
+        Rgba([x as u8 * 8, y as u8 * 10, (x * y) as u8, a])
+    })
+}
+
+fn palette(index_bits: u32, quantizer: Quantizer, transparent_index: bool) -> PaletteOptions {
+    PaletteOptions {
+        index_bits,
+        quantizer,
+        transparent_index,
+    }
+}
+
+fn quantize(layout: &Layout, img: &RgbaImage, options: &PaletteOptions) -> Indexed {
+    quantize_indexed(layout, img, &PackOptions::default(), options).unwrap()
+}
+
+#[test]
+fn palettes_fit_their_index_bits() {
+    let layout = PixelFormat::Argb4444.layout();
+    let img = gradient();
+    for index_bits in [4, 8] {
+        for quantizer in QUANTIZERS {
+            for transparent_index in [false, true] {
+                let options = palette(index_bits, quantizer, transparent_index);
+                let indexed = quantize(&layout, &img, &options);
+                assert_eq!(indexed.palette.len(), 1 << index_bits, "{quantizer}");
+                assert_eq!(indexed.indices.len(), 32 * 24);
+                assert!(
+                    indexed
+                        .indices
+                        .iter()
+                        .all(|&i| (i as usize) < 1 << index_bits),
+                    "{quantizer} {index_bits}-bit"
+                );
+                // Entries are exactly representable in the layout.
+                for &entry in &indexed.palette {
+                    assert_eq!(layout.pack(layout.unpack(entry)), entry);
+                }
+                assert_eq!(indexed.resolve().unwrap().len(), indexed.indices.len());
+            }
+        }
+    }
+}
+
+#[test]
+fn few_colours_are_kept_exactly() {
+    let layout = PixelFormat::Rgb565.layout();
+    let colours = [[255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 255]];
+    let img = RgbaImage::from_fn(9, 4, |x, y| Rgba(colours[((x + y) % 3) as usize]));
+    for quantizer in QUANTIZERS {
+        let indexed = quantize(&layout, &img, &palette(4, quantizer, false));
+        let expected: Vec<u32> = img.pixels().map(|px| layout.pack(px.0)).collect();
+        assert_eq!(indexed.resolve().unwrap(), expected, "{quantizer}");
+    }
+}
+
+#[test]
+fn transparent_pixels_keep_index_zero() {
+    let layout = PixelFormat::Argb1555.layout();
+    let img = gradient();
+    let dithers: [Dither; 3] = ["none", "floyd-steinberg", "bayer4"].map(|d| d.parse().unwrap());
+    for dither in dithers {
+        let options = PackOptions {
+            dither,
+            ..PackOptions::default()
+        };
+        for index_bits in [4, 8] {
+            let indexed = quantize_indexed(
+                &layout,
+                &img,
+                &options,
+                &palette(index_bits, Quantizer::KMeans, true),
+            )
+            .unwrap();
+            assert_eq!(indexed.palette[0], layout.pack([0, 0, 0, 0]));
+            for (px, &index) in img.pixels().zip(&indexed.indices) {
+                assert_eq!(px.0[3] == 0, index == 0, "{dither} {index_bits}-bit");
+            }
+        }
+    }
+}
+
+#[test]
+fn transparency_follows_the_alpha_rule() {
+    let mut layout = PixelFormat::Argb1555.layout();
+    layout.alpha_mode.threshold = 100;
+    // Alpha 60 is below the threshold, so those pixels pack as transparent.
+    let img = RgbaImage::from_fn(8, 2, |x, y| {
+        Rgba([x as u8 * 30, 200, 50, if y == 0 { 60 } else { 255 }])
+    });
+    let indexed = quantize(&layout, &img, &palette(4, Quantizer::MedianCut, true));
+    assert!(indexed.indices[..8].iter().all(|&i| i == 0));
+    assert!(indexed.indices[8..].iter().all(|&i| i != 0));
+
+    // Without a transparent index every pixel is quantized like the rest.
+    let indexed = quantize(&layout, &img, &palette(4, Quantizer::MedianCut, false));
+    let resolved = indexed.resolve().unwrap();
+    assert!(resolved[..8].iter().all(|&v| layout.unpack(v)[3] == 0));
+}
+
+#[test]
+fn rejects_other_index_bits() {
+    let layout = PixelFormat::Argb1555.layout();
+    for index_bits in [0, 2, 16] {
+        let result = quantize_indexed(
+            &layout,
+            &gradient(),
+            &PackOptions::default(),
+            &palette(index_bits, Quantizer::MedianCut, false),
+        );
+        assert!(matches!(result, Err(Error::InvalidIndexBits(bits)) if bits == index_bits));
+    }
+}
+
+#[test]
+fn index_bytes_round_trip() {
+    let layout = PixelFormat::Argb4444.layout();
+    // An odd width pads every 4-bit row to a whole byte.
+    let img = RgbaImage::from_fn(7, 3, |x, y| Rgba([x as u8 * 36, y as u8 * 80, 0, 255]));
+    for index_bits in [4, 8] {
+        let indexed = quantize(
+            &layout,
+            &img,
+            &palette(index_bits, Quantizer::MedianCut, false),
+        );
+        let bytes = indexed.index_bytes();
+        let pitch = if index_bits == 4 { 4 } else { 7 };
+        assert_eq!(bytes.len(), pitch * 3);
+        let indices = read_indices(&bytes, index_bits, 7, 3).unwrap();
+        assert_eq!(indices, indexed.indices, "{index_bits}-bit");
+        assert!(matches!(
+            read_indices(&bytes[1..], index_bits, 7, 3),
+            Err(Error::Truncated { .. })
+        ));
+    }
+}
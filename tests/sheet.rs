@@ -0,0 +1,85 @@
+use std::fs;
+
+use image::{Rgba, RgbaImage, imageops};
+use image_packer::{
+    AtlasOptions, Layout, PackOptions, SheetFormat, SheetOptions, extract_frame, pack_atlas,
+    parse_sheet, sheet_path,
+};
+
+const FORMATS: [SheetFormat; 5] = [
+    SheetFormat::JsonHash,
+    SheetFormat::JsonArray,
+    SheetFormat::Phaser,
+    SheetFormat::Libgdx,
+    SheetFormat::Csv,
+];
+
+/// A 6x2 sprite with a different colour in every pixel.
+fn sprite() -> RgbaImage {
+    RgbaImage::from_fn(6, 2, |x, y| Rgba([x as u8 * 40, y as u8 * 200, 100, 255]))
+}
+
+/// Packs the sprite onto a page too narrow for it, so it has to be
+/// rotated, and returns the page region the metadata gives for it. Slicing
+/// the frame out again must give back the sprite.
+fn rotated_region(format: SheetFormat) -> RgbaImage {
+    let dir = std::env::temp_dir().join(format!(
+        "image-packer-sheet-{format}-{}",
+        std::process::id()
+    ));
+    fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("sprite.png");
+    sprite().save(&input).unwrap();
+    let output = dir.join("atlas.png");
+    let atlas = AtlasOptions {
+        max_width: 4,
+        max_height: 8,
+        padding: 0,
+        rotate: true,
+        ..AtlasOptions::default()
+    };
+    let sheet = SheetOptions {
+        format,
+        ..SheetOptions::default()
+    };
+    let layout: Layout = "A8R8G8B8".parse().unwrap();
+    let built = pack_atlas(
+        &[input],
+        &output,
+        &layout,
+        &PackOptions::default(),
+        &atlas,
+        Some(&sheet),
+    )
+    .unwrap();
+    let text = fs::read_to_string(sheet_path(&output, format)).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    let frames = parse_sheet(&text, format).unwrap();
+    let frame = &frames.frames[0];
+    assert!(frame.rotated, "{format}");
+    assert_eq!((frame.width, frame.height), (6, 2), "{format}");
+    let page = &built.pages[frame.page];
+    let sliced = extract_frame(page, frame, frames.rotation).unwrap();
+    assert_eq!(sliced, sprite(), "{format}");
+    imageops::crop_imm(page, frame.x, frame.y, 2, 6).to_image()
+}
+
+#[test]
+fn json_phaser_and_csv_rotate_clockwise() {
+    for format in FORMATS.into_iter().filter(|&f| f != SheetFormat::Libgdx) {
+        let region = rotated_region(format);
+        // The sprite's top-left pixel ends up at the top right.
+        assert_eq!(region.get_pixel(1, 0), sprite().get_pixel(0, 0), "{format}");
+        assert_eq!(region, imageops::rotate90(&sprite()), "{format}");
+    }
+}
+
+#[test]
+fn libgdx_rotates_counter_clockwise() {
+    let region = rotated_region(SheetFormat::Libgdx);
+    // libGDX turns regions counter-clockwise: the top-left pixel ends up at
+    // the bottom left.
+    assert_eq!(region.get_pixel(0, 5), sprite().get_pixel(0, 0));
+    assert_eq!(region, imageops::rotate270(&sprite()));
+}
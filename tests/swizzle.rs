@@ -0,0 +1,206 @@
+use std::fs;
+use std::path::Path;
+
+use image::{Rgba, RgbaImage};
+use image_packer::{
+    Carrier, Layout, PackOptions, PixelFormat, Swizzle, UnpackOptions, pack_image, unpack_image,
+};
+
+const SWIZZLES: [Swizzle; 5] = [
+    Swizzle::Morton,
+    Swizzle::Psp,
+    Swizzle::Ps2,
+    Swizzle::Ctr,
+    Swizzle::Switch,
+];
+
+/// Square, non-square and non-power-of-two sizes, including ones smaller
+/// than a block and ones spanning several Switch blocks.
+const SIZES: [(u32, u32); 12] = [
+    (1, 1),
+    (8, 8),
+    (64, 64),
+    (3, 5),
+    (13, 7),
+    (7, 13),
+    (32, 8),
+    (8, 32),
+    (100, 37),
+    (37, 100),
+    (130, 66),
+    (20, 300),
+];
+
+/// Non-zero values that fit `bits`, so padding is told apart.
+fn ramp(width: u32, height: u32, bits: u32) -> Vec<u32> {
+    let mask = if bits == 32 {
+        u32::MAX
+    } else {
+        (1 << bits) - 1
+    };
+    (0..width * height).map(|i| (i % mask) + 1).collect()
+}
+
+fn supported_bits(swizzle: Swizzle) -> &'static [u32] {
+    match swizzle {
+        Swizzle::Ps2 => &[16, 32],
+        _ => &[8, 16, 32],
+    }
+}
+
+#[test]
+fn every_swizzle_round_trips() {
+    for swizzle in SWIZZLES {
+        for &bits in supported_bits(swizzle) {
+            for (width, height) in SIZES {
+                let values = ramp(width, height, bits);
+                let (padded_width, padded_height, swizzled) =
+                    swizzle.swizzle(bits, width, height, &values).unwrap();
+                assert!(padded_width >= width && padded_height >= height);
+                assert_eq!(
+                    (padded_width, padded_height),
+                    swizzle.padded_size(bits, width, height).unwrap()
+                );
+                let back = swizzle.unswizzle(bits, width, height, &swizzled).unwrap();
+                assert_eq!(back, values, "{swizzle} {bits}-bit {width}x{height}");
+            }
+        }
+    }
+}
+
+#[test]
+fn every_pixel_gets_its_own_address() {
+    for swizzle in SWIZZLES {
+        for &bits in supported_bits(swizzle) {
+            for (width, height) in SIZES {
+                let values: Vec<u32> = (1..=width * height).collect();
+                let (_, _, swizzled) = swizzle.swizzle(bits, width, height, &values).unwrap();
+                let mut seen: Vec<u32> = swizzled.into_iter().filter(|&v| v != 0).collect();
+                seen.sort_unstable();
+                assert_eq!(seen, values, "{swizzle} {bits}-bit {width}x{height}");
+            }
+        }
+    }
+}
+
+#[test]
+fn padded_size_is_stable() {
+    for swizzle in SWIZZLES {
+        for &bits in supported_bits(swizzle) {
+            for (width, height) in SIZES {
+                let padded = swizzle.padded_size(bits, width, height).unwrap();
+                assert_eq!(
+                    swizzle.padded_size(bits, padded.0, padded.1).unwrap(),
+                    padded
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn morton_interleaves_x_in_even_bits() {
+    let values: Vec<u32> = (0..16).collect();
+    let (_, _, swizzled) = Swizzle::Morton.swizzle(16, 4, 4, &values).unwrap();
+    assert_eq!(swizzled[..8], [0, 1, 4, 5, 2, 3, 6, 7]);
+    // A 4x2 rectangle interleaves one bit of each, then x's high bit.
+    let (_, _, swizzled) = Swizzle::Morton.swizzle(16, 4, 2, &values[..8]).unwrap();
+    assert_eq!(swizzled, [0, 1, 4, 5, 2, 3, 6, 7]);
+}
+
+#[test]
+fn psp_blocks_are_16_bytes_by_8_rows() {
+    let values: Vec<u32> = (0..16 * 8).collect();
+    let (width, height, swizzled) = Swizzle::Psp.swizzle(16, 16, 8, &values).unwrap();
+    assert_eq!((width, height), (16, 8));
+    // 8 pixels of row 0, then 8 pixels of row 1 from the first block.
+    assert_eq!(swizzled[..8], [0, 1, 2, 3, 4, 5, 6, 7]);
+    assert_eq!(swizzled[8..10], [16, 17]);
+    // The second block starts after 8 rows of the first.
+    assert_eq!(swizzled[64], 8);
+}
+
+#[test]
+fn ctr_orders_8x8_tiles_in_morton_order() {
+    let values: Vec<u32> = (0..16 * 8).collect();
+    let (_, _, swizzled) = Swizzle::Ctr.swizzle(32, 16, 8, &values).unwrap();
+    assert_eq!(swizzled[..4], [0, 1, 16, 17]);
+    assert_eq!(swizzled[64], 8);
+}
+
+#[test]
+fn switch_gobs_follow_block_linear_order() {
+    let values: Vec<u32> = (0..16 * 8).collect();
+    let (width, height, swizzled) = Swizzle::Switch.swizzle(32, 16, 8, &values).unwrap();
+    assert_eq!((width, height), (16, 8));
+    // 16 bytes of row 0, 16 bytes of row 1, then the next 16 bytes of row 0.
+    assert_eq!(swizzled[..4], [0, 1, 2, 3]);
+    assert_eq!(swizzled[4..8], [16, 17, 18, 19]);
+    assert_eq!(swizzled[8..12], [4, 5, 6, 7]);
+}
+
+#[test]
+fn ps2_rejects_8_bit_pixels() {
+    assert!(Swizzle::Ps2.swizzle(8, 4, 4, &[0; 16]).is_err());
+}
+
+#[test]
+fn pack_and_unpack_round_trip_through_files() {
+    let dir = std::env::temp_dir().join(format!("image-packer-swizzle-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let (width, height) = (37, 21);
+    let img = RgbaImage::from_fn(width, height, |x, y| {
+        Rgba([(x * 7) as u8, (y * 12) as u8, (x * y) as u8, 255])
+    });
+    let input = dir.join("input.png");
+    img.save(&input).unwrap();
+    let layout = Layout::from(PixelFormat::Argb1555);
+    let expected = dir.join("expected.png");
+    unpack_plain(&input, &expected, &layout);
+
+    for swizzle in SWIZZLES {
+        for carrier in [Carrier::Raw, Carrier::Image, Carrier::Dds] {
+            let extension = match carrier {
+                Carrier::Image => "png",
+                carrier => carrier.name(),
+            };
+            let packed = dir.join(format!("{swizzle}.{extension}"));
+            let pack_options = PackOptions {
+                carrier: Some(carrier),
+                swizzle: Some(swizzle),
+                ..PackOptions::default()
+            };
+            pack_image(&input, &packed, &layout, &pack_options).unwrap();
+
+            let output = dir.join(format!("{swizzle}-{}.png", carrier.name()));
+            let unpack_options = UnpackOptions {
+                carrier: Some(carrier),
+                width: Some(width),
+                height: Some(height),
+                swizzle: Some(swizzle),
+                ..UnpackOptions::default()
+            };
+            unpack_image(&packed, &output, &layout, &unpack_options).unwrap();
+            assert_eq!(
+                image::open(&output).unwrap().to_rgba8(),
+                image::open(&expected).unwrap().to_rgba8(),
+                "{swizzle} through {carrier}"
+            );
+        }
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Packs and unpacks without swizzling, giving the image swizzled round
+/// trips must reproduce.
+fn unpack_plain(input: &Path, output: &Path, layout: &Layout) {
+    let packed = output.with_extension("raw");
+    pack_image(input, &packed, layout, &PackOptions::default()).unwrap();
+    let img = image::open(input).unwrap();
+    let options = UnpackOptions {
+        width: Some(img.width()),
+        height: Some(img.height()),
+        ..UnpackOptions::default()
+    };
+    unpack_image(&packed, output, layout, &options).unwrap();
+}
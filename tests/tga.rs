@@ -0,0 +1,207 @@
+use image_packer::{Error, Indexed, Layout, decode_tga, encode_indexed_tga, encode_tga};
+
+/// Every layout TGA stores natively.
+const SPECS: [&str; 6] = ["A1R5G5B5", "X1R5G5B5", "A8R8G8B8", "X8R8G8B8", "L8", "A8L8"];
+
+/// Includes a row longer than the 128 pixels one packet can hold.
+const SIZES: [(u32, u32); 4] = [(1, 1), (3, 5), (16, 9), (300, 2)];
+
+fn layout(spec: &str) -> Layout {
+    Layout::parse_spec(spec).unwrap()
+}
+
+/// Runs of repeated values, some longer than a packet, between stretches
+/// of scrambled ones, using every bit the layout stores.
+fn values(layout: &Layout, width: u32, height: u32) -> Vec<u32> {
+    let mask = layout.masks().into_iter().fold(0, |all, m| all | m);
+    (0..width * height)
+        .map(|i| match (i / 9) % 3 {
+            0 => i / 200,
+            _ => i.wrapping_mul(0x9E37_79B9).rotate_left(i % 32),
+        })
+        .map(|v| v & mask)
+        .collect()
+}
+
+fn invalid_reason(result: Result<(Layout, u32, u32, Vec<u32>), Error>) -> String {
+    match result {
+        Err(Error::InvalidFile {
+            format: "TGA",
+            reason,
+        }) => reason,
+        other => panic!("expected an invalid TGA file, got {other:?}"),
+    }
+}
+
+#[test]
+fn every_layout_round_trips_with_and_without_rle() {
+    for spec in SPECS {
+        let layout = layout(spec);
+        for (width, height) in SIZES {
+            let packed = values(&layout, width, height);
+            for rle in [false, true] {
+                let bytes = encode_tga(&layout, width, height, &packed, rle).unwrap();
+                let (decoded, w, h, values) = decode_tga(&bytes).unwrap();
+                assert_eq!(decoded, layout, "{spec} rle {rle}");
+                assert_eq!((w, h), (width, height), "{spec}");
+                assert_eq!(values, packed, "{spec} {width}x{height} rle {rle}");
+            }
+        }
+    }
+}
+
+#[test]
+fn rle_packs_runs() {
+    let layout = layout("A1R5G5B5");
+    let packed = vec![0x8000; 300 * 4];
+    let raw = encode_tga(&layout, 300, 4, &packed, false).unwrap();
+    let rle = encode_tga(&layout, 300, 4, &packed, true).unwrap();
+    assert_eq!(raw[2], 2);
+    assert_eq!(rle[2], 10);
+    // Each row is a run of 128, another of 128 and one of 44.
+    let packets = &rle[18..rle.len() - 26];
+    assert_eq!(packets.len(), 4 * 3 * 3);
+    assert_eq!(
+        &packets[..9],
+        [0xFF, 0, 0x80, 0xFF, 0, 0x80, 0x80 | 43, 0, 0x80]
+    );
+    assert_eq!(decode_tga(&rle).unwrap().3, packed);
+}
+
+#[test]
+fn header_records_alpha_as_attribute_bits() {
+    let cases = [
+        ("A1R5G5B5", 2, 16, 1),
+        ("X1R5G5B5", 2, 16, 0),
+        ("A8R8G8B8", 2, 32, 8),
+        ("L8", 3, 8, 0),
+        ("A8L8", 3, 16, 8),
+    ];
+    for (spec, image_type, depth, attribute_bits) in cases {
+        let layout = layout(spec);
+        let bytes = encode_tga(&layout, 2, 3, &values(&layout, 2, 3), false).unwrap();
+        assert_eq!(bytes[2], image_type, "{spec}");
+        assert_eq!(bytes[16], depth, "{spec}");
+        assert_eq!(bytes[17], attribute_bits, "{spec} bottom-up, left to right");
+        assert_eq!(&bytes[bytes.len() - 18..], b"TRUEVISION-XFILE.\0");
+    }
+}
+
+#[test]
+fn colour_mapped_files_resolve_their_palette() {
+    for spec in ["A1R5G5B5", "A8R8G8B8"] {
+        let layout = layout(spec);
+        let mut palette = values(&layout, 16, 1);
+        palette.resize(256, 0);
+        let (width, height) = (19, 7);
+        let indices: Vec<u8> = (0..width * height).map(|i| (i / 5 % 16) as u8).collect();
+        let indexed = Indexed {
+            width,
+            height,
+            index_bits: 8,
+            indices: indices.clone(),
+            palette: palette.clone(),
+        };
+        let expected: Vec<u32> = indices.iter().map(|&i| palette[i as usize]).collect();
+        for rle in [false, true] {
+            let bytes = encode_indexed_tga(&layout, &indexed, rle).unwrap();
+            assert_eq!(bytes[1], 1, "{spec} colour map");
+            assert_eq!(bytes[2] & !8, 1, "{spec} colour-mapped type");
+            let (decoded, w, h, values) = decode_tga(&bytes).unwrap();
+            assert_eq!(decoded, layout, "{spec}");
+            assert_eq!((w, h), (width, height));
+            assert_eq!(values, expected, "{spec} rle {rle}");
+        }
+    }
+}
+
+#[test]
+fn descriptor_sets_the_row_and_column_order() {
+    let layout = layout("A8R8G8B8");
+    let (width, height) = (4, 3);
+    let packed = values(&layout, width, height);
+    let bytes = encode_tga(&layout, width, height, &packed, false).unwrap();
+    let rows: Vec<&[u32]> = packed.chunks(width as usize).collect();
+
+    let mut top_down = bytes.clone();
+    top_down[17] |= 0x20;
+    let flipped: Vec<u32> = rows.iter().rev().flat_map(|row| row.to_vec()).collect();
+    assert_eq!(decode_tga(&top_down).unwrap().3, flipped);
+
+    let mut right_to_left = bytes.clone();
+    right_to_left[17] |= 0x10;
+    let mirrored: Vec<u32> = rows
+        .iter()
+        .flat_map(|row| row.iter().rev().copied())
+        .collect();
+    assert_eq!(decode_tga(&right_to_left).unwrap().3, mirrored);
+}
+
+#[test]
+fn refuses_layouts_and_sizes_it_cannot_store() {
+    let rgb565 = layout("R5G6B5");
+    assert!(encode_tga(&rgb565, 1, 1, &[0], false).is_err());
+    let argb = layout("A1R5G5B5");
+    assert!(encode_tga(&argb, 70000, 1, &vec![0; 70000], false).is_err());
+}
+
+#[test]
+fn rejects_malformed_headers() {
+    let layout = layout("A1R5G5B5");
+    let packed = values(&layout, 8, 8);
+    let raw = encode_tga(&layout, 8, 8, &packed, false).unwrap();
+    let rle = encode_tga(&layout, 8, 8, &packed, true).unwrap();
+
+    assert_eq!(invalid_reason(decode_tga(&raw[..17])), "missing TGA header");
+    let mut image_type = raw.clone();
+    image_type[2] = 32;
+    assert_eq!(
+        invalid_reason(decode_tga(&image_type)),
+        "unsupported image type"
+    );
+    let mut depth = raw.clone();
+    depth[16] = 24;
+    assert_eq!(
+        invalid_reason(decode_tga(&depth)),
+        "24-bit pixels have no packed layout"
+    );
+    let mut index_depth = raw.clone();
+    (index_depth[1], index_depth[2]) = (1, 1);
+    assert_eq!(
+        invalid_reason(decode_tga(&index_depth)),
+        "only 8-bit colour map indices are supported"
+    );
+
+    assert!(matches!(
+        decode_tga(&raw[..18 + 100]),
+        Err(Error::Truncated { .. })
+    ));
+    assert_eq!(
+        invalid_reason(decode_tga(&rle[..rle.len() - 40])),
+        "truncated run-length data"
+    );
+    // A forged size must fail on the missing packets, not allocate for it.
+    let mut huge = rle.clone();
+    huge[12..16].copy_from_slice(&[0xFF; 4]);
+    assert_eq!(
+        invalid_reason(decode_tga(&huge)),
+        "truncated run-length data"
+    );
+}
+
+#[test]
+fn rejects_truncated_colour_maps() {
+    let layout = layout("A8R8G8B8");
+    let indexed = Indexed {
+        width: 2,
+        height: 2,
+        index_bits: 8,
+        indices: vec![0, 1, 2, 3],
+        palette: vec![0; 256],
+    };
+    let bytes = encode_indexed_tga(&layout, &indexed, false).unwrap();
+    assert!(matches!(
+        decode_tga(&bytes[..18 + 500]),
+        Err(Error::Truncated { expected: 1042, .. })
+    ));
+}
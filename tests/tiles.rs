@@ -0,0 +1,244 @@
+use image_packer::{
+    Endian, Error, Flip, TILE_SIZE, TileFormat, TileOptions, decode_map, encode_map, make_tiles,
+    untile,
+};
+
+const FORMATS: [TileFormat; 2] = [TileFormat::Gba, TileFormat::Snes];
+
+const NONE: Flip = Flip {
+    horizontal: false,
+    vertical: false,
+};
+const H: Flip = Flip {
+    horizontal: true,
+    vertical: false,
+};
+const V: Flip = Flip {
+    horizontal: false,
+    vertical: true,
+};
+const HV: Flip = Flip {
+    horizontal: true,
+    vertical: true,
+};
+
+/// A tile with a different value in every pixel, so no flip maps it onto
+/// itself.
+fn tile(base: u32) -> Vec<u32> {
+    (0..64).map(|i| base + i).collect()
+}
+
+fn flipped(tile: &[u32], flip: Flip) -> Vec<u32> {
+    (0..64)
+        .map(|i| {
+            let (x, y) = (i % 8, i / 8);
+            let x = if flip.horizontal { 7 - x } else { x };
+            let y = if flip.vertical { 7 - y } else { y };
+            tile[y * 8 + x]
+        })
+        .collect()
+}
+
+/// Lays tiles out row by row, `columns` to a row.
+fn image(tiles: &[Vec<u32>], columns: usize) -> Vec<u32> {
+    let width = columns * 8;
+    let mut values = vec![0; tiles.len() * 64];
+    for (n, tile) in tiles.iter().enumerate() {
+        let (column, row) = (n % columns, n / columns);
+        for (i, &value) in tile.iter().enumerate() {
+            values[(row * 8 + i / 8) * width + column * 8 + i % 8] = value;
+        }
+    }
+    values
+}
+
+/// Tile A in every orientation, tile B plain and mirrored, A again and a
+/// flat tile C that every flip maps onto itself.
+fn sample() -> (Vec<u32>, u32, u32) {
+    let (a, b, c) = (tile(0), tile(100), vec![5; 64]);
+    let tiles = [
+        a.clone(),
+        flipped(&a, H),
+        flipped(&a, V),
+        flipped(&a, HV),
+        b.clone(),
+        a.clone(),
+        flipped(&b, H),
+        c,
+    ];
+    (image(&tiles, 4), 32, 16)
+}
+
+#[test]
+fn flips_dedupe_mirrored_tiles() {
+    let (values, width, height) = sample();
+    for format in FORMATS {
+        let options = TileOptions {
+            format,
+            flips: true,
+        };
+        let tiled = make_tiles(&values, width, height, &options).unwrap();
+        assert_eq!(tiled.tile_count(), 3, "{format}");
+        let parsed: Vec<(usize, Flip)> = tiled.map.iter().map(|&e| format.parse(e)).collect();
+        assert_eq!(
+            parsed,
+            [
+                (0, NONE),
+                (0, H),
+                (0, V),
+                (0, HV),
+                (1, NONE),
+                (0, NONE),
+                (1, H),
+                (2, NONE)
+            ],
+            "{format}"
+        );
+        let untiled = untile(&tiled.tiles, &tiled.map, width, height, format).unwrap();
+        assert_eq!(untiled, values, "{format}");
+    }
+}
+
+#[test]
+fn without_flips_mirrored_tiles_are_stored() {
+    let (values, width, height) = sample();
+    let options = TileOptions {
+        format: TileFormat::Gba,
+        flips: false,
+    };
+    let tiled = make_tiles(&values, width, height, &options).unwrap();
+    assert_eq!(tiled.tile_count(), 7);
+    assert!(tiled.map.iter().all(|&e| e & 0xFC00 == 0));
+    assert_eq!(tiled.map[5], tiled.map[0]);
+    let untiled = untile(&tiled.tiles, &tiled.map, width, height, TileFormat::Gba).unwrap();
+    assert_eq!(untiled, values);
+}
+
+#[test]
+fn entries_place_the_flip_bits() {
+    assert_eq!(TileFormat::Gba.entry(0x123, HV), 0x123 | 3 << 10);
+    assert_eq!(TileFormat::Snes.entry(0x123, HV), 0x123 | 3 << 14);
+    assert_eq!(TileFormat::Gba.entry(7, H), 7 | 1 << 10);
+    assert_eq!(TileFormat::Snes.entry(7, V), 7 | 1 << 15);
+    // Palette bank and priority bits are ignored when reading.
+    assert_eq!(TileFormat::Gba.parse(0xF000 | 9), (9, NONE));
+    assert_eq!(TileFormat::Snes.parse(0x3C00 | 9), (9, NONE));
+    assert_eq!(TileFormat::Snes.parse(0x4000 | 9), (9, H));
+}
+
+#[test]
+fn indices_round_trip_through_tile_data() {
+    let cases = [
+        (TileFormat::Gba, 4, 32),
+        (TileFormat::Gba, 8, 64),
+        (TileFormat::Snes, 2, 16),
+        (TileFormat::Snes, 4, 32),
+        (TileFormat::Snes, 8, 64),
+    ];
+    for (format, index_bits, size) in cases {
+        let mask = (1 << index_bits) - 1;
+        let tile: Vec<u32> = (0..64u32)
+            .map(|i| i.wrapping_mul(0x9E37_79B9).rotate_left(i % 32) & mask)
+            .collect();
+        let bytes = format.encode_indices(&tile, index_bits);
+        assert_eq!(bytes.len(), size, "{format} {index_bits}-bit");
+        assert_eq!(
+            format.decode_indices(&bytes, index_bits),
+            tile,
+            "{format} {index_bits}-bit"
+        );
+    }
+}
+
+#[test]
+fn snes_interleaves_bitplane_pairs() {
+    // Index 1 in the leftmost pixel of the first row, index 2 in the
+    // rightmost, and index 5 (planes 0 and 2) in the leftmost of the last.
+    let mut tile = vec![0; 64];
+    (tile[0], tile[7], tile[56]) = (1, 2, 5);
+    let bytes = TileFormat::Snes.encode_indices(&tile, 4);
+    let mut expected = vec![0u8; 32];
+    // Planes 0 and 1, row by row, then planes 2 and 3.
+    (expected[0], expected[1]) = (0x80, 0x01);
+    expected[14] = 0x80;
+    expected[16 + 14] = 0x80;
+    assert_eq!(bytes, expected);
+}
+
+#[test]
+fn indexed_tiles_round_trip_through_snes_bitplanes() {
+    let (values, width, height) = sample();
+    let indices: Vec<u32> = values.iter().map(|v| v % 16).collect();
+    let options = TileOptions {
+        format: TileFormat::Snes,
+        flips: true,
+    };
+    let tiled = make_tiles(&indices, width, height, &options).unwrap();
+    let data: Vec<u8> = tiled
+        .tiles
+        .chunks(64)
+        .flat_map(|tile| TileFormat::Snes.encode_indices(tile, 4))
+        .collect();
+    let map = decode_map(&encode_map(&tiled.map, Endian::Little), Endian::Little);
+    let tiles: Vec<u32> = data
+        .chunks(32)
+        .flat_map(|bytes| TileFormat::Snes.decode_indices(bytes, 4))
+        .collect();
+    let untiled = untile(&tiles, &map, width, height, TileFormat::Snes).unwrap();
+    assert_eq!(untiled, indices);
+}
+
+#[test]
+fn maps_round_trip_in_either_byte_order() {
+    let map = [0x0001, 0x4C02, 0xFFFF];
+    let little = encode_map(&map, Endian::Little);
+    assert_eq!(little, [0x01, 0x00, 0x02, 0x4C, 0xFF, 0xFF]);
+    let big = encode_map(&map, Endian::Big);
+    assert_eq!(big, [0x00, 0x01, 0x4C, 0x02, 0xFF, 0xFF]);
+    assert_eq!(decode_map(&little, Endian::Little), map);
+    assert_eq!(decode_map(&big, Endian::Big), map);
+}
+
+#[test]
+fn rejects_bad_dimensions_and_maps() {
+    let options = TileOptions::default();
+    assert!(matches!(
+        make_tiles(&[0; 12 * 8], 12, 8, &options),
+        Err(Error::TileDimensions {
+            width: 12,
+            height: 8
+        })
+    ));
+    assert!(matches!(
+        untile(&[0; 64], &[0], 8, 9, TileFormat::Gba),
+        Err(Error::TileDimensions { .. })
+    ));
+    assert!(matches!(
+        untile(&[0; 64], &[0], 16, 8, TileFormat::Gba),
+        Err(Error::Truncated {
+            expected: 2,
+            actual: 1
+        })
+    ));
+    assert!(matches!(
+        untile(&[0; 64], &[1], 8, 8, TileFormat::Gba),
+        Err(Error::InvalidFile {
+            format: "tilemap",
+            ..
+        })
+    ));
+}
+
+#[test]
+fn rejects_more_tiles_than_an_entry_can_number() {
+    let tiles: Vec<Vec<u32>> = (0..1025).map(|n| vec![n; 64]).collect();
+    let values = image(&tiles, 1025);
+    let width = 1025 * TILE_SIZE;
+    assert!(matches!(
+        make_tiles(&values, width, 8, &TileOptions::default()),
+        Err(Error::TooManyTiles(1024))
+    ));
+    let values = image(&tiles[..1024], 1024);
+    let tiled = make_tiles(&values, 1024 * TILE_SIZE, 8, &TileOptions::default()).unwrap();
+    assert_eq!(tiled.tile_count(), 1024);
+}